@@ -1,3 +1,3 @@
 mod loader;
 
-pub use loader::AssetsPlugin;
\ No newline at end of file
+pub use loader::{AssetsPlugin, GameAssets};
\ No newline at end of file
@@ -2,13 +2,15 @@ use bevy::prelude::*;
 use serde::{Serialize, Deserialize};
 
 // Character stats components
-#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+#[derive(Component, Debug, Clone, Serialize, Deserialize, Reflect)]
+#[reflect(Component)]
 pub struct Health {
     pub current: f32,
     pub max: f32,
 }
 
-#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+#[derive(Component, Debug, Clone, Serialize, Deserialize, Reflect)]
+#[reflect(Component)]
 pub struct Stamina {
     pub current: f32,
     pub max: f32,
@@ -37,10 +39,53 @@ pub struct Reputation {
     pub faction_relations: Vec<(String, i32)>, // (faction_id, relation_value)
 }
 
+impl Reputation {
+    pub fn relation(&self, faction_id: &str) -> i32 {
+        self.faction_relations
+            .iter()
+            .find(|(id, _)| id == faction_id)
+            .map_or(0, |(_, value)| *value)
+    }
+
+    /// Shift the relation with a faction, starting from neutral if there was none
+    pub fn adjust(&mut self, faction_id: &str, delta: i32) {
+        match self.faction_relations.iter_mut().find(|(id, _)| id == faction_id) {
+            Some((_, value)) => *value += delta,
+            None => self.faction_relations.push((faction_id.to_string(), delta)),
+        }
+    }
+}
+
 // Player-specific components
 #[derive(Component, Debug, Clone)]
 pub struct Player;
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Item {
+    pub name: String,
+    pub item_type: String,
+    pub value: u32,
+}
+
+#[derive(Component, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Inventory {
+    pub gold: u32,
+    pub items: Vec<Item>,
+}
+
+/// Held by another faction after losing a battle, and led about the map by the
+/// party that took them until they are let go
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct Captive {
+    pub captor_faction_id: String,
+    /// Party holding the captive, if it is on the map
+    #[serde(skip)]
+    pub captor: Option<Entity>,
+    /// Days held so far
+    #[serde(default)]
+    pub days: u32,
+}
+
 #[derive(Component, Debug, Clone)]
 pub struct CharacterController {
     pub movement_speed: f32,
@@ -48,6 +93,68 @@ pub struct CharacterController {
 }
 
 // World components
+/// Party pushing on at a forced march, spending stamina to cover more ground
+#[derive(Component, Debug, Clone)]
+pub struct ForcedMarch;
+
+/// Cells a party has still to cross on its way across the campaign map, the next one first
+#[derive(Component, Debug, Clone, Default)]
+pub struct MapPath {
+    pub cells: Vec<UVec2>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PartyKind {
+    Lord,
+    Bandit,
+    Caravan,
+    Villager,
+}
+
+/// What an AI party on the campaign map is setting out to do
+#[derive(Debug, Clone, PartialEq)]
+pub enum PartyGoal {
+    /// Waiting on the planner for something to do
+    Idle,
+    /// Wander about a point, keeping an eye out
+    Patrol { around: Vec2 },
+    /// Run down another party and fall on it
+    Raid { target: Entity },
+    Besiege { settlement: Entity },
+    /// Carry goods to a settlement
+    Trade { settlement: Entity },
+    /// Go home to rest and heal
+    Return { settlement: Entity },
+    /// Get away from a stronger party
+    Flee { from: Entity },
+}
+
+/// A party on the campaign map that plans for itself
+#[derive(Component, Debug, Clone)]
+pub struct AiParty {
+    pub kind: PartyKind,
+    /// Settlement the party comes from; bandits have none
+    pub home: Option<Entity>,
+    pub goal: PartyGoal,
+}
+
+/// How well a party's scouts pick out others on the map
+#[derive(Component, Debug, Clone)]
+pub struct Spotting {
+    pub skill: u32,
+}
+
+/// A party the player's scouts can currently see
+#[derive(Component, Debug, Clone)]
+pub struct Spotted;
+
+/// A party that has just met the player and leaves them be for a while
+#[derive(Component, Debug, Clone)]
+pub struct EncounterCooldown {
+    /// Campaign time, as `CampaignClock::elapsed`, until it can be met again
+    pub until: f64,
+}
+
 #[derive(Component, Debug, Clone, Serialize, Deserialize)]
 pub struct WorldPosition {
     pub x: f32,
@@ -62,8 +169,135 @@ pub struct Settlement {
     pub owner_faction_id: String,
 }
 
-// Combat components
+/// Food laid by in a settlement, eaten by its garrison while it is besieged
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct FoodStores {
+    pub current: f32,
+    pub max: f32,
+}
+
+/// Engines a besieging party builds before it storms the walls
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Reflect)]
+pub enum SiegeEngine {
+    /// Scaling ladders thrown up against the wall
+    Ladders,
+    /// Battering ram for the gate
+    Ram,
+    /// Rolling tower that docks against the wall
+    SiegeTower,
+}
+
+/// Party camped outside a settlement, building engines for an assault
 #[derive(Component, Debug, Clone)]
+pub struct Besieging {
+    pub settlement: Entity,
+    /// Days since the siege began
+    pub days: f32,
+    /// Engines still to build, in order
+    pub queue: Vec<SiegeEngine>,
+    /// Days of work put into the engine at the front of the queue
+    pub progress: f32,
+    pub built: Vec<SiegeEngine>,
+}
+
+/// Settlement with an enemy camped outside its walls
+#[derive(Component, Debug, Clone)]
+pub struct UnderSiege {
+    pub besieger: Entity,
+}
+
+// Party components
+/// A stack of identical troops in a party
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TroopStack {
+    pub troop_id: String,
+    pub tier: u8,
+    pub weapon_type: WeaponType,
+    pub count: u32,
+    pub wounded: u32,
+    /// Troops who ride into battle
+    #[serde(default)]
+    pub mounted: bool,
+}
+
+#[derive(Component, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TroopRoster {
+    pub stacks: Vec<TroopStack>,
+}
+
+impl TroopRoster {
+    /// Troops able to fight
+    pub fn healthy_count(&self) -> u32 {
+        self.stacks.iter().map(|stack| stack.count.saturating_sub(stack.wounded)).sum()
+    }
+
+    pub fn total_count(&self) -> u32 {
+        self.stacks.iter().map(|stack| stack.count).sum()
+    }
+
+    /// Merge troops into the stack of the same kind, or start a new one
+    pub fn add(&mut self, stack: TroopStack) {
+        if stack.count == 0 {
+            return;
+        }
+        match self.stacks.iter_mut().find(|existing| existing.troop_id == stack.troop_id) {
+            Some(existing) => {
+                existing.count += stack.count;
+                existing.wounded += stack.wounded;
+            }
+            None => self.stacks.push(stack),
+        }
+    }
+
+    /// Take up to `count` healthy troops out of the roster, lowest tiers first,
+    /// and return them as stacks
+    pub fn remove_healthy(&mut self, count: u32) -> Vec<TroopStack> {
+        let mut order: Vec<usize> = (0..self.stacks.len()).collect();
+        order.sort_by_key(|&index| self.stacks[index].tier);
+
+        let mut removed = Vec::new();
+        let mut remaining = count;
+        for index in order {
+            if remaining == 0 {
+                break;
+            }
+            let stack = &mut self.stacks[index];
+            let taken = stack.count.saturating_sub(stack.wounded).min(remaining);
+            if taken == 0 {
+                continue;
+            }
+            stack.count -= taken;
+            remaining -= taken;
+            removed.push(TroopStack { count: taken, wounded: 0, ..stack.clone() });
+        }
+        self.stacks.retain(|stack| stack.count > 0);
+        removed
+    }
+
+    /// Mark up to `count` healthy troops as wounded, lowest tiers first
+    pub fn wound(&mut self, count: u32) {
+        let mut order: Vec<usize> = (0..self.stacks.len()).collect();
+        order.sort_by_key(|&index| self.stacks[index].tier);
+
+        let mut remaining = count;
+        for index in order {
+            let stack = &mut self.stacks[index];
+            let wounded = stack.count.saturating_sub(stack.wounded).min(remaining);
+            stack.wounded += wounded;
+            remaining -= wounded;
+        }
+    }
+}
+
+/// Troops taken captive by a party
+#[derive(Component, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Prisoners {
+    pub roster: TroopRoster,
+}
+
+// Combat components
+#[derive(Component, Debug, Clone, Reflect)]
+#[reflect(Component)]
 pub struct Weapon {
     pub damage: f32,
     pub speed: f32,
@@ -71,7 +305,7 @@ pub struct Weapon {
     pub weapon_type: WeaponType,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Reflect)]
 pub enum WeaponType {
     OneHandedSword,
     TwoHandedSword,
@@ -81,8 +315,206 @@ pub enum WeaponType {
     Shield,
 }
 
-#[derive(Component, Debug)]
+impl WeaponType {
+    /// How a blow from this weapon hurts; swords cut, but their thrusts pierce
+    pub fn damage_type(self, direction: AttackDirection) -> DamageType {
+        match (self, direction) {
+            (WeaponType::OneHandedSword | WeaponType::TwoHandedSword, AttackDirection::Thrust) => DamageType::Pierce,
+            (WeaponType::OneHandedSword | WeaponType::TwoHandedSword, _) => DamageType::Cut,
+            (WeaponType::Spear | WeaponType::Bow | WeaponType::Crossbow, _) => DamageType::Pierce,
+            (WeaponType::Shield, _) => DamageType::Blunt,
+        }
+    }
+}
+
+/// How a blow hurts, which decides how well armor stops it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Reflect)]
+pub enum DamageType {
+    Cut,
+    Pierce,
+    /// Crushing blows; these can knock a combatant out instead of killing them
+    Blunt,
+}
+
+/// Part of the body a blow lands on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Reflect)]
+pub enum BodyPart {
+    Head,
+    Body,
+    Arms,
+    Legs,
+}
+
+/// Protection worn over each part of the body
+#[derive(Component, Debug, Clone, Default, Serialize, Deserialize, Reflect)]
+#[reflect(Component)]
+pub struct Armor {
+    pub head: f32,
+    pub body: f32,
+    pub arms: f32,
+    pub legs: f32,
+}
+
+impl Armor {
+    pub fn protection(&self, part: BodyPart) -> f32 {
+        match part {
+            BodyPart::Head => self.head,
+            BodyPart::Body => self.body,
+            BodyPart::Arms => self.arms,
+            BodyPart::Legs => self.legs,
+        }
+    }
+}
+
+/// An equipped shield that soaks frontal hits until it breaks
+#[derive(Component, Debug, Clone, Reflect)]
+#[reflect(Component)]
+pub struct Shield {
+    pub durability: f32,
+    pub max_durability: f32,
+}
+
+/// Time left before a ranged weapon can fire again
+#[derive(Component, Debug, Clone, Reflect)]
+#[reflect(Component)]
+pub struct Reload {
+    pub remaining: f32,
+}
+
+/// Arrows or bolts left in the quiver
+#[derive(Component, Debug, Clone, Reflect)]
+#[reflect(Component)]
+pub struct Ammo {
+    pub current: u32,
+    pub max: u32,
+}
+
+/// Spearman planted against incoming charges
+#[derive(Component, Debug, Clone)]
+pub struct Bracing;
+
+/// Unit running into contact; braced spears punish it
+#[derive(Component, Debug, Clone)]
+pub struct Charging;
+
+/// Guard raised against attacks from one direction
+#[derive(Component, Debug, Clone, Copy, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct Blocking {
+    pub direction: AttackDirection,
+    /// Seconds since the guard went up; a fresh guard parries
+    pub held: f32,
+}
+
+/// Knocked off balance and unable to act until `remaining` runs out
+#[derive(Component, Debug, Clone, Reflect)]
+#[reflect(Component)]
+pub struct Staggered {
+    pub remaining: f32,
+}
+
+/// Beaten senseless by a blunt blow: down for the rest of the battle, but alive
+#[derive(Component, Debug, Clone)]
+pub struct KnockedOut;
+
+/// Horse carrying the unit into battle
+#[derive(Component, Debug, Clone, Serialize, Deserialize, Reflect)]
+#[reflect(Component)]
+pub struct Mount {
+    pub speed: f32,
+    /// Damage of riding someone down at full gallop
+    pub charge_damage: f32,
+    pub health: f32,
+    pub max_health: f32,
+    /// How quickly the horse turns, in radians per second
+    pub maneuverability: f32,
+}
+
+/// Running flat out
+#[derive(Component, Debug, Clone)]
+pub struct Sprinting;
+
+/// Out of stamina: swings slow, guards weaken and there is no sprinting until
+/// the unit has caught its breath
+#[derive(Component, Debug, Clone)]
+pub struct Exhausted;
+
+#[derive(Component, Debug, Clone)]
+pub struct Crouching;
+
+/// Weapons carried besides the one in hand; switching copies a slot into `Weapon`
+#[derive(Component, Debug, Clone)]
+pub struct WeaponLoadout {
+    pub slots: Vec<Weapon>,
+    pub equipped: usize,
+}
+
+/// Which side of a battle a unit fights on; factions allied in a battle share a side
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Reflect)]
+#[reflect(Component)]
+pub enum BattleSide {
+    Attacker,
+    Defender,
+}
+
+impl BattleSide {
+    pub fn opponent(self) -> Self {
+        match self {
+            BattleSide::Attacker => BattleSide::Defender,
+            BattleSide::Defender => BattleSide::Attacker,
+        }
+    }
+}
+
+/// Which formation of its side a unit deploys and takes orders with
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Reflect)]
+#[reflect(Component)]
+pub enum FormationGroup {
+    Infantry,
+    Archers,
+    Cavalry,
+    Skirmishers,
+}
+
+/// Will to keep fighting; a unit at zero morale routs
+#[derive(Component, Debug, Clone, Reflect)]
+#[reflect(Component)]
+pub struct Morale {
+    pub current: f32,
+    pub max: f32,
+}
+
+/// Unit has broken and is fleeing the field
+#[derive(Component, Debug, Clone)]
+pub struct Routing;
+
+/// Direction a melee swing comes from, as seen by the defender
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Reflect)]
+pub enum AttackDirection {
+    Overhead,
+    Left,
+    Right,
+    Thrust,
+}
+
+#[derive(Component, Debug, Reflect)]
+#[reflect(Component)]
 pub struct CombatAI {
     pub aggression: f32, // 0.0 to 1.0
     pub preferred_distance: f32,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::test_party;
+
+    #[test]
+    fn test_roster_wounds_lowest_tiers_first() {
+        let mut roster = test_party("vlandia", 2, 3).roster;
+        roster.wound(3);
+        assert_eq!(roster.stacks[0].wounded, 2);
+        // The archers already had one wounded
+        assert_eq!(roster.stacks[1].wounded, 2);
+        assert_eq!(roster.healthy_count(), 1);
+    }
+}
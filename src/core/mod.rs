@@ -1,5 +1,6 @@
 pub mod states;
 pub mod components;
+pub mod random;
 
 pub use states::*;
 pub use components::*;
\ No newline at end of file
@@ -0,0 +1,6 @@
+use rand_core::RngCore;
+
+/// A uniform roll in `[0, 1)`
+pub fn roll(rng: &mut impl RngCore) -> f32 {
+    (rng.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+}
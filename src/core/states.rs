@@ -1,4 +1,7 @@
 use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+use crate::core::components::{Health, Faction, BattleSide, Routing, Player, KnockedOut};
 
 /// Main game states that control which systems are active
 #[derive(States, Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
@@ -16,7 +19,8 @@ pub enum GameState {
 }
 
 /// SubStates for specific game modes that need their own state machine
-#[derive(States, Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+#[derive(SubStates, Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+#[source(GameState = GameState::Combat)]
 pub enum CombatState {
     #[default]
     Preparation,    // Deployment phase
@@ -25,7 +29,8 @@ pub enum CombatState {
     Defeat,         // Post-battle defeat
 }
 
-#[derive(States, Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+#[derive(SubStates, Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+#[source(GameState = GameState::WorldMap)]
 pub enum WorldMapState {
     #[default]
     Free,           // Free movement
@@ -44,13 +49,69 @@ pub fn check_loading_complete(
     }
 }
 
-// Example state transition system for combat
+/// Outcome of a finished battle, counted per faction id
+#[derive(Event, Debug, Clone)]
+pub struct BattleResult {
+    pub winner: BattleSide,
+    pub casualties: HashMap<String, u32>,
+    /// Knocked out rather than killed
+    pub wounded: HashMap<String, u32>,
+    pub routed: HashMap<String, u32>,
+    /// Knocked-out troops of the beaten side, led away by the winners
+    pub prisoners: HashMap<String, u32>,
+}
+
+// Ends the battle once one side has nobody left standing
 pub fn check_combat_victory(
     mut next_combat_state: ResMut<NextState<CombatState>>,
-    // You would include resources/queries to determine victory conditions
+    mut battle_results: EventWriter<BattleResult>,
+    units: Query<(&Health, &BattleSide, Option<&Faction>, Has<Routing>, Has<KnockedOut>)>,
+    player: Query<&BattleSide, With<Player>>,
 ) {
-    // Logic to check if combat is won
-    // if victory_condition {
-    //     next_combat_state.set(CombatState::Victory);
-    // }
-}
\ No newline at end of file
+    let mut attackers_standing = 0;
+    let mut defenders_standing = 0;
+    for (health, side, _, routing, _) in units.iter() {
+        if health.current > 0.0 && !routing {
+            match side {
+                BattleSide::Attacker => attackers_standing += 1,
+                BattleSide::Defender => defenders_standing += 1,
+            }
+        }
+    }
+    
+    let winner = match (attackers_standing, defenders_standing) {
+        (0, 0) if units.is_empty() => return,
+        // Mutual annihilation: the defenders hold the field
+        (0, _) => BattleSide::Defender,
+        (_, 0) => BattleSide::Attacker,
+        _ => return,
+    };
+    
+    let mut casualties = HashMap::new();
+    let mut wounded = HashMap::new();
+    let mut routed = HashMap::new();
+    let mut prisoners = HashMap::new();
+    for (health, side, faction, routing, knocked_out) in units.iter() {
+        let faction_id = faction.map_or_else(String::new, |faction| faction.id.clone());
+        if health.current <= 0.0 && knocked_out {
+            if *side != winner {
+                *prisoners.entry(faction_id.clone()).or_insert(0) += 1;
+            }
+            *wounded.entry(faction_id).or_insert(0) += 1;
+        } else if health.current <= 0.0 {
+            *casualties.entry(faction_id).or_insert(0) += 1;
+        } else if routing {
+            *routed.entry(faction_id).or_insert(0) += 1;
+        }
+    }
+    
+    battle_results.send(BattleResult { winner, casualties, wounded, routed, prisoners });
+    
+    // Without a player on the field, report from the attackers' point of view
+    let player_side = player.get_single().copied().unwrap_or(BattleSide::Attacker);
+    if winner == player_side {
+        next_combat_state.set(CombatState::Victory);
+    } else {
+        next_combat_state.set(CombatState::Defeat);
+    }
+}
@@ -1,12 +1,17 @@
 use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use bevy_egui::EguiPlugin;
+use bevy_rand::prelude::{EntropyPlugin, WyRand};
 
 mod core;
 mod plugins;
 mod assets;
 mod save;
+#[cfg(test)]
+mod test_support;
 
 use core::states::GameState;
-use plugins::{CombatPlugin, WorldMapPlugin, MenuPlugin};
+use plugins::{CombatPlugin, WorldMapPlugin, MenuPlugin, StaminaPlugin};
 use assets::AssetsPlugin;
 use save::SaveSystemPlugin;
 
@@ -22,8 +27,19 @@ fn main() {
             ..default()
         }))
         
+        // Physics for projectiles, terrain and unit colliders
+        .add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
+        
+        // Immediate-mode UI for menus and battle results
+        .add_plugins(EguiPlugin)
+        
+        // Shared random number source, so seeded runs play out the same
+        .add_plugins(EntropyPlugin::<WyRand>::default())
+        
         // Initialize the game state
-        .add_sub_state::<GameState>()
+        .init_state::<GameState>()
+        // Entities tagged `StateScoped(state)` are despawned when that state exits
+        .enable_state_scoped_entities::<GameState>()
         
         // Register our custom plugins
         .add_plugins((
@@ -32,6 +48,7 @@ fn main() {
             MenuPlugin,
             WorldMapPlugin,
             CombatPlugin,
+            StaminaPlugin,
         ))
         
         // Add core startup systems
@@ -1,106 +0,0 @@
-use bevy::prelude::*;
-use crate::core::states::{GameState, CombatState, check_combat_victory};
-use crate::core::components::{Health, Stamina, Weapon, CombatAI};
-
-pub struct CombatPlugin;
-
-impl Plugin for CombatPlugin {
-    fn build(&self, app: &mut App) {
-        app
-            // Register the combat substate
-            .add_sub_state::<CombatState>()
-            
-            // Register combat-specific components
-            .register_type::<Health>()
-            .register_type::<Stamina>()
-            .register_type::<Weapon>()
-            .register_type::<CombatAI>()
-            
-            // Systems that run only in Combat state
-            .add_systems(
-                Update, 
-                (
-                    process_attacks,
-                    handle_damage,
-                    update_combat_ai,
-                    check_combat_victory,
-                )
-                .run_if(in_state(GameState::Combat))
-            )
-            
-            // Systems for different combat substates
-            .add_systems(
-                Update,
-                deploy_troops.run_if(in_state(CombatState::Preparation))
-            )
-            .add_systems(
-                Update,
-                (
-                    process_combat_input,
-                    update_combat_animations,
-                )
-                .run_if(in_state(CombatState::Active))
-            )
-            .add_systems(
-                Update,
-                handle_victory_screen.run_if(in_state(CombatState::Victory))
-            )
-            .add_systems(
-                Update,
-                handle_defeat_screen.run_if(in_state(CombatState::Defeat))
-            )
-            
-            // Systems for entering/exiting combat
-            .add_systems(OnEnter(GameState::Combat), setup_combat_scene)
-            .add_systems(OnExit(GameState::Combat), cleanup_combat_scene);
-    }
-}
-
-// Combat systems
-fn setup_combat_scene(mut commands: Commands) {
-    // Initialize combat scene
-    info!("Setting up combat scene");
-    // Create battlefield terrain
-    // Spawn units
-    // Setup combat cameras
-}
-
-fn cleanup_combat_scene(mut commands: Commands, entities: Query<Entity, With<CombatAI>>) {
-    // Clean up combat entities when leaving combat
-    for entity in entities.iter() {
-        commands.entity(entity).despawn_recursive();
-    }
-    info!("Combat scene cleaned up");
-}
-
-fn process_attacks() {
-    // Handle attack logic
-}
-
-fn handle_damage() {
-    // Apply damage to entities
-}
-
-fn update_combat_ai() {
-    // Update AI decision making
-}
-
-fn deploy_troops() {
-    // Logic for the deployment phase
-}
-
-fn process_combat_input() {
-    // Handle player input during combat
-}
-
-fn update_combat_animations() {
-    // Update character animations
-}
-
-fn handle_victory_screen() {
-    // Show victory UI and rewards
-}
-
-fn handle_defeat_screen() {
-    // Show defeat UI and consequences
-}
\ No newline at end of file
@@ -0,0 +1,597 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::core::states::{GameState, BattleResult};
+use crate::core::components::{
+    Health, CharacterStats, Reputation, Inventory, Item, Captive, Player, BattleSide,
+    TroopRoster, TroopStack, Prisoners,
+};
+
+use crate::save::SaveGameConfig;
+
+use super::scene::BattleSetup;
+use super::stats::{BattleReport, export_battle_report};
+
+/// Gold stripped from each defeated enemy, multiplied by one plus their tier
+pub const LOOT_GOLD_PER_TROOP: u32 = 10;
+/// One piece of gear is recovered for every this many defeated troops of a kind
+pub const LOOT_ITEM_INTERVAL: u32 = 5;
+/// Base value of looted gear, multiplied by one plus the tier it came from
+pub const LOOT_ITEM_VALUE: u32 = 25;
+/// Experience for each defeated enemy, multiplied by one plus their tier
+pub const EXPERIENCE_PER_ENEMY: u32 = 20;
+/// Reaching level `n + 1` takes `n² × LEVEL_EXPERIENCE_STEP` experience in total
+pub const LEVEL_EXPERIENCE_STEP: u32 = 100;
+/// Share of enemies cut down who are found alive after the battle and taken
+/// prisoner; those knocked senseless are always taken
+pub const PRISONER_RATIO: f32 = 0.5;
+/// Relation gained with the faction the player fought for
+pub const VICTORY_REPUTATION_GAIN: i32 = 3;
+/// Relation lost with the faction the player beat
+pub const VICTORY_REPUTATION_LOSS: i32 = 5;
+/// Share of the surviving party taken captive along with a defeated player
+pub const DEFEAT_TROOP_LOSS_RATIO: f32 = 0.5;
+pub const DEFEAT_GOLD_LOSS_RATIO: f32 = 0.3;
+/// Share of the player's items, most valuable first, taken by the victors
+pub const DEFEAT_ITEM_LOSS_RATIO: f32 = 0.5;
+/// Health a wounded player is left with after escaping a lost battle
+pub const WOUNDED_HEALTH_RATIO: f32 = 0.25;
+
+/// What a finished battle means for the player, shown on the results screen
+/// and written back to the world map once they close it
+#[derive(Resource, Debug, Clone, Default)]
+pub struct BattleAftermath {
+    pub victory: bool,
+    pub enemy_faction_id: String,
+    pub enemy_faction_name: String,
+    /// The enemy's party on the world map, if it has one
+    pub enemy_party: Option<Entity>,
+    pub gold_looted: u32,
+    pub items_looted: Vec<Item>,
+    pub experience: u32,
+    pub levels_gained: u8,
+    /// Enemy troops the player takes captive
+    pub prisoners: Vec<TroopStack>,
+    pub reputation_changes: Vec<(String, i32)>,
+    /// The player's troops who fell
+    pub troops_killed: u32,
+    /// The player's troops knocked out, who recover in time
+    pub troops_wounded: u32,
+    /// The player's troops led away by the enemy
+    pub troops_captured: Vec<TroopStack>,
+    /// Enemy troops who fell, including those taken prisoner
+    pub enemy_killed: u32,
+    /// Enemy troops knocked out; taken prisoner if the player won
+    pub enemy_wounded: u32,
+    pub player_captured: bool,
+    pub player_wounded: bool,
+    pub gold_lost: u32,
+    pub items_lost: Vec<Item>,
+}
+
+/// The player closed the results screen
+#[derive(Event, Debug, Clone)]
+pub struct AftermathConfirmed;
+
+/// Total experience needed to reach `level`
+pub fn experience_for_level(level: u8) -> u32 {
+    let previous = level.saturating_sub(1) as u32;
+    previous * previous * LEVEL_EXPERIENCE_STEP
+}
+
+/// Add experience and level up as often as it allows, returning the levels gained
+pub fn grant_experience(stats: &mut CharacterStats, amount: u32) -> u8 {
+    stats.experience = stats.experience.saturating_add(amount);
+    let mut gained = 0;
+    while stats.level < u8::MAX && stats.experience >= experience_for_level(stats.level + 1) {
+        stats.level += 1;
+        gained += 1;
+    }
+    gained
+}
+
+/// Works out loot, experience, prisoners and losses from a battle's outcome.
+/// `player_fell` is whether the player's own avatar was cut down.
+pub fn compute_aftermath(
+    result: &BattleResult,
+    setup: &BattleSetup,
+    player_side: BattleSide,
+    player_fell: bool,
+    stats: Option<&CharacterStats>,
+    inventory: Option<&Inventory>,
+) -> BattleAftermath {
+    let own = setup.party(player_side);
+    let enemy = setup.party(player_side.opponent());
+    let count = |tally: &HashMap<String, u32>, id: &str| tally.get(id).copied().unwrap_or(0);
+
+    // Leaders are counted with their faction but aren't part of the roster; a
+    // fallen player is taken off the dead if any, otherwise off the knocked out
+    let own_casualties = count(&result.casualties, &own.faction.id);
+    let player_killed = player_fell && own_casualties > 0;
+    let own_killed = (own_casualties - player_killed as u32).min(own.roster.healthy_count());
+    let own_wounded = count(&result.wounded, &own.faction.id)
+        .saturating_sub((player_fell && !player_killed) as u32)
+        .min(own.roster.healthy_count() - own_killed);
+    let enemy_killed = count(&result.casualties, &enemy.faction.id).min(enemy.roster.healthy_count());
+    let enemy_wounded = count(&result.wounded, &enemy.faction.id)
+        .min(enemy.roster.healthy_count() - enemy_killed);
+
+    let mut aftermath = BattleAftermath {
+        victory: result.winner == player_side,
+        enemy_faction_id: enemy.faction.id.clone(),
+        enemy_faction_name: enemy.faction.name.clone(),
+        enemy_party: enemy.party_entity,
+        troops_killed: own_killed,
+        troops_wounded: own_wounded,
+        enemy_killed,
+        enemy_wounded,
+        ..default()
+    };
+
+    if aftermath.victory {
+        let enemy_routed = count(&result.routed, &enemy.faction.id);
+        let defeated = (enemy_killed + enemy_wounded + enemy_routed).min(enemy.roster.healthy_count());
+        let enemy_healthy = enemy.roster.healthy_count().max(1);
+
+        // Spoils from each kind of troop in proportion to how many of them were beaten
+        for stack in &enemy.roster.stacks {
+            let beaten = stack.count.saturating_sub(stack.wounded) * defeated / enemy_healthy;
+            let tier_factor = 1 + stack.tier as u32;
+            aftermath.gold_looted += beaten * LOOT_GOLD_PER_TROOP * tier_factor;
+            aftermath.experience += beaten * EXPERIENCE_PER_ENEMY * tier_factor;
+            for _ in 0..beaten / LOOT_ITEM_INTERVAL {
+                aftermath.items_looted.push(Item {
+                    name: format!("{:?}", stack.weapon_type),
+                    item_type: "Weapon".to_string(),
+                    value: LOOT_ITEM_VALUE * tier_factor,
+                });
+            }
+        }
+
+        let prisoners = (enemy_killed as f32 * PRISONER_RATIO).floor() as u32 + enemy_wounded;
+        aftermath.prisoners = enemy.roster.clone().remove_healthy(prisoners);
+
+        aftermath.reputation_changes.push((enemy.faction.id.clone(), -VICTORY_REPUTATION_LOSS));
+        if own.faction.id != enemy.faction.id {
+            aftermath.reputation_changes.push((own.faction.id.clone(), VICTORY_REPUTATION_GAIN));
+        }
+
+        if let Some(stats) = stats {
+            aftermath.levels_gained = grant_experience(&mut stats.clone(), aftermath.experience);
+        }
+    } else {
+        // The victors lead away a share of the troops who survived the fighting
+        let mut roster = own.roster.clone();
+        roster.remove_healthy(own_killed);
+        roster.wound(own_wounded);
+        let captured = (roster.healthy_count() as f32 * DEFEAT_TROOP_LOSS_RATIO).ceil() as u32;
+        aftermath.troops_captured = roster.remove_healthy(captured);
+
+        aftermath.player_captured = player_fell;
+        aftermath.player_wounded = !player_fell;
+
+        if let Some(inventory) = inventory {
+            aftermath.gold_lost = (inventory.gold as f32 * DEFEAT_GOLD_LOSS_RATIO).round() as u32;
+            let mut items = inventory.items.clone();
+            items.sort_by(|a, b| b.value.cmp(&a.value));
+            let lost = (items.len() as f32 * DEFEAT_ITEM_LOSS_RATIO).ceil() as usize;
+            items.truncate(lost);
+            aftermath.items_lost = items;
+        }
+    }
+
+    aftermath
+}
+
+pub(super) fn prepare_aftermath(
+    mut commands: Commands,
+    mut results: EventReader<BattleResult>,
+    setup: Option<Res<BattleSetup>>,
+    avatar: Query<(&Health, &BattleSide), With<Player>>,
+    party: Query<(Option<&CharacterStats>, Option<&Inventory>), (With<Player>, With<TroopRoster>)>,
+) {
+    let Some(result) = results.read().last() else {
+        return;
+    };
+
+    let (player_side, player_fell) = avatar
+        .get_single()
+        .map_or((BattleSide::Attacker, false), |(health, side)| (*side, health.current <= 0.0));
+    let aftermath = match setup {
+        Some(setup) => {
+            let (stats, inventory) = party.get_single().unwrap_or((None, None));
+            compute_aftermath(result, &setup, player_side, player_fell, stats, inventory)
+        }
+        None => BattleAftermath {
+            victory: result.winner == player_side,
+            ..default()
+        },
+    };
+    commands.insert_resource(aftermath);
+}
+
+pub(super) fn handle_victory_screen(
+    mut contexts: EguiContexts,
+    aftermath: Option<Res<BattleAftermath>>,
+    report: Option<Res<BattleReport>>,
+    config: Option<Res<SaveGameConfig>>,
+    confirmations: EventWriter<AftermathConfirmed>,
+) {
+    if let (Some(ctx), Some(aftermath)) = (contexts.try_ctx_mut(), aftermath) {
+        aftermath_window(ctx, "Victory", &aftermath, report.as_deref(), config.as_deref(), confirmations);
+    }
+}
+
+pub(super) fn handle_defeat_screen(
+    mut contexts: EguiContexts,
+    aftermath: Option<Res<BattleAftermath>>,
+    report: Option<Res<BattleReport>>,
+    config: Option<Res<SaveGameConfig>>,
+    confirmations: EventWriter<AftermathConfirmed>,
+) {
+    if let (Some(ctx), Some(aftermath)) = (contexts.try_ctx_mut(), aftermath) {
+        aftermath_window(ctx, "Defeat", &aftermath, report.as_deref(), config.as_deref(), confirmations);
+    }
+}
+
+fn aftermath_window(
+    ctx: &egui::Context,
+    title: &str,
+    aftermath: &BattleAftermath,
+    report: Option<&BattleReport>,
+    config: Option<&SaveGameConfig>,
+    mut confirmations: EventWriter<AftermathConfirmed>,
+) {
+    egui::Window::new(title)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ctx, |ui| {
+            if !aftermath.enemy_faction_name.is_empty() {
+                ui.label(format!("Against {}", aftermath.enemy_faction_name));
+            }
+            ui.label(format!("Troops lost: {}", aftermath.troops_killed));
+            if aftermath.troops_wounded > 0 {
+                ui.label(format!("Troops wounded: {}", aftermath.troops_wounded));
+            }
+            ui.label(format!("Enemies fallen: {}", aftermath.enemy_killed + aftermath.enemy_wounded));
+
+            if aftermath.victory {
+                ui.separator();
+                ui.label(format!("Gold looted: {}", aftermath.gold_looted));
+                for item in &aftermath.items_looted {
+                    ui.label(format!("  {} ({} denars)", item.name, item.value));
+                }
+                ui.label(format!("Experience: {}", aftermath.experience));
+                if aftermath.levels_gained > 0 {
+                    ui.label(format!("Levels gained: {}", aftermath.levels_gained));
+                }
+                let prisoners: u32 = aftermath.prisoners.iter().map(|stack| stack.count).sum();
+                ui.label(format!("Prisoners taken: {}", prisoners));
+            } else {
+                ui.separator();
+                if aftermath.player_captured {
+                    ui.label("You have been taken captive");
+                } else if aftermath.player_wounded {
+                    ui.label("You escaped, badly wounded");
+                }
+                let captured: u32 = aftermath.troops_captured.iter().map(|stack| stack.count).sum();
+                ui.label(format!("Troops captured: {}", captured));
+                ui.label(format!("Gold lost: {}", aftermath.gold_lost));
+                for item in &aftermath.items_lost {
+                    ui.label(format!("  Lost {}", item.name));
+                }
+            }
+
+            for (faction_id, change) in &aftermath.reputation_changes {
+                ui.label(format!("Relation with {}: {:+}", faction_id, change));
+            }
+
+            if let Some(report) = report {
+                ui.separator();
+                egui::CollapsingHeader::new("Battle statistics").show(ui, |ui| {
+                    ui.label(format!("The fighting lasted {:.0} seconds", report.duration));
+                    egui::Grid::new("battle_statistics").striped(true).show(ui, |ui| {
+                        for heading in ["Faction", "Fell", "Damage dealt", "Damage taken", "Kills", "Shots hit", "Blocks"] {
+                            ui.label(heading);
+                        }
+                        ui.end_row();
+                        for faction in &report.factions {
+                            ui.label(faction.faction_id.as_str());
+                            ui.label(format!("{} / {}", faction.units - faction.survivors, faction.units));
+                            ui.label(format!("{:.0}", faction.damage_dealt));
+                            ui.label(format!("{:.0}", faction.damage_taken));
+                            ui.label(faction.kills.to_string());
+                            ui.label(format!("{} / {}", faction.shots_hit, faction.shots_fired));
+                            ui.label(faction.blocks.to_string());
+                            ui.end_row();
+                        }
+                    });
+                    // Balance spreadsheets take the full per-unit breakdown
+                    if let Some(config) = config {
+                        if ui.button("Export statistics").clicked() {
+                            match export_battle_report(report, config) {
+                                Ok(path) => info!("Battle report exported to {}", path.display()),
+                                Err(error) => error!("Failed to export battle report: {}", error),
+                            }
+                        }
+                    }
+                });
+            }
+
+            ui.separator();
+            if ui.button("Return to the map").clicked() {
+                confirmations.send(AftermathConfirmed);
+            }
+        });
+}
+
+/// Writes the battle's consequences onto the world map parties and leaves combat
+pub(super) fn apply_battle_aftermath(
+    mut commands: Commands,
+    mut confirmations: EventReader<AftermathConfirmed>,
+    aftermath: Option<Res<BattleAftermath>>,
+    mut next_game_state: ResMut<NextState<GameState>>,
+    mut player_party: Query<
+        (
+            Entity,
+            &mut TroopRoster,
+            Option<&mut CharacterStats>,
+            Option<&mut Reputation>,
+            Option<&mut Inventory>,
+            Option<&mut Prisoners>,
+            Option<&mut Health>,
+        ),
+        (With<Player>, Without<BattleSide>),
+    >,
+    mut parties: Query<(&mut TroopRoster, Option<&mut Prisoners>), Without<Player>>,
+) {
+    if confirmations.read().last().is_none() {
+        return;
+    }
+
+    if let Some(aftermath) = aftermath.as_deref() {
+        if let Ok((entity, mut roster, stats, reputation, inventory, prisoners, health)) = player_party.get_single_mut() {
+            // Same order as `compute_aftermath`, so the same troops are taken
+            roster.remove_healthy(aftermath.troops_killed);
+            roster.wound(aftermath.troops_wounded);
+            roster.remove_healthy(aftermath.troops_captured.iter().map(|stack| stack.count).sum());
+            if let Some(mut stats) = stats {
+                grant_experience(&mut stats, aftermath.experience);
+            }
+            if let Some(mut reputation) = reputation {
+                for (faction_id, change) in &aftermath.reputation_changes {
+                    reputation.adjust(faction_id, *change);
+                }
+            }
+            if let Some(mut inventory) = inventory {
+                inventory.gold = inventory.gold.saturating_sub(aftermath.gold_lost) + aftermath.gold_looted;
+                for item in &aftermath.items_lost {
+                    if let Some(index) = inventory.items.iter().position(|owned| owned == item) {
+                        inventory.items.remove(index);
+                    }
+                }
+                inventory.items.extend(aftermath.items_looted.iter().cloned());
+            }
+            take_prisoners(&mut commands, entity, prisoners, &aftermath.prisoners);
+            if aftermath.player_captured {
+                commands.entity(entity).insert(Captive {
+                    captor_faction_id: aftermath.enemy_faction_id.clone(),
+                    captor: aftermath.enemy_party,
+                    days: 0,
+                });
+            }
+            if let Some(mut health) = health.filter(|_| aftermath.player_wounded) {
+                health.current = health.current.min(health.max * WOUNDED_HEALTH_RATIO);
+            }
+        }
+
+        // The enemy party on the map loses its fallen and keeps whoever it captured
+        if let Some(entity) = aftermath.enemy_party {
+            if let Ok((mut roster, prisoners)) = parties.get_mut(entity) {
+                roster.remove_healthy(aftermath.enemy_killed);
+                // Knocked-out enemies are led away by a victorious player and nurse their wounds otherwise
+                if aftermath.victory {
+                    roster.remove_healthy(aftermath.enemy_wounded);
+                } else {
+                    roster.wound(aftermath.enemy_wounded);
+                }
+                take_prisoners(&mut commands, entity, prisoners, &aftermath.troops_captured);
+            }
+        }
+    }
+
+    commands.remove_resource::<BattleAftermath>();
+    commands.remove_resource::<BattleSetup>();
+    next_game_state.set(GameState::WorldMap);
+}
+
+fn take_prisoners(commands: &mut Commands, party: Entity, prisoners: Option<Mut<Prisoners>>, stacks: &[TroopStack]) {
+    if stacks.is_empty() {
+        return;
+    }
+    match prisoners {
+        Some(mut prisoners) => {
+            for stack in stacks {
+                prisoners.roster.add(stack.clone());
+            }
+        }
+        None => {
+            commands.entity(party).insert(Prisoners {
+                roster: TroopRoster { stacks: stacks.to_vec() },
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{CombatState, WorldPosition};
+    use crate::plugins::{WorldMapPlugin, NewDay, CAPTIVITY_DAYS};
+    use crate::test_support::{
+        combat_app, combat_test_app, spawn_swordsman, enlist, kill, start_battle, test_party, hero_stats,
+        plains_map, enter_state, run_test_app,
+    };
+
+    fn battle_result(winner: BattleSide, casualties: &[(&str, u32)], routed: &[(&str, u32)]) -> BattleResult {
+        let tally = |counts: &[(&str, u32)]| -> HashMap<String, u32> {
+            counts.iter().map(|(id, count)| (id.to_string(), *count)).collect()
+        };
+        BattleResult {
+            winner,
+            casualties: tally(casualties),
+            wounded: HashMap::new(),
+            routed: tally(routed),
+            prisoners: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_victory_aftermath_rewards() {
+        let setup = BattleSetup {
+            attacker: test_party("vlandia", 5, 3),
+            defender: test_party("looters", 10, 0),
+            field_size: 200.0,
+        };
+        let result = battle_result(BattleSide::Attacker, &[("looters", 6), ("vlandia", 1)], &[("looters", 2)]);
+        let aftermath = compute_aftermath(&result, &setup, BattleSide::Attacker, false, Some(&hero_stats()), None);
+
+        assert!(aftermath.victory);
+        // Eight tier 1 looters beaten
+        assert_eq!(aftermath.gold_looted, 160);
+        assert_eq!(aftermath.experience, 320);
+        assert_eq!(aftermath.levels_gained, 1);
+        assert_eq!(aftermath.items_looted.len(), 1);
+        // Half the fallen are dragged off alive
+        assert_eq!(aftermath.prisoners.iter().map(|stack| stack.count).sum::<u32>(), 3);
+        assert_eq!(aftermath.troops_killed, 1);
+        assert!(aftermath.reputation_changes.contains(&("looters".to_string(), -5)));
+        assert!(aftermath.reputation_changes.contains(&("vlandia".to_string(), 3)));
+    }
+
+    #[test]
+    fn test_defeat_aftermath_losses() {
+        let setup = BattleSetup {
+            attacker: test_party("vlandia", 5, 3),
+            defender: test_party("looters", 10, 0),
+            field_size: 200.0,
+        };
+        let inventory = Inventory {
+            gold: 100,
+            items: vec![
+                Item { name: "Bread".to_string(), item_type: "Food".to_string(), value: 10 },
+                Item { name: "Mail".to_string(), item_type: "Armor".to_string(), value: 50 },
+                Item { name: "Helmet".to_string(), item_type: "Armor".to_string(), value: 30 },
+            ],
+        };
+        // The player's avatar is one of the three who fell
+        let result = battle_result(BattleSide::Defender, &[("vlandia", 3)], &[]);
+        let aftermath = compute_aftermath(&result, &setup, BattleSide::Attacker, true, None, Some(&inventory));
+
+        assert!(!aftermath.victory);
+        assert!(aftermath.player_captured);
+        assert_eq!(aftermath.troops_killed, 2);
+        // Half of the five survivors, rounded up, are led away
+        assert_eq!(aftermath.troops_captured.iter().map(|stack| stack.count).sum::<u32>(), 3);
+        assert_eq!(aftermath.gold_lost, 30);
+        let lost: Vec<&str> = aftermath.items_lost.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(lost, vec!["Mail", "Helmet"]);
+    }
+
+    #[test]
+    fn test_aftermath_written_back_to_world_map() {
+        let mut app = combat_test_app();
+        let party = app.world_mut().spawn((
+            Player,
+            test_party("vlandia", 5, 0).roster,
+            hero_stats(),
+            Reputation { faction_relations: Vec::new() },
+            Inventory { gold: 50, items: Vec::new() },
+        )).id();
+        let enemy_party = app.world_mut().spawn(test_party("looters", 10, 0).roster).id();
+        let mut defender = test_party("looters", 10, 0);
+        defender.party_entity = Some(enemy_party);
+        app.insert_resource(BattleSetup {
+            attacker: test_party("vlandia", 5, 0),
+            defender,
+            field_size: 200.0,
+        });
+
+        let avatar = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+        enlist(&mut app, avatar, BattleSide::Attacker, "vlandia");
+        app.world_mut().entity_mut(avatar).insert(Player);
+        let enemy = spawn_swordsman(&mut app, 10, Vec3::new(0.0, 0.0, -30.0));
+        enlist(&mut app, enemy, BattleSide::Defender, "looters");
+        start_battle(&mut app);
+
+        kill(&mut app, avatar, enemy);
+        run_test_app(&mut app, 2);
+        assert_eq!(app.world().resource::<State<CombatState>>().get(), &CombatState::Victory);
+        assert!(app.world().get_resource::<BattleAftermath>().is_some());
+
+        app.world_mut().send_event(AftermathConfirmed);
+        run_test_app(&mut app, 2);
+
+        assert_eq!(app.world().resource::<State<GameState>>().get(), &GameState::WorldMap);
+        assert!(app.world().get_resource::<BattleAftermath>().is_none());
+        let world = app.world();
+        assert_eq!(world.get::<CharacterStats>(party).unwrap().experience, 40);
+        assert_eq!(world.get::<Inventory>(party).unwrap().gold, 70);
+        assert_eq!(world.get::<Reputation>(party).unwrap().relation("looters"), -5);
+        assert!(world.get::<Captive>(party).is_none());
+        // One looter fell, too few to leave a prisoner
+        assert!(world.get::<Prisoners>(party).is_none());
+        assert_eq!(world.get::<TroopRoster>(enemy_party).unwrap().healthy_count(), 9);
+    }
+
+    #[test]
+    fn test_captured_player_led_off_and_let_go() {
+        let mut app = combat_app();
+        app.add_plugins(WorldMapPlugin).insert_resource(plains_map(8, 1));
+        enter_state(&mut app, GameState::Combat);
+        let party = app.world_mut().spawn((
+            Player,
+            test_party("vlandia", 5, 0).roster,
+            hero_stats(),
+            WorldPosition { x: 15.0, y: 5.0 },
+        )).id();
+        let enemy_party = app.world_mut().spawn((
+            test_party("looters", 10, 0).roster,
+            WorldPosition { x: 20.0, y: 5.0 },
+        )).id();
+        let mut defender = test_party("looters", 10, 0);
+        defender.party_entity = Some(enemy_party);
+        app.insert_resource(BattleSetup {
+            attacker: test_party("vlandia", 5, 0),
+            defender,
+            field_size: 200.0,
+        });
+
+        let avatar = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+        enlist(&mut app, avatar, BattleSide::Attacker, "vlandia");
+        app.world_mut().entity_mut(avatar).insert(Player);
+        let enemy = spawn_swordsman(&mut app, 10, Vec3::new(0.0, 0.0, -30.0));
+        enlist(&mut app, enemy, BattleSide::Defender, "looters");
+        start_battle(&mut app);
+
+        kill(&mut app, enemy, avatar);
+        run_test_app(&mut app, 2);
+        assert_eq!(app.world().resource::<State<CombatState>>().get(), &CombatState::Defeat);
+
+        app.world_mut().send_event(AftermathConfirmed);
+        run_test_app(&mut app, 3);
+        assert_eq!(app.world().resource::<State<GameState>>().get(), &GameState::WorldMap);
+        let captive = app.world().get::<Captive>(party).unwrap();
+        assert_eq!((captive.captor_faction_id.as_str(), captive.captor), ("looters", Some(enemy_party)));
+        // Led off wherever the looters go
+        assert_eq!(app.world().get::<WorldPosition>(party).unwrap().x, 20.0);
+
+        for day in 0..CAPTIVITY_DAYS {
+            app.world_mut().send_event(NewDay { day: day + 2 });
+        }
+        run_test_app(&mut app, 1);
+        assert!(app.world().get::<Captive>(party).is_none());
+    }
+}
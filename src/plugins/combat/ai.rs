@@ -0,0 +1,570 @@
+use bevy::prelude::*;
+use bevy_rand::prelude::{GlobalEntropy, WyRand};
+use rand_core::RngCore;
+
+use crate::core::random::roll;
+use crate::core::components::{
+    Health, Stamina, Weapon, CombatAI, BattleSide, Routing, CharacterController, AttackDirection,
+    Blocking, Staggered, Mount, WeaponType, Exhausted,
+};
+
+use super::{WeaponRules, AttackEvent};
+use super::orders::{FormationDirective, constrain_decision};
+use super::mounts::{CavalryPhase, decide_cavalry_action, ride_towards};
+use super::animation::CombatAnimation;
+use super::input::PlayerCombatState;
+use super::replay::ReplayPlayback;
+use crate::plugins::stamina::EXHAUSTED_SWING_SPEED_MULTIPLIER;
+
+/// Walking pace for units without a `CharacterController`
+pub const DEFAULT_AI_MOVE_SPEED: f32 = 3.0;
+/// Seconds between swings for a weapon of speed 1.0
+pub const BASE_SWING_INTERVAL: f32 = 1.2;
+/// Health ratio at which a fully timid (aggression 0.0) unit breaks off
+pub const RETREAT_HEALTH_RATIO: f32 = 0.5;
+/// Below this stamina ratio units stop swinging and cover up
+pub const EXHAUSTED_STAMINA_RATIO: f32 = 0.15;
+/// Allies closer than this to the line of attack block the shot or swing
+pub const FRIENDLY_FIRE_CLEARANCE: f32 = 0.75;
+/// How close to the preferred distance counts as "in position"
+pub const POSITION_TOLERANCE: f32 = 0.25;
+/// Chance a guard reads an opponent's wind-up wrong and covers another direction
+pub const MISREAD_CHANCE: f32 = 0.25;
+
+/// What the AI saw of one combatant this frame
+#[derive(Debug, Clone)]
+pub struct CombatantSnapshot {
+    pub entity: Entity,
+    /// Combatants without a side are hostile to everybody
+    pub team: Option<BattleSide>,
+    pub position: Vec3,
+    pub health_ratio: f32,
+    pub stamina_ratio: f32,
+    /// Furthest distance this combatant can hurt someone from
+    pub reach: f32,
+    pub is_ranged: bool,
+}
+
+impl CombatantSnapshot {
+    pub fn is_alive(&self) -> bool {
+        self.health_ratio > 0.0
+    }
+
+    pub fn is_enemy_of(&self, other: &CombatantSnapshot) -> bool {
+        self.entity != other.entity && (self.team.is_none() || self.team != other.team)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AiDecision {
+    /// No enemies left, or nothing useful to do
+    Idle,
+    /// Walk to `position` to get `target` at the preferred distance
+    MoveTo { target: Entity, position: Vec3 },
+    Attack { target: Entity, direction: AttackDirection },
+    /// Hold ground and defend against `target`
+    Block { target: Entity },
+    /// Back away from `threat` towards `position`
+    Retreat { threat: Entity, position: Vec3 },
+    /// Take up a formation slot, facing the way the formation faces
+    HoldPosition { position: Vec3, facing: Vec3 },
+}
+
+/// Per-unit AI memory between frames
+#[derive(Component, Debug, Clone)]
+pub struct CombatAIState {
+    pub decision: AiDecision,
+    /// Seconds until the unit may swing again
+    pub attack_cooldown: f32,
+    /// Wind-up the guard was last raised against, so each is only read once
+    pub read_windup: Option<AttackDirection>,
+}
+
+impl Default for CombatAIState {
+    fn default() -> Self {
+        Self {
+            decision: AiDecision::Idle,
+            attack_cooldown: 0.0,
+            read_windup: None,
+        }
+    }
+}
+
+/// Picks what `me` should do given everyone else on the field. Pure and
+/// deterministic so it can be exercised without a Bevy schedule.
+pub fn decide_action(me: &CombatantSnapshot, ai: &CombatAI, battlefield: &[CombatantSnapshot]) -> AiDecision {
+    if !me.is_alive() {
+        return AiDecision::Idle;
+    }
+
+    // Nearest enemies first; entity order breaks ties so results never depend on query order
+    let mut enemies: Vec<&CombatantSnapshot> = battlefield
+        .iter()
+        .filter(|other| other.is_alive() && me.is_enemy_of(other))
+        .collect();
+    enemies.sort_by(|a, b| {
+        me.position.distance(a.position)
+            .total_cmp(&me.position.distance(b.position))
+            .then(a.entity.cmp(&b.entity))
+    });
+    let Some(nearest) = enemies.first().copied() else {
+        return AiDecision::Idle;
+    };
+    let nearest_distance = me.position.distance(nearest.position);
+
+    // Timid units break off sooner; fully aggressive ones fight to the death
+    let retreat_threshold = RETREAT_HEALTH_RATIO * (1.0 - ai.aggression.clamp(0.0, 1.0));
+    if me.health_ratio < retreat_threshold {
+        return AiDecision::Retreat {
+            threat: nearest.entity,
+            position: away_from(me.position, nearest.position, me.reach.max(ai.preferred_distance)),
+        };
+    }
+
+    let engage_distance = ai.preferred_distance.min(me.reach).max(0.0);
+
+    if me.is_ranged {
+        // Skirmish: back off from anyone who closed to melee range
+        if nearest_distance < engage_distance * 0.5 && nearest_distance < nearest.reach * 2.0 {
+            return AiDecision::Retreat {
+                threat: nearest.entity,
+                position: away_from(me.position, nearest.position, engage_distance - nearest_distance),
+            };
+        }
+        // Shoot the closest enemy we have a clear line to
+        let clear_target = enemies.iter().find(|enemy| {
+            me.position.distance(enemy.position) <= me.reach
+                && !ally_in_line(me, enemy.position, battlefield)
+        });
+        return match clear_target {
+            Some(target) => AiDecision::Attack {
+                target: target.entity,
+                direction: AttackDirection::Thrust,
+            },
+            None => AiDecision::MoveTo {
+                target: nearest.entity,
+                position: toward(me.position, nearest.position, nearest_distance - engage_distance),
+            },
+        };
+    }
+
+    if nearest_distance > me.reach {
+        return AiDecision::MoveTo {
+            target: nearest.entity,
+            position: toward(me.position, nearest.position, nearest_distance - engage_distance),
+        };
+    }
+
+    // In reach: swing or cover up depending on temperament and how fresh we are
+    if me.stamina_ratio < EXHAUSTED_STAMINA_RATIO || ally_in_line(me, nearest.position, battlefield) {
+        return AiDecision::Block { target: nearest.entity };
+    }
+    let attack_desire = ai.aggression * 0.6 + me.stamina_ratio * 0.2 + me.health_ratio * 0.2;
+    if attack_desire >= 0.5 {
+        AiDecision::Attack {
+            target: nearest.entity,
+            direction: swing_direction(me.entity, nearest.entity),
+        }
+    } else {
+        AiDecision::Block { target: nearest.entity }
+    }
+}
+
+/// Whether an ally stands close to the line from `me` to `target`
+fn ally_in_line(me: &CombatantSnapshot, target: Vec3, battlefield: &[CombatantSnapshot]) -> bool {
+    let line = target - me.position;
+    let length = line.length();
+    if length < f32::EPSILON {
+        return false;
+    }
+    let direction = line / length;
+
+    battlefield
+        .iter()
+        .filter(|other| other.entity != me.entity && other.is_alive() && !me.is_enemy_of(other))
+        .any(|ally| {
+            let offset = ally.position - me.position;
+            let along = offset.dot(direction);
+            along > 0.0 && along < length && (offset - direction * along).length() < FRIENDLY_FIRE_CLEARANCE
+        })
+}
+
+/// Vary swings between opponents without needing an RNG
+fn swing_direction(attacker: Entity, target: Entity) -> AttackDirection {
+    match (attacker.index() + target.index()) % 4 {
+        0 => AttackDirection::Overhead,
+        1 => AttackDirection::Left,
+        2 => AttackDirection::Right,
+        _ => AttackDirection::Thrust,
+    }
+}
+
+/// Direction of the swing an opponent is visibly drawing back at `me`, if any
+fn telegraphed_swing(animation: Option<&CombatAnimation>, player: Option<&PlayerCombatState>, me: Entity) -> Option<AttackDirection> {
+    // A player's held wind-up shows its direction before it has picked a target
+    player
+        .and_then(|player| player.windup)
+        .map(|(direction, _)| direction)
+        .or_else(|| {
+            animation
+                .and_then(|animation| animation.swing)
+                .filter(|swing| swing.target == me)
+                .map(|swing| swing.direction)
+        })
+}
+
+/// Where a guard goes against a wind-up from `seen`: usually the right way,
+/// but now and then one of the others
+fn guard_against(seen: AttackDirection, rng: &mut impl RngCore) -> AttackDirection {
+    if roll(rng) >= MISREAD_CHANCE {
+        return seen;
+    }
+    let others: Vec<AttackDirection> = [
+        AttackDirection::Overhead,
+        AttackDirection::Left,
+        AttackDirection::Right,
+        AttackDirection::Thrust,
+    ]
+    .into_iter()
+    .filter(|direction| *direction != seen)
+    .collect();
+    others[rng.next_u32() as usize % others.len()]
+}
+
+fn toward(from: Vec3, to: Vec3, distance: f32) -> Vec3 {
+    from + (to - from).normalize_or_zero() * distance
+}
+
+fn away_from(from: Vec3, threat: Vec3, distance: f32) -> Vec3 {
+    from + (from - threat).normalize_or_zero() * distance
+}
+
+pub(super) fn update_combat_ai(
+    mut commands: Commands,
+    time: Res<Time>,
+    rules: Res<WeaponRules>,
+    playback: Option<Res<ReplayPlayback>>,
+    mut rng: ResMut<GlobalEntropy<WyRand>>,
+    mut attack_events: EventWriter<AttackEvent>,
+    windups: Query<(Option<&CombatAnimation>, Option<&PlayerCombatState>)>,
+    mut queries: ParamSet<(
+        Query<(Entity, &Transform, &Health, Option<&Stamina>, Option<&Weapon>, Option<&BattleSide>)>,
+        Query<(
+            Entity,
+            &CombatAI,
+            &mut Transform,
+            Option<&mut CombatAIState>,
+            Option<&Weapon>,
+            Option<&CharacterController>,
+            Option<&FormationDirective>,
+            Option<&Blocking>,
+            Option<&Mount>,
+            Option<&mut CavalryPhase>,
+            Has<Staggered>,
+            Has<Exhausted>,
+        ), Without<Routing>>,
+    )>,
+) {
+    let battlefield: Vec<CombatantSnapshot> = queries
+        .p0()
+        .iter()
+        .map(|(entity, transform, health, stamina, weapon, side)| {
+            let ranged = weapon.and_then(|weapon| rules.get(weapon.weapon_type).ranged.as_ref());
+            let reach = match (weapon, ranged) {
+                (_, Some(ranged)) => ranged.max_range,
+                (Some(weapon), None) => weapon.reach + rules.get(weapon.weapon_type).reach_bonus,
+                (None, None) => 0.0,
+            };
+            CombatantSnapshot {
+                entity,
+                team: side.copied(),
+                position: transform.translation,
+                health_ratio: ratio(health.current, health.max),
+                stamina_ratio: stamina.map_or(1.0, |stamina| ratio(stamina.current, stamina.max)),
+                reach,
+                is_ranged: ranged.is_some(),
+            }
+        })
+        .collect();
+    let delta = time.delta_secs();
+
+    let mut units = queries.p1();
+    for (
+        entity,
+        ai,
+        mut transform,
+        mut state,
+        weapon,
+        controller,
+        directive,
+        guard,
+        mount,
+        phase,
+        staggered,
+        exhausted,
+    ) in units.iter_mut() {
+        let Some(me) = battlefield.iter().find(|snapshot| snapshot.entity == entity) else {
+            continue;
+        };
+        // Reeling units can only wait for their footing to come back
+        if staggered {
+            if let Some(state) = state.as_mut() {
+                state.attack_cooldown = (state.attack_cooldown - delta).max(0.0);
+            }
+            continue;
+        }
+        let decision = match (&playback, state.as_deref()) {
+            // A replay has already fed in what the unit decided
+            (Some(_), state) => state.map_or(AiDecision::Idle, |state| state.decision),
+            (None, _) => {
+                let mut decision = decide_action(me, ai, &battlefield);
+                // Riders who aren't breaking off keep circling and charging instead of trading blows
+                if mount.is_some() && me.is_alive() && !matches!(decision, AiDecision::Retreat { .. }) {
+                    let mut cavalry = phase.as_deref().copied().unwrap_or_default();
+                    decision = decide_cavalry_action(me, &mut cavalry, &battlefield);
+                    match phase {
+                        Some(mut phase) => *phase = cavalry,
+                        None => {
+                            commands.entity(entity).insert(cavalry);
+                        }
+                    }
+                }
+                // Orders from the formation outrank the soldier's own judgement, but
+                // the fallen take no more orders
+                if let Some(directive) = directive.filter(|_| me.is_alive()) {
+                    decision = constrain_decision(decision, me, directive);
+                }
+                decision
+            }
+        };
+        let speed = controller.map_or(DEFAULT_AI_MOVE_SPEED, |controller| controller.movement_speed);
+
+        // Winded units swing slower
+        let mut swing_speed = weapon.map_or(1.0, |weapon| weapon.speed).max(0.1);
+        if exhausted {
+            swing_speed *= EXHAUSTED_SWING_SPEED_MULTIPLIER;
+        }
+        let mut cooldown = state.as_ref().map_or(0.0, |state| state.attack_cooldown);
+        cooldown = (cooldown - delta).max(0.0);
+
+        match (decision, mount) {
+            (AiDecision::MoveTo { target, position } | AiDecision::Retreat { threat: target, position }, Some(mount)) => {
+                ride_towards(&mut transform, position, mount, delta);
+                // Lances do their work by charging; other riders cut at whoever they pass
+                let passing = battlefield
+                    .iter()
+                    .find(|snapshot| snapshot.entity == target)
+                    .is_some_and(|target| target.position.distance(me.position) <= me.reach);
+                let lance = weapon.is_some_and(|weapon| weapon.weapon_type == WeaponType::Spear);
+                if passing && !lance && !me.is_ranged && cooldown <= 0.0 {
+                    attack_events.send(AttackEvent {
+                        attacker: entity,
+                        target,
+                        direction: swing_direction(entity, target),
+                        timing: ai.aggression.clamp(0.0, 1.0),
+                    });
+                    cooldown = BASE_SWING_INTERVAL / swing_speed;
+                }
+            }
+            (AiDecision::HoldPosition { position, .. }, Some(mount))
+                if transform.translation.with_y(0.0).distance(position.with_y(0.0)) > POSITION_TOLERANCE =>
+            {
+                let position = Vec3::new(position.x, transform.translation.y, position.z);
+                ride_towards(&mut transform, position, mount, delta);
+            }
+            (AiDecision::MoveTo { target, position } | AiDecision::Retreat { threat: target, position }, None) => {
+                step_towards(&mut transform, position, speed * delta);
+                face(&mut transform, &battlefield, target);
+            }
+            (AiDecision::HoldPosition { position, facing }, _) => {
+                let position = Vec3::new(position.x, transform.translation.y, position.z);
+                step_towards(&mut transform, position, speed * delta);
+                let look_target = transform.translation + Vec3::new(facing.x, 0.0, facing.z);
+                if facing.length_squared() > f32::EPSILON {
+                    transform.look_at(look_target, Vec3::Y);
+                }
+            }
+            (AiDecision::Attack { target, direction }, _) => {
+                face(&mut transform, &battlefield, target);
+                if cooldown <= 0.0 {
+                    attack_events.send(AttackEvent {
+                        attacker: entity,
+                        target,
+                        direction,
+                        timing: ai.aggression.clamp(0.0, 1.0),
+                    });
+                    cooldown = BASE_SWING_INTERVAL / swing_speed;
+                }
+            }
+            (AiDecision::Block { target }, _) => face(&mut transform, &battlefield, target),
+            (AiDecision::Idle, _) => {}
+        }
+
+        // Guard against the swing this opponent is seen winding up, reading each
+        // wind-up once; anything else lowers the guard
+        let mut read_windup = state.as_ref().and_then(|state| state.read_windup);
+        match (decision, guard) {
+            (AiDecision::Block { target }, guard) => {
+                let seen = windups
+                    .get(target)
+                    .ok()
+                    .and_then(|(animation, player)| telegraphed_swing(animation, player, entity));
+                let direction = match seen {
+                    Some(seen) if read_windup != Some(seen) => {
+                        read_windup = Some(seen);
+                        Some(guard_against(seen, &mut *rng))
+                    }
+                    // Already reacting to this one
+                    Some(_) => None,
+                    // Nothing coming yet: the guard stays where it is, or goes up overhead
+                    None => {
+                        read_windup = None;
+                        guard.is_none().then_some(AttackDirection::Overhead)
+                    }
+                };
+                if let Some(direction) = direction.filter(|direction| guard.map(|guard| guard.direction) != Some(*direction)) {
+                    commands.entity(entity).insert(Blocking { direction, held: 0.0 });
+                }
+            }
+            (_, Some(_)) => {
+                read_windup = None;
+                commands.entity(entity).remove::<Blocking>();
+            }
+            _ => read_windup = None,
+        }
+
+        let new_state = CombatAIState { decision, attack_cooldown: cooldown, read_windup };
+        match state {
+            Some(mut state) => *state = new_state,
+            None => {
+                commands.entity(entity).insert(new_state);
+            }
+        }
+    }
+}
+
+fn step_towards(transform: &mut Transform, position: Vec3, max_step: f32) {
+    let offset = position - transform.translation;
+    if offset.length() > POSITION_TOLERANCE {
+        transform.translation += offset.normalize() * max_step.min(offset.length());
+    }
+}
+
+fn face(transform: &mut Transform, battlefield: &[CombatantSnapshot], target: Entity) {
+    let Some(target) = battlefield.iter().find(|snapshot| snapshot.entity == target) else {
+        return;
+    };
+    let look_at = Vec3::new(target.position.x, transform.translation.y, target.position.z);
+    if look_at.distance_squared(transform.translation) > f32::EPSILON {
+        transform.look_at(look_at, Vec3::Y);
+    }
+}
+
+fn ratio(current: f32, max: f32) -> f32 {
+    if max > 0.0 { (current / max).clamp(0.0, 1.0) } else { 0.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_core::SeedableRng;
+    use crate::plugins::combat::animation::PendingSwing;
+    use crate::test_support::{combat_test_app, spawn_swordsman, start_battle, snapshot, run_test_app};
+
+    #[test]
+    fn test_ai_closes_to_preferred_distance() {
+        let me = snapshot(0, BattleSide::Attacker, Vec3::ZERO);
+        let enemy = snapshot(1, BattleSide::Defender, Vec3::new(10.0, 0.0, 0.0));
+        let ai = CombatAI { aggression: 0.8, preferred_distance: 1.0 };
+
+        let decision = decide_action(&me, &ai, &[me.clone(), enemy.clone()]);
+        match decision {
+            AiDecision::MoveTo { target, position } => {
+                assert_eq!(target, enemy.entity);
+                assert!((position.distance(enemy.position) - 1.0).abs() < 1e-4);
+            }
+            other => panic!("expected MoveTo, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_ai_attack_block_and_retreat() {
+        let enemy = snapshot(1, BattleSide::Defender, Vec3::new(1.0, 0.0, 0.0));
+        let mut me = snapshot(0, BattleSide::Attacker, Vec3::ZERO);
+
+        let aggressive = CombatAI { aggression: 0.9, preferred_distance: 1.0 };
+        let timid = CombatAI { aggression: 0.1, preferred_distance: 1.0 };
+        assert!(matches!(decide_action(&me, &aggressive, &[me.clone(), enemy.clone()]), AiDecision::Attack { .. }));
+
+        // Winded units cover up rather than swing
+        me.stamina_ratio = 0.05;
+        assert_eq!(
+            decide_action(&me, &aggressive, &[me.clone(), enemy.clone()]),
+            AiDecision::Block { target: enemy.entity }
+        );
+
+        // Badly hurt timid units break off, aggressive ones keep fighting
+        me.stamina_ratio = 1.0;
+        me.health_ratio = 0.3;
+        assert!(matches!(decide_action(&me, &timid, &[me.clone(), enemy.clone()]), AiDecision::Retreat { .. }));
+        assert!(matches!(decide_action(&me, &aggressive, &[me.clone(), enemy.clone()]), AiDecision::Attack { .. }));
+    }
+
+    #[test]
+    fn test_ai_holds_fire_through_allies() {
+        let mut archer = snapshot(0, BattleSide::Attacker, Vec3::ZERO);
+        archer.is_ranged = true;
+        archer.reach = 100.0;
+        let ally = snapshot(1, BattleSide::Attacker, Vec3::new(10.0, 0.0, 0.0));
+        let blocked_enemy = snapshot(2, BattleSide::Defender, Vec3::new(20.0, 0.0, 0.0));
+        let open_enemy = snapshot(3, BattleSide::Defender, Vec3::new(0.0, 0.0, 25.0));
+        let ai = CombatAI { aggression: 0.5, preferred_distance: 40.0 };
+
+        let battlefield = [archer.clone(), ally, blocked_enemy, open_enemy.clone()];
+        assert_eq!(
+            decide_action(&archer, &ai, &battlefield),
+            AiDecision::Attack { target: open_enemy.entity, direction: AttackDirection::Thrust }
+        );
+    }
+
+    #[test]
+    fn test_ai_unit_swings_in_schedule() {
+        let mut app = combat_test_app();
+        let soldier = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+        app.world_mut().entity_mut(soldier).insert((
+            CombatAI { aggression: 1.0, preferred_distance: 1.0 },
+            BattleSide::Attacker,
+        ));
+        let enemy = spawn_swordsman(&mut app, 10, Vec3::new(0.0, 0.0, -4.0));
+        app.world_mut().entity_mut(enemy).insert(BattleSide::Defender);
+        start_battle(&mut app);
+
+        run_test_app(&mut app, 120);
+
+        assert!(app.world().get::<Health>(enemy).unwrap().current < 100.0);
+    }
+
+    #[test]
+    fn test_ai_guards_against_the_windup_it_sees() {
+        let me = Entity::from_raw(0);
+        let someone_else = Entity::from_raw(2);
+        let mut animation = CombatAnimation::default();
+        assert_eq!(telegraphed_swing(Some(&animation), None, me), None);
+
+        let swing = PendingSwing { target: me, direction: AttackDirection::Left, timing: 1.0 };
+        animation.start_swing(swing, 0.5);
+        assert_eq!(telegraphed_swing(Some(&animation), None, me), Some(AttackDirection::Left));
+        // A swing at someone else is no reason to guard
+        assert_eq!(telegraphed_swing(Some(&animation), None, someone_else), None);
+
+        let player = PlayerCombatState { windup: Some((AttackDirection::Overhead, 0.2)), cooldown: 0.0 };
+        assert_eq!(telegraphed_swing(None, Some(&player), me), Some(AttackDirection::Overhead));
+
+        // Most wind-ups are read right, but not all
+        let mut rng = WyRand::seed_from_u64(5);
+        let misread = (0..1000)
+            .filter(|_| guard_against(AttackDirection::Right, &mut rng) != AttackDirection::Right)
+            .count();
+        assert!((150..350).contains(&misread), "{misread} misreads");
+    }
+}
@@ -0,0 +1,342 @@
+use bevy::prelude::*;
+
+use crate::core::components::{
+    Health, Weapon, AttackDirection, Blocking, Staggered, Sprinting, Exhausted,
+};
+use crate::plugins::stamina::EXHAUSTED_SWING_SPEED_MULTIPLIER;
+
+use super::AttackEvent;
+use super::input::{PlayerCombatState, FULL_WINDUP_TIME};
+use super::mounts::Momentum;
+
+/// Seconds a swing spends in release, the only time it can land
+pub const RELEASE_TIME: f32 = 0.15;
+/// Seconds spent recovering after a swing with a weapon of speed 1.0
+pub const RECOVERY_TIME: f32 = 0.35;
+/// Shortest wind-up the AI commits to, as a share of a full one
+pub const MIN_AI_WINDUP_SHARE: f32 = 0.3;
+/// Ground speed in metres per second above which a unit walks
+pub const WALK_SPEED_THRESHOLD: f32 = 0.2;
+/// Ground speed in metres per second above which a unit runs
+pub const RUN_SPEED_THRESHOLD: f32 = 4.5;
+
+/// What a combatant's body is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum AnimationState {
+    #[default]
+    Idle,
+    Walk,
+    Run,
+    /// Drawing back for a swing or shot
+    WindUp,
+    /// Swing travelling through its arc; hits land only now
+    Release,
+    /// Bringing the weapon back after a swing
+    Recovery,
+    Block,
+    Stagger,
+    Death,
+}
+
+/// A swing on its way through the animation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PendingSwing {
+    pub target: Entity,
+    pub direction: AttackDirection,
+    pub timing: f32,
+}
+
+/// What the state machine needs to know about its combatant this frame
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AnimationInputs {
+    pub dead: bool,
+    pub staggered: bool,
+    pub blocking: bool,
+    pub sprinting: bool,
+    /// Ground speed in metres per second
+    pub speed: f32,
+    /// Weapon speed, scaling how quickly a swing recovers
+    pub swing_speed: f32,
+}
+
+/// Per-combatant animation state machine. Logic only, so it runs headless;
+/// anything that plays clips reads `state` and follows along.
+#[derive(Component, Debug, Clone, Default)]
+pub struct CombatAnimation {
+    pub state: AnimationState,
+    /// Seconds spent in the current state
+    pub elapsed: f32,
+    /// How long the current wind-up, release or recovery lasts
+    pub duration: f32,
+    pub swing: Option<PendingSwing>,
+    /// Whether the swing in release has already been resolved
+    struck: bool,
+}
+
+impl CombatAnimation {
+    /// Begin winding up `swing` over `windup` seconds. Only a unit that is
+    /// standing, moving, guarding or holding an open wind-up can start a swing.
+    pub fn start_swing(&mut self, swing: PendingSwing, windup: f32) -> bool {
+        if !self.can_swing() {
+            return false;
+        }
+        self.enter(AnimationState::WindUp, windup.max(0.0));
+        self.swing = Some(swing);
+        true
+    }
+
+    /// Follow a wind-up held by hand, which lasts until a swing is released
+    /// into it or it is let go
+    pub fn hold_windup(&mut self, holding: bool) {
+        let open_windup = self.state == AnimationState::WindUp && self.swing.is_none();
+        if holding && !open_windup && self.can_swing() {
+            self.enter(AnimationState::WindUp, f32::INFINITY);
+        } else if !holding && open_windup {
+            self.enter(AnimationState::Idle, 0.0);
+        }
+    }
+
+    fn can_swing(&self) -> bool {
+        match self.state {
+            AnimationState::Idle | AnimationState::Walk | AnimationState::Run | AnimationState::Block => true,
+            AnimationState::WindUp => self.swing.is_none(),
+            _ => false,
+        }
+    }
+
+    /// Step the state machine, returning the swing if it just entered release
+    pub fn advance(&mut self, delta: f32, inputs: &AnimationInputs) -> Option<PendingSwing> {
+        self.elapsed += delta;
+
+        if inputs.dead {
+            self.swing = None;
+            self.enter(AnimationState::Death, 0.0);
+            return None;
+        }
+        // A stagger knocks any swing out of the unit's hands
+        if inputs.staggered {
+            self.swing = None;
+            self.enter(AnimationState::Stagger, 0.0);
+            return None;
+        }
+
+        match self.state {
+            AnimationState::WindUp if self.elapsed >= self.duration => {
+                self.enter(AnimationState::Release, RELEASE_TIME);
+                self.struck = false;
+                self.swing
+            }
+            AnimationState::Release if self.elapsed >= self.duration => {
+                self.swing = None;
+                self.enter(AnimationState::Recovery, RECOVERY_TIME / inputs.swing_speed.max(0.1));
+                None
+            }
+            AnimationState::Recovery if self.elapsed >= self.duration => {
+                self.enter(locomotion(inputs), 0.0);
+                None
+            }
+            AnimationState::WindUp | AnimationState::Release | AnimationState::Recovery => None,
+            _ => {
+                self.enter(locomotion(inputs), 0.0);
+                None
+            }
+        }
+    }
+
+    /// Whether `attack` is the swing now in release and hasn't landed yet;
+    /// marks it as landed so it resolves only once
+    pub fn take_release(&mut self, attack: &AttackEvent) -> bool {
+        let releasing = self.state == AnimationState::Release
+            && !self.struck
+            && self.swing.is_some_and(|swing| swing.target == attack.target && swing.direction == attack.direction);
+        if releasing {
+            self.struck = true;
+        }
+        releasing
+    }
+
+    fn enter(&mut self, state: AnimationState, duration: f32) {
+        if self.state != state {
+            self.state = state;
+            self.elapsed = 0.0;
+        }
+        self.duration = duration;
+    }
+}
+
+fn locomotion(inputs: &AnimationInputs) -> AnimationState {
+    if inputs.blocking {
+        AnimationState::Block
+    } else if inputs.sprinting || inputs.speed > RUN_SPEED_THRESHOLD {
+        AnimationState::Run
+    } else if inputs.speed > WALK_SPEED_THRESHOLD {
+        AnimationState::Walk
+    } else {
+        AnimationState::Idle
+    }
+}
+
+/// Turns swing requests into wind-ups and re-sends each swing as it reaches
+/// release, which is when `process_attacks` lets it land. The player's wind-up
+/// happens while the button is held, so their swings release straight away.
+pub(super) fn update_combat_animations(
+    time: Res<Time>,
+    mut attack_events: ParamSet<(EventReader<AttackEvent>, EventWriter<AttackEvent>)>,
+    mut combatants: Query<(
+        Entity,
+        &mut CombatAnimation,
+        &Health,
+        Option<&Weapon>,
+        Option<&Momentum>,
+        Option<&PlayerCombatState>,
+        Has<Blocking>,
+        Has<Staggered>,
+        Has<Sprinting>,
+        Has<Exhausted>,
+    )>,
+) {
+    let requests: Vec<AttackEvent> = attack_events.p0().read().cloned().collect();
+    for attack in requests {
+        let Ok((_, mut animation, _, weapon, _, player, ..)) = combatants.get_mut(attack.attacker) else {
+            continue;
+        };
+        let windup = match player {
+            Some(_) => 0.0,
+            None => {
+                let speed = weapon.map_or(1.0, |weapon| weapon.speed).max(0.1);
+                FULL_WINDUP_TIME * attack.timing.max(MIN_AI_WINDUP_SHARE) / speed
+            }
+        };
+        animation.start_swing(
+            PendingSwing { target: attack.target, direction: attack.direction, timing: attack.timing },
+            windup,
+        );
+    }
+
+    let delta = time.delta_secs();
+    let mut released = Vec::new();
+    for (entity, mut animation, health, weapon, momentum, player, blocking, staggered, sprinting, exhausted) in
+        combatants.iter_mut()
+    {
+        if let Some(player) = player {
+            animation.hold_windup(player.windup.is_some());
+        }
+        let mut swing_speed = weapon.map_or(1.0, |weapon| weapon.speed);
+        if exhausted {
+            swing_speed *= EXHAUSTED_SWING_SPEED_MULTIPLIER;
+        }
+        let inputs = AnimationInputs {
+            dead: health.current <= 0.0,
+            staggered,
+            blocking,
+            sprinting,
+            speed: momentum.map_or(0.0, |momentum| momentum.velocity.with_y(0.0).length()),
+            swing_speed,
+        };
+        if let Some(swing) = animation.advance(delta, &inputs) {
+            released.push((entity, swing));
+        }
+    }
+
+    let mut writer = attack_events.p1();
+    for (attacker, swing) in released {
+        writer.send(AttackEvent {
+            attacker,
+            target: swing.target,
+            direction: swing.direction,
+            timing: swing.timing,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Stamina, BattleSide};
+    use crate::test_support::{combat_test_app, spawn_swordsman, enlist, start_battle, swing_at, run_test_app};
+
+    #[test]
+    fn test_animation_swing_cycle() {
+        let mut animation = CombatAnimation::default();
+        let still = AnimationInputs { swing_speed: 1.0, ..default() };
+        let swing = PendingSwing { target: Entity::from_raw(1), direction: AttackDirection::Left, timing: 1.0 };
+
+        assert!(animation.start_swing(swing, 0.3));
+        assert_eq!(animation.advance(0.1, &still), None);
+        assert_eq!(animation.state, AnimationState::WindUp);
+        // Can't start another swing halfway through one
+        assert!(!animation.start_swing(swing, 0.3));
+
+        assert_eq!(animation.advance(0.25, &still), Some(swing));
+        assert_eq!(animation.state, AnimationState::Release);
+        animation.advance(0.2, &still);
+        assert_eq!(animation.state, AnimationState::Recovery);
+        animation.advance(0.5, &still);
+        assert_eq!(animation.state, AnimationState::Idle);
+
+        // Locomotion and guards
+        animation.advance(0.1, &AnimationInputs { speed: 2.0, ..still });
+        assert_eq!(animation.state, AnimationState::Walk);
+        animation.advance(0.1, &AnimationInputs { speed: 6.0, ..still });
+        assert_eq!(animation.state, AnimationState::Run);
+        animation.advance(0.1, &AnimationInputs { blocking: true, ..still });
+        assert_eq!(animation.state, AnimationState::Block);
+    }
+
+    #[test]
+    fn test_stagger_and_death_interrupt_swings() {
+        let mut animation = CombatAnimation::default();
+        let still = AnimationInputs { swing_speed: 1.0, ..default() };
+        let swing = PendingSwing { target: Entity::from_raw(1), direction: AttackDirection::Overhead, timing: 1.0 };
+
+        animation.start_swing(swing, 0.3);
+        animation.advance(0.1, &AnimationInputs { staggered: true, ..still });
+        assert_eq!(animation.state, AnimationState::Stagger);
+        assert_eq!(animation.swing, None);
+        // Nothing is released once the stagger wears off
+        assert_eq!(animation.advance(0.5, &still), None);
+        assert_eq!(animation.state, AnimationState::Idle);
+
+        animation.advance(0.1, &AnimationInputs { dead: true, ..still });
+        assert_eq!(animation.state, AnimationState::Death);
+        assert!(!animation.start_swing(swing, 0.3));
+    }
+
+    #[test]
+    fn test_animated_swing_lands_only_on_release() {
+        let mut app = combat_test_app();
+        let attacker = spawn_swordsman(&mut app, 10, Vec3::new(0.0, 0.0, -1.0));
+        enlist(&mut app, attacker, BattleSide::Attacker, "vlandia");
+        app.world_mut().entity_mut(attacker).insert(CombatAnimation::default());
+        let target = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+        enlist(&mut app, target, BattleSide::Defender, "looters");
+        start_battle(&mut app);
+
+        // A full 0.6 second wind-up before anything lands
+        swing_at(&mut app, attacker, target, AttackDirection::Left);
+        assert_eq!(app.world().get::<CombatAnimation>(attacker).unwrap().state, AnimationState::WindUp);
+        run_test_app(&mut app, 20);
+        assert_eq!(app.world().get::<Health>(target).unwrap().current, 100.0);
+
+        run_test_app(&mut app, 30);
+        assert!((app.world().get::<Health>(target).unwrap().current - 76.0).abs() < 1e-3);
+        // Stamina is paid once, for the swing that landed
+        assert!((app.world().get::<Stamina>(attacker).unwrap().current - 90.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_animated_swing_misses_target_that_stepped_away() {
+        let mut app = combat_test_app();
+        let attacker = spawn_swordsman(&mut app, 10, Vec3::new(0.0, 0.0, -1.0));
+        enlist(&mut app, attacker, BattleSide::Attacker, "vlandia");
+        app.world_mut().entity_mut(attacker).insert(CombatAnimation::default());
+        let target = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+        enlist(&mut app, target, BattleSide::Defender, "looters");
+        start_battle(&mut app);
+
+        swing_at(&mut app, attacker, target, AttackDirection::Right);
+        app.world_mut().get_mut::<Transform>(target).unwrap().translation.z = 5.0;
+        run_test_app(&mut app, 50);
+        assert_eq!(app.world().get::<Health>(target).unwrap().current, 100.0);
+    }
+}
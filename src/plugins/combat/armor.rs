@@ -0,0 +1,163 @@
+use bevy::prelude::*;
+
+use crate::core::components::{Armor, AttackDirection, BodyPart, DamageType};
+
+use super::scene::UNIT_RADIUS;
+
+/// Armor rating that stops half of a cut; heavier armor gives diminishing returns
+pub const ARMOR_HALF_PROTECTION: f32 = 40.0;
+/// How much of its rating armor brings to bear against each kind of damage
+pub const CUT_ARMOR_EFFECT: f32 = 1.0;
+pub const PIERCE_ARMOR_EFFECT: f32 = 0.7;
+/// Padding spreads a crushing blow but can't turn it aside
+pub const BLUNT_ARMOR_EFFECT: f32 = 0.4;
+/// Height above the middle of a soldier above which a projectile strikes the head
+pub const HEAD_HEIGHT: f32 = 0.65;
+/// Height below the middle of a soldier below which a projectile strikes the legs
+pub const LEGS_HEIGHT: f32 = -0.25;
+/// Share of the soldier's radius off the centre line where the arms are
+pub const ARMS_OFFSET_RATIO: f32 = 0.6;
+
+/// Where a melee swing from `direction` lands
+pub fn melee_hit_location(direction: AttackDirection) -> BodyPart {
+    match direction {
+        AttackDirection::Overhead => BodyPart::Head,
+        AttackDirection::Left | AttackDirection::Right | AttackDirection::Thrust => BodyPart::Body,
+    }
+}
+
+/// Where a projectile striking `impact` hits a soldier standing at `target`
+pub fn impact_location(target: &Transform, impact: Vec3) -> BodyPart {
+    let offset = impact - target.translation;
+    if offset.y > HEAD_HEIGHT {
+        return BodyPart::Head;
+    }
+    if offset.y < LEGS_HEIGHT {
+        return BodyPart::Legs;
+    }
+    let sideways = offset.dot(*target.right()).abs();
+    if sideways > UNIT_RADIUS * ARMS_OFFSET_RATIO {
+        BodyPart::Arms
+    } else {
+        BodyPart::Body
+    }
+}
+
+/// Damage left of a blow of `amount` after armor rated `protection` has stopped what it can
+pub fn armored_damage(amount: f32, damage_type: DamageType, protection: f32) -> f32 {
+    let effect = match damage_type {
+        DamageType::Cut => CUT_ARMOR_EFFECT,
+        DamageType::Pierce => PIERCE_ARMOR_EFFECT,
+        DamageType::Blunt => BLUNT_ARMOR_EFFECT,
+    };
+    let protection = protection.max(0.0);
+    amount * (1.0 - effect * protection / (protection + ARMOR_HALF_PROTECTION))
+}
+
+/// Armor issued to troops of the given tier
+pub fn troop_armor(tier: u8) -> Armor {
+    let tier = tier as f32;
+    Armor {
+        head: 3.0 + tier * 5.0,
+        body: 5.0 + tier * 7.0,
+        arms: 2.0 + tier * 3.0,
+        legs: 2.0 + tier * 4.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Health, Player, WeaponType, BattleSide, KnockedOut, TroopRoster, Prisoners};
+    use crate::plugins::{DamageApplied, BattleSetup, BattleAftermath, AftermathConfirmed};
+    use crate::test_support::{
+        combat_test_app, spawn_soldier, spawn_swordsman, enlist, start_battle, swing_at, test_party, run_test_app,
+    };
+
+    #[test]
+    fn test_armor_stops_damage_types_differently() {
+        assert_eq!(armored_damage(40.0, DamageType::Cut, 0.0), 40.0);
+
+        // Rated 40, armor halves a cut but does less against a point or a mace
+        let cut = armored_damage(40.0, DamageType::Cut, 40.0);
+        let pierce = armored_damage(40.0, DamageType::Pierce, 40.0);
+        let blunt = armored_damage(40.0, DamageType::Blunt, 40.0);
+        assert!((cut - 20.0).abs() < 1e-4);
+        assert!(cut < pierce && pierce < blunt);
+
+        assert_eq!(WeaponType::OneHandedSword.damage_type(AttackDirection::Left), DamageType::Cut);
+        assert_eq!(WeaponType::OneHandedSword.damage_type(AttackDirection::Thrust), DamageType::Pierce);
+        assert_eq!(WeaponType::Crossbow.damage_type(AttackDirection::Thrust), DamageType::Pierce);
+        assert_eq!(WeaponType::Shield.damage_type(AttackDirection::Right), DamageType::Blunt);
+    }
+
+    #[test]
+    fn test_hit_locations() {
+        assert_eq!(melee_hit_location(AttackDirection::Overhead), BodyPart::Head);
+        assert_eq!(melee_hit_location(AttackDirection::Right), BodyPart::Body);
+
+        // A soldier facing -Z: arrows land by height, and off the centre line on an arm
+        let soldier = Transform::from_xyz(0.0, 1.0, 0.0);
+        assert_eq!(impact_location(&soldier, Vec3::new(0.0, 1.8, 0.0)), BodyPart::Head);
+        assert_eq!(impact_location(&soldier, Vec3::new(0.0, 1.2, 0.0)), BodyPart::Body);
+        assert_eq!(impact_location(&soldier, Vec3::new(0.3, 1.2, 0.0)), BodyPart::Arms);
+        assert_eq!(impact_location(&soldier, Vec3::new(0.0, 0.3, 0.0)), BodyPart::Legs);
+    }
+
+    #[test]
+    fn test_armor_protects_the_part_struck() {
+        let mut app = combat_test_app();
+        let attacker = spawn_swordsman(&mut app, 10, Vec3::new(0.0, 0.0, -1.0));
+        let target = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+        app.world_mut().entity_mut(target).insert(Armor { head: 40.0, body: 0.0, arms: 0.0, legs: 0.0 });
+
+        // A helmet halves the 24 damage cut; the unarmored body takes it all
+        swing_at(&mut app, attacker, target, AttackDirection::Overhead);
+        assert!((app.world().get::<Health>(target).unwrap().current - 88.0).abs() < 1e-3);
+        swing_at(&mut app, attacker, target, AttackDirection::Left);
+        assert!((app.world().get::<Health>(target).unwrap().current - 64.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_blunt_blow_knocks_out_instead_of_killing() {
+        let mut app = combat_test_app();
+        let party = app.world_mut().spawn((Player, test_party("vlandia", 5, 0).roster)).id();
+        let enemy_party = app.world_mut().spawn(test_party("looters", 10, 0).roster).id();
+        let mut defender = test_party("looters", 10, 0);
+        defender.party_entity = Some(enemy_party);
+        app.insert_resource(BattleSetup {
+            attacker: test_party("vlandia", 5, 0),
+            defender,
+            field_size: 200.0,
+        });
+
+        let avatar = spawn_soldier(&mut app, WeaponType::Shield, 10, Vec3::ZERO);
+        enlist(&mut app, avatar, BattleSide::Attacker, "vlandia");
+        app.world_mut().entity_mut(avatar).insert(Player);
+        let enemy = spawn_swordsman(&mut app, 10, Vec3::new(0.0, 0.0, -30.0));
+        enlist(&mut app, enemy, BattleSide::Defender, "looters");
+        start_battle(&mut app);
+
+        app.world_mut().send_event(DamageApplied {
+            attacker: avatar,
+            target: enemy,
+            direction: AttackDirection::Overhead,
+            amount: 1000.0,
+            damage_type: DamageType::Blunt,
+            location: BodyPart::Head,
+        });
+        run_test_app(&mut app, 2);
+        assert!(app.world().get::<KnockedOut>(enemy).is_some());
+
+        // Senseless rather than dead, the looter is led away instead of counted among the fallen
+        let aftermath = app.world().resource::<BattleAftermath>();
+        assert_eq!(aftermath.enemy_killed, 0);
+        assert_eq!(aftermath.enemy_wounded, 1);
+        assert_eq!(aftermath.prisoners.iter().map(|stack| stack.count).sum::<u32>(), 1);
+
+        app.world_mut().send_event(AftermathConfirmed);
+        run_test_app(&mut app, 2);
+        assert_eq!(app.world().get::<TroopRoster>(enemy_party).unwrap().healthy_count(), 9);
+        assert_eq!(app.world().get::<Prisoners>(party).unwrap().roster.total_count(), 1);
+    }
+}
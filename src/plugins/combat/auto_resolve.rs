@@ -0,0 +1,297 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use rand_core::RngCore;
+
+use crate::core::components::{
+    BattleSide, CharacterStats, Player, TroopRoster, DamageType,
+};
+use crate::core::states::{CombatState, BattleResult};
+use crate::core::random::roll;
+
+use super::STRENGTH_DAMAGE_BONUS;
+use super::aftermath::{BattleAftermath, compute_aftermath};
+use super::armor::{armored_damage, troop_armor};
+use super::scene::{BattleSetup, BattleParty, troop_weapon, LEADER_TIER};
+
+/// Share of a round's blows that take an enemy down
+pub const AUTO_RESOLVE_HIT_CHANCE: f32 = 0.25;
+/// Chance a troop taken down is knocked out rather than killed
+pub const AUTO_RESOLVE_WOUND_CHANCE: f32 = 0.35;
+/// A side breaks once fewer than this share of its troops are still standing
+pub const AUTO_RESOLVE_BREAK_RATIO: f32 = 0.3;
+/// Rounds fought before the side in better shape is declared the winner
+pub const AUTO_RESOLVE_MAX_ROUNDS: u32 = 50;
+/// How much harder mounted troops hit
+pub const MOUNTED_POWER_MULTIPLIER: f32 = 1.3;
+/// Bonus to the whole side's blows for each level of its leader
+pub const LEADER_TACTICS_PER_LEVEL: f32 = 0.02;
+
+/// A battle settled without taking the field; the results screens show it
+/// like any other
+#[derive(Resource, Debug, Clone)]
+pub struct AutoResolvedBattle {
+    pub result: BattleResult,
+    /// Side the player's troops fought on
+    pub player_side: BattleSide,
+}
+
+/// One troop in an auto-resolved battle
+#[derive(Debug, Clone, Copy)]
+struct Fighter {
+    attack: f32,
+    /// Damage it takes to bring the troop down through its armor
+    toughness: f32,
+}
+
+/// Troops of a party able to fight, and the weight its leader throws behind them
+fn muster(party: &BattleParty) -> (Vec<Fighter>, f32) {
+    let mut fighters = Vec::new();
+    let mut leader_attack = 0.0;
+    let mut tactics = 1.0;
+    if let Some(leader) = &party.leader {
+        let strength = 1.0 + leader.stats.strength as f32 * STRENGTH_DAMAGE_BONUS;
+        leader_attack = troop_weapon(leader.weapon_type, LEADER_TIER).damage * strength;
+        tactics += leader.stats.level as f32 * LEADER_TACTICS_PER_LEVEL;
+    }
+    for stack in &party.roster.stacks {
+        let mut attack = troop_weapon(stack.weapon_type, stack.tier).damage;
+        if stack.mounted {
+            attack *= MOUNTED_POWER_MULTIPLIER;
+        }
+        let max_health = 80.0 + stack.tier as f32 * 10.0;
+        let toughness = max_health / armored_damage(1.0, DamageType::Cut, troop_armor(stack.tier).body);
+        let fighter = Fighter { attack: attack * tactics, toughness };
+        fighters.resize(fighters.len() + stack.count.saturating_sub(stack.wounded) as usize, fighter);
+    }
+    (fighters, leader_attack * tactics)
+}
+
+/// Settles a battle between the two parties of `setup` without fighting it
+/// out on the field. Each round both sides trade blows in proportion to their
+/// troops' weapons against the other side's health and armor, until one side
+/// breaks. Leaders lend their strength and tactics but never fall. The same
+/// rng state always gives the same result.
+pub fn auto_resolve(setup: &BattleSetup, rng: &mut impl RngCore) -> BattleResult {
+    let (mut attackers, attacker_leader) = muster(&setup.attacker);
+    let (mut defenders, defender_leader) = muster(&setup.defender);
+    let attacker_start = attackers.len();
+    let defender_start = defenders.len();
+    let mut attacker_down = (0, 0);
+    let mut defender_down = (0, 0);
+
+    let broken = |standing: usize, start: usize| {
+        standing == 0 || (standing as f32) < start as f32 * AUTO_RESOLVE_BREAK_RATIO
+    };
+    let mut round = 0;
+    while round < AUTO_RESOLVE_MAX_ROUNDS
+        && !broken(attackers.len(), attacker_start)
+        && !broken(defenders.len(), defender_start)
+    {
+        round += 1;
+        // Both sides strike at once, so work out the blows before anyone falls
+        let attacker_blows = attackers.iter().map(|fighter| fighter.attack).sum::<f32>() + attacker_leader;
+        let defender_blows = defenders.iter().map(|fighter| fighter.attack).sum::<f32>() + defender_leader;
+        strike(attacker_blows, &mut defenders, &mut defender_down, rng);
+        strike(defender_blows, &mut attackers, &mut attacker_down, rng);
+    }
+
+    let attacker_share = attackers.len() as f32 / attacker_start.max(1) as f32;
+    let defender_share = defenders.len() as f32 / defender_start.max(1) as f32;
+    let winner = match (broken(attackers.len(), attacker_start), broken(defenders.len(), defender_start)) {
+        (false, true) => BattleSide::Attacker,
+        // The defenders hold the field if both sides break, or neither does and they are in no worse shape
+        (true, _) => BattleSide::Defender,
+        (false, false) if attacker_share > defender_share => BattleSide::Attacker,
+        (false, false) => BattleSide::Defender,
+    };
+
+    let mut result = BattleResult {
+        winner,
+        casualties: HashMap::new(),
+        wounded: HashMap::new(),
+        routed: HashMap::new(),
+        prisoners: HashMap::new(),
+    };
+    for (side, (killed, wounded), standing) in [
+        (BattleSide::Attacker, attacker_down, attackers.len()),
+        (BattleSide::Defender, defender_down, defenders.len()),
+    ] {
+        let faction_id = &setup.party(side).faction.id;
+        let tally = |counts: &mut HashMap<String, u32>, count: u32| {
+            if count > 0 {
+                *counts.entry(faction_id.clone()).or_insert(0) += count;
+            }
+        };
+        tally(&mut result.casualties, killed);
+        tally(&mut result.wounded, wounded);
+        // The beaten side's survivors flee and its wounded are left to the victors
+        if side != winner {
+            tally(&mut result.routed, standing as u32);
+            tally(&mut result.prisoners, wounded);
+        }
+    }
+    result
+}
+
+/// Lands a round's worth of `blows` on `targets`, taking down random troops
+/// and counting them as killed or wounded
+fn strike(blows: f32, targets: &mut Vec<Fighter>, down: &mut (u32, u32), rng: &mut impl RngCore) {
+    if targets.is_empty() {
+        return;
+    }
+    let toughness = targets.iter().map(|fighter| fighter.toughness).sum::<f32>() / targets.len() as f32;
+    // Luck swings a round by half either way
+    let expected = blows * AUTO_RESOLVE_HIT_CHANCE / toughness * (0.5 + roll(rng));
+    let mut falls = expected.floor() as u32;
+    if roll(rng) < expected.fract() {
+        falls += 1;
+    }
+    for _ in 0..falls {
+        if targets.is_empty() {
+            break;
+        }
+        let index = rng.next_u32() as usize % targets.len();
+        targets.swap_remove(index);
+        if roll(rng) < AUTO_RESOLVE_WOUND_CHANCE {
+            down.1 += 1;
+        } else {
+            down.0 += 1;
+        }
+    }
+}
+
+/// Takes an auto-resolved battle straight to the results screen
+pub(super) fn present_auto_resolved(
+    mut commands: Commands,
+    battle: Res<AutoResolvedBattle>,
+    setup: Option<Res<BattleSetup>>,
+    party: Query<Option<&CharacterStats>, (With<Player>, With<TroopRoster>)>,
+    mut battle_results: EventWriter<BattleResult>,
+    mut next_combat_state: ResMut<NextState<CombatState>>,
+) {
+    let victory = battle.result.winner == battle.player_side;
+    let aftermath = setup.map(|setup| {
+        let stats = party.get_single().unwrap_or(None);
+        // The player stayed behind, so comes to no harm and keeps their purse and gear
+        let mut aftermath = compute_aftermath(&battle.result, &setup, battle.player_side, false, stats, None);
+        aftermath.player_wounded = false;
+        aftermath
+    });
+    commands.insert_resource(aftermath.unwrap_or_else(|| BattleAftermath { victory, ..default() }));
+
+    battle_results.send(battle.result.clone());
+    next_combat_state.set(if victory { CombatState::Victory } else { CombatState::Defeat });
+}
+
+pub(super) fn clear_auto_resolved(mut commands: Commands) {
+    commands.remove_resource::<AutoResolvedBattle>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy_rand::prelude::WyRand;
+    use rand_core::SeedableRng;
+    use crate::core::{GameState, WeaponType, Inventory, Item};
+    use crate::plugins::BattleLeader;
+    use crate::test_support::{combat_app, test_party, hero_stats, run_test_app};
+
+    #[test]
+    fn test_auto_resolve_is_reproducible() {
+        let setup = BattleSetup {
+            attacker: test_party("vlandia", 20, 10),
+            defender: test_party("looters", 25, 5),
+            field_size: 200.0,
+        };
+        let first = auto_resolve(&setup, &mut WyRand::seed_from_u64(42));
+        let second = auto_resolve(&setup, &mut WyRand::seed_from_u64(42));
+        assert_eq!(first.winner, second.winner);
+        assert_eq!(first.casualties, second.casualties);
+        assert_eq!(first.wounded, second.wounded);
+        assert_eq!(first.routed, second.routed);
+        assert_eq!(first.prisoners, second.prisoners);
+    }
+
+    #[test]
+    fn test_auto_resolve_favours_stronger_party_and_counts_every_troop() {
+        let mut attacker = test_party("vlandia", 40, 10);
+        attacker.leader = Some(BattleLeader {
+            name: "Derthert".to_string(),
+            stats: hero_stats(),
+            weapon_type: WeaponType::TwoHandedSword,
+            is_player: false,
+        });
+        let setup = BattleSetup {
+            attacker,
+            defender: test_party("looters", 10, 0),
+            field_size: 200.0,
+        };
+        let looters = setup.defender.roster.healthy_count();
+
+        for seed in 0..10 {
+            let result = auto_resolve(&setup, &mut WyRand::seed_from_u64(seed));
+            assert_eq!(result.winner, BattleSide::Attacker);
+            let count = |tally: &HashMap<String, u32>| tally.get("looters").copied().unwrap_or(0);
+            // Every looter is dead, knocked out or running, and the knocked out are taken
+            assert_eq!(count(&result.casualties) + count(&result.wounded) + count(&result.routed), looters);
+            assert_eq!(count(&result.prisoners), count(&result.wounded));
+            assert!(result.prisoners.get("vlandia").is_none());
+        }
+    }
+
+    #[test]
+    fn test_sent_troops_go_straight_to_results() {
+        let mut app = combat_app();
+        let setup = BattleSetup {
+            attacker: test_party("player", 30, 10),
+            defender: test_party("looters", 8, 0),
+            field_size: 200.0,
+        };
+        let result = auto_resolve(&setup, &mut WyRand::seed_from_u64(3));
+        let victory = result.winner == BattleSide::Attacker;
+        app.insert_resource(AutoResolvedBattle { result, player_side: BattleSide::Attacker });
+        app.insert_resource(setup);
+        app.world_mut().resource_mut::<NextState<GameState>>().set(GameState::Combat);
+        run_test_app(&mut app, 3);
+
+        // Nobody takes the field; the results screen comes straight up
+        let world = app.world_mut();
+        assert_eq!(world.query::<&BattleSide>().iter(world).count(), 0);
+        let expected = if victory { CombatState::Victory } else { CombatState::Defeat };
+        assert_eq!(*app.world().resource::<State<CombatState>>().get(), expected);
+        let aftermath = app.world().resource::<BattleAftermath>();
+        assert_eq!(aftermath.victory, victory);
+        assert!(!aftermath.player_wounded);
+    }
+
+    #[test]
+    fn test_sent_troops_lose_without_costing_the_player_their_purse() {
+        let mut app = combat_app();
+        let setup = BattleSetup {
+            attacker: test_party("player", 2, 0),
+            defender: test_party("looters", 40, 0),
+            field_size: 200.0,
+        };
+        let result = auto_resolve(&setup, &mut WyRand::seed_from_u64(3));
+        assert_eq!(result.winner, BattleSide::Defender);
+        app.world_mut().spawn((
+            Player,
+            hero_stats(),
+            setup.attacker.roster.clone(),
+            Inventory {
+                gold: 300,
+                items: vec![Item { name: "Sword".to_string(), item_type: "Weapon".to_string(), value: 50 }],
+            },
+        ));
+        app.insert_resource(AutoResolvedBattle { result, player_side: BattleSide::Attacker });
+        app.insert_resource(setup);
+        app.world_mut().resource_mut::<NextState<GameState>>().set(GameState::Combat);
+        run_test_app(&mut app, 3);
+
+        assert_eq!(*app.world().resource::<State<CombatState>>().get(), CombatState::Defeat);
+        let aftermath = app.world().resource::<BattleAftermath>();
+        assert!(!aftermath.victory);
+        assert_eq!(aftermath.gold_lost, 0);
+        assert!(aftermath.items_lost.is_empty());
+    }
+}
@@ -0,0 +1,241 @@
+use bevy::prelude::*;
+
+use crate::core::components::{
+    AttackDirection, Blocking, Staggered, Stamina, DamageType, BodyPart, Exhausted,
+};
+
+use super::{AttackBlocked, DamageApplied};
+use crate::plugins::stamina::EXHAUSTED_BLOCK_COST_MULTIPLIER;
+
+/// Seconds after raising a guard during which a block becomes a parry
+pub const PARRY_WINDOW: f32 = 0.15;
+/// Seconds into a wind-up during which a swing in the attacker's direction chambers the attack
+pub const CHAMBER_WINDOW: f32 = 0.2;
+pub const PARRY_STAGGER_TIME: f32 = 1.0;
+pub const GUARD_BREAK_STAGGER_TIME: f32 = 1.2;
+/// Stamina a weapon block costs per point of damage stopped
+pub const BLOCK_STAMINA_PER_DAMAGE: f32 = 0.5;
+/// Shields spread the blow; they cost less stamina and take durability damage instead
+pub const SHIELD_BLOCK_STAMINA_PER_DAMAGE: f32 = 0.2;
+/// Share of the blow that still lands when it breaks through a guard
+pub const GUARD_BREAK_DAMAGE_FACTOR: f32 = 0.5;
+/// Weapon guards only cover attacks from in front of the defender
+pub const WEAPON_BLOCK_ARC: f32 = 180.0;
+
+/// How a hit was stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockKind {
+    /// Caught on a shield, which takes the damage
+    Shield,
+    /// Caught on a weapon guarding the right direction
+    Weapon,
+    /// Caught on a guard raised just in time; the attacker is thrown off balance
+    Parry,
+    /// Met by a swing from the same direction early in its wind-up
+    Chamber,
+}
+
+/// Decides whether a defender stops a melee attack. `guard` is the defender's
+/// raised block, `windup` the swing they are winding up, `shield_covers`
+/// whether an intact shield faces the attacker and `facing_attacker` whether
+/// the attacker is in front at all.
+pub fn resolve_block(
+    direction: AttackDirection,
+    guard: Option<&Blocking>,
+    windup: Option<(AttackDirection, f32)>,
+    shield_covers: bool,
+    facing_attacker: bool,
+) -> Option<BlockKind> {
+    if facing_attacker && windup.is_some_and(|(swing, held)| swing == direction && held <= CHAMBER_WINDOW) {
+        return Some(BlockKind::Chamber);
+    }
+
+    if let Some(guard) = guard {
+        // A raised shield covers every direction; a weapon only the one it guards
+        let covered = shield_covers || (facing_attacker && guard.direction == direction);
+        if covered && guard.held <= PARRY_WINDOW {
+            return Some(BlockKind::Parry);
+        }
+        if covered {
+            return Some(if shield_covers { BlockKind::Shield } else { BlockKind::Weapon });
+        }
+    }
+
+    // Shields soak frontal hits even when not raised
+    shield_covers.then_some(BlockKind::Shield)
+}
+
+pub(super) fn tick_guards(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut guards: Query<&mut Blocking>,
+    mut staggered: Query<(Entity, &mut Staggered)>,
+) {
+    let delta = time.delta_secs();
+    for mut guard in guards.iter_mut() {
+        guard.held += delta;
+    }
+    for (entity, mut stagger) in staggered.iter_mut() {
+        stagger.remaining -= delta;
+        if stagger.remaining <= 0.0 {
+            commands.entity(entity).remove::<Staggered>();
+        }
+    }
+}
+
+/// Pays for blocks in stamina, staggers parried attackers and lets blows
+/// through guards that can no longer hold. Exhausted defenders can't parry
+/// and pay more for every block.
+pub(super) fn handle_blocks(
+    mut commands: Commands,
+    mut blocked_events: EventReader<AttackBlocked>,
+    mut damage_events: EventWriter<DamageApplied>,
+    mut defenders: Query<(&mut Stamina, Has<Exhausted>)>,
+) {
+    for event in blocked_events.read() {
+        let exhausted = defenders.get(event.target).is_ok_and(|(_, exhausted)| exhausted);
+        let kind = match event.kind {
+            BlockKind::Parry if exhausted => BlockKind::Weapon,
+            kind => kind,
+        };
+        let cost_per_damage = match kind {
+            BlockKind::Parry => {
+                commands.entity(event.attacker).insert(Staggered { remaining: PARRY_STAGGER_TIME });
+                continue;
+            }
+            BlockKind::Chamber => continue,
+            BlockKind::Shield => SHIELD_BLOCK_STAMINA_PER_DAMAGE,
+            BlockKind::Weapon => BLOCK_STAMINA_PER_DAMAGE,
+        };
+
+        let Ok((mut stamina, _)) = defenders.get_mut(event.target) else {
+            continue;
+        };
+        let mut cost = event.damage * cost_per_damage;
+        if exhausted {
+            cost *= EXHAUSTED_BLOCK_COST_MULTIPLIER;
+        }
+        if stamina.current >= cost {
+            stamina.current -= cost;
+            continue;
+        }
+
+        // Too tired to hold: the guard gives way. A shield still takes the hit itself.
+        stamina.current = 0.0;
+        if kind == BlockKind::Weapon {
+            commands
+                .entity(event.target)
+                .remove::<Blocking>()
+                .insert(Staggered { remaining: GUARD_BREAK_STAGGER_TIME });
+            damage_events.send(DamageApplied {
+                attacker: event.attacker,
+                target: event.target,
+                direction: event.direction,
+                amount: event.damage * GUARD_BREAK_DAMAGE_FACTOR,
+                // The blow batters through the guarding arm
+                damage_type: DamageType::Blunt,
+                location: BodyPart::Arms,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Health;
+    use crate::test_support::{combat_test_app, spawn_swordsman, swing_at, run_test_app};
+
+    #[test]
+    fn test_resolve_block_rules() {
+        let high_guard = Blocking { direction: AttackDirection::Overhead, held: 1.0 };
+
+        // Weapon guards only stop the direction they cover, and only from the front
+        assert_eq!(resolve_block(AttackDirection::Overhead, Some(&high_guard), None, false, true), Some(BlockKind::Weapon));
+        assert_eq!(resolve_block(AttackDirection::Left, Some(&high_guard), None, false, true), None);
+        assert_eq!(resolve_block(AttackDirection::Overhead, Some(&high_guard), None, false, false), None);
+
+        // A raised shield covers every direction
+        assert_eq!(resolve_block(AttackDirection::Left, Some(&high_guard), None, true, true), Some(BlockKind::Shield));
+
+        // A guard raised at the last moment parries
+        let fresh = Blocking { held: 0.05, ..high_guard };
+        assert_eq!(resolve_block(AttackDirection::Overhead, Some(&fresh), None, false, true), Some(BlockKind::Parry));
+
+        // Swinging back from the same direction early in the wind-up chambers the attack
+        assert_eq!(
+            resolve_block(AttackDirection::Right, None, Some((AttackDirection::Right, 0.1)), false, true),
+            Some(BlockKind::Chamber)
+        );
+        assert_eq!(resolve_block(AttackDirection::Right, None, Some((AttackDirection::Right, 0.5)), false, true), None);
+    }
+
+    #[test]
+    fn test_directional_block_costs_stamina() {
+        let mut app = combat_test_app();
+        let attacker = spawn_swordsman(&mut app, 10, Vec3::new(0.0, 0.0, -1.0));
+        let defender = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+        app.world_mut().entity_mut(defender).insert(Blocking { direction: AttackDirection::Overhead, held: 1.0 });
+
+        swing_at(&mut app, attacker, defender, AttackDirection::Overhead);
+        assert_eq!(app.world().get::<Health>(defender).unwrap().current, 100.0);
+        // 24 damage stopped at half a point of stamina each
+        assert!((app.world().get::<Stamina>(defender).unwrap().current - 88.0).abs() < 1e-3);
+
+        // The wrong guard doesn't help
+        swing_at(&mut app, attacker, defender, AttackDirection::Left);
+        assert!(app.world().get::<Health>(defender).unwrap().current < 100.0);
+    }
+
+    #[test]
+    fn test_parry_staggers_attacker() {
+        let mut app = combat_test_app();
+        let attacker = spawn_swordsman(&mut app, 10, Vec3::new(0.0, 0.0, -1.0));
+        let defender = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+        app.world_mut().entity_mut(defender).insert(Blocking { direction: AttackDirection::Thrust, held: 0.0 });
+
+        swing_at(&mut app, attacker, defender, AttackDirection::Thrust);
+        run_test_app(&mut app, 1);
+        assert!(app.world().get::<Staggered>(attacker).is_some());
+        assert_eq!(app.world().get::<Stamina>(defender).unwrap().current, 100.0);
+
+        // Off balance, the attacker can't follow up
+        let stamina = app.world().get::<Stamina>(attacker).unwrap().current;
+        swing_at(&mut app, attacker, defender, AttackDirection::Left);
+        assert_eq!(app.world().get::<Stamina>(attacker).unwrap().current, stamina);
+        assert_eq!(app.world().get::<Health>(defender).unwrap().current, 100.0);
+    }
+
+    #[test]
+    fn test_exhausted_guard_breaks() {
+        let mut app = combat_test_app();
+        let attacker = spawn_swordsman(&mut app, 10, Vec3::new(0.0, 0.0, -1.0));
+        let defender = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+        app.world_mut().entity_mut(defender).insert(Blocking { direction: AttackDirection::Overhead, held: 1.0 });
+        app.world_mut().get_mut::<Stamina>(defender).unwrap().current = 5.0;
+
+        swing_at(&mut app, attacker, defender, AttackDirection::Overhead);
+        run_test_app(&mut app, 1);
+        // Half of the 24 damage gets through
+        assert!((app.world().get::<Health>(defender).unwrap().current - 88.0).abs() < 1e-3);
+        assert!(app.world().get::<Blocking>(defender).is_none());
+        assert!(app.world().get::<Staggered>(defender).is_some());
+    }
+
+    #[test]
+    fn test_exhausted_guard_cannot_parry() {
+        let mut app = combat_test_app();
+        let attacker = spawn_swordsman(&mut app, 10, Vec3::new(0.0, 0.0, -1.0));
+        let defender = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+        app.world_mut().entity_mut(defender).insert((
+            Blocking { direction: AttackDirection::Thrust, held: 0.0 },
+            Exhausted,
+        ));
+
+        swing_at(&mut app, attacker, defender, AttackDirection::Thrust);
+        run_test_app(&mut app, 1);
+        assert!(app.world().get::<Staggered>(attacker).is_none());
+        // A plain weapon block of 24 damage, paid twice over
+        assert!((app.world().get::<Stamina>(defender).unwrap().current - 76.0).abs() < 1e-3);
+    }
+}
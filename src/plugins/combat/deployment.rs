@@ -0,0 +1,374 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use serde::{Serialize, Deserialize};
+
+use crate::core::components::{BattleSide, FormationGroup, Shield};
+use crate::core::states::CombatState;
+
+use super::orders::FormationOrder;
+
+/// Distance between the infantry line and the formations deployed ahead of or beside it
+pub const FORMATION_GROUP_SPACING: f32 = 10.0;
+pub const FLANK_OFFSET: f32 = 25.0;
+
+/// Shape a formation takes around its anchor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FormationTemplate {
+    Line,
+    ShieldWall,
+    Loose,
+    Wedge,
+    Column,
+}
+
+impl FormationTemplate {
+    /// Gap between neighbours in a rank and between ranks
+    fn spacing(self) -> (f32, f32) {
+        match self {
+            FormationTemplate::Line => (1.5, 2.0),
+            FormationTemplate::ShieldWall => (0.9, 1.2),
+            FormationTemplate::Loose => (3.0, 3.0),
+            FormationTemplate::Wedge => (1.5, 1.5),
+            FormationTemplate::Column => (1.5, 2.0),
+        }
+    }
+
+    /// Units in each rank, front rank first
+    fn ranks(self, count: usize) -> Vec<usize> {
+        let width = match self {
+            FormationTemplate::Line | FormationTemplate::Loose => count.div_ceil(2).max(1),
+            FormationTemplate::ShieldWall => count.div_ceil(3).max(1),
+            FormationTemplate::Column => 4,
+            // Each rank of a wedge is two wider than the one in front of it
+            FormationTemplate::Wedge => {
+                let mut ranks = Vec::new();
+                let mut remaining = count;
+                let mut rank = 0;
+                while remaining > 0 {
+                    let size = (2 * rank + 1).min(remaining);
+                    ranks.push(size);
+                    remaining -= size;
+                    rank += 1;
+                }
+                return ranks;
+            }
+        };
+
+        let mut ranks = vec![width; count / width];
+        if count % width > 0 {
+            ranks.push(count % width);
+        }
+        ranks
+    }
+}
+
+/// Local slot offsets for `count` units: `x` to the formation's right, `y`
+/// forwards. The front rank is centred on the anchor and later ranks stand behind it.
+pub fn formation_offsets(template: FormationTemplate, count: usize) -> Vec<Vec2> {
+    let (spacing, rank_spacing) = template.spacing();
+    let mut offsets = Vec::with_capacity(count);
+
+    for (rank, size) in template.ranks(count).into_iter().enumerate() {
+        let half_width = (size as f32 - 1.0) / 2.0;
+        for file in 0..size {
+            offsets.push(Vec2::new(
+                (file as f32 - half_width) * spacing,
+                -(rank as f32) * rank_spacing,
+            ));
+        }
+    }
+    offsets
+}
+
+/// World position of a formation slot
+pub fn slot_position(anchor: Vec3, facing: Vec3, offset: Vec2) -> Vec3 {
+    let forward = Vec3::new(facing.x, 0.0, facing.z).normalize_or(Vec3::NEG_Z);
+    let right = forward.cross(Vec3::Y);
+    anchor + right * offset.x + forward * offset.y
+}
+
+/// Area a side may deploy in, facing the enemy
+#[derive(Debug, Clone)]
+pub struct DeploymentZone {
+    pub center: Vec3,
+    pub half_width: f32,
+    pub half_depth: f32,
+    pub facing: Vec3,
+}
+
+impl DeploymentZone {
+    /// Nearest point inside the zone
+    pub fn clamp(&self, point: Vec3) -> Vec3 {
+        let forward = Vec3::new(self.facing.x, 0.0, self.facing.z).normalize_or(Vec3::NEG_Z);
+        let right = forward.cross(Vec3::Y);
+        let offset = point - self.center;
+        let across = offset.dot(right).clamp(-self.half_width, self.half_width);
+        let along = offset.dot(forward).clamp(-self.half_depth, self.half_depth);
+        self.center + right * across + forward * along + Vec3::Y * offset.y
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct DeploymentZones {
+    pub attacker: DeploymentZone,
+    pub defender: DeploymentZone,
+}
+
+impl DeploymentZones {
+    pub fn get(&self, side: BattleSide) -> &DeploymentZone {
+        match side {
+            BattleSide::Attacker => &self.attacker,
+            BattleSide::Defender => &self.defender,
+        }
+    }
+}
+
+impl Default for DeploymentZones {
+    fn default() -> Self {
+        Self {
+            attacker: DeploymentZone {
+                center: Vec3::new(0.0, 0.0, 40.0),
+                half_width: 40.0,
+                half_depth: 15.0,
+                facing: Vec3::NEG_Z,
+            },
+            defender: DeploymentZone {
+                center: Vec3::new(0.0, 0.0, -40.0),
+                half_width: 40.0,
+                half_depth: 15.0,
+                facing: Vec3::Z,
+            },
+        }
+    }
+}
+
+/// Where and how one formation group of a side stands, and what it was told to do
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Formation {
+    pub template: FormationTemplate,
+    pub anchor: Vec3,
+    pub facing: Vec3,
+    pub order: FormationOrder,
+    pub fire_at_will: bool,
+}
+
+/// Every formation on the field, keyed by side and group
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Formations {
+    formations: HashMap<(BattleSide, FormationGroup), Formation>,
+}
+
+impl Formations {
+    pub fn get(&self, side: BattleSide, group: FormationGroup) -> Option<&Formation> {
+        self.formations.get(&(side, group))
+    }
+
+    pub fn get_mut(&mut self, side: BattleSide, group: FormationGroup) -> Option<&mut Formation> {
+        self.formations.get_mut(&(side, group))
+    }
+
+    pub fn insert(&mut self, side: BattleSide, group: FormationGroup, formation: Formation) {
+        self.formations.insert((side, group), formation);
+    }
+
+    pub fn clear(&mut self) {
+        self.formations.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (BattleSide, FormationGroup, &Formation)> {
+        self.formations.iter().map(|((side, group), formation)| (*side, *group, formation))
+    }
+}
+
+/// The default layout used by AI sides, and as the starting point for the player's
+pub fn auto_formation(zone: &DeploymentZone, group: FormationGroup, shielded_ratio: f32) -> Formation {
+    let forward = Vec3::new(zone.facing.x, 0.0, zone.facing.z).normalize_or(Vec3::NEG_Z);
+    let right = forward.cross(Vec3::Y);
+    let (template, anchor) = match group {
+        FormationGroup::Infantry if shielded_ratio >= 0.5 => (FormationTemplate::ShieldWall, zone.center),
+        FormationGroup::Infantry => (FormationTemplate::Line, zone.center),
+        FormationGroup::Archers => (FormationTemplate::Loose, zone.center + forward * FORMATION_GROUP_SPACING),
+        FormationGroup::Cavalry => (FormationTemplate::Wedge, zone.center + right * FLANK_OFFSET),
+        FormationGroup::Skirmishers => (FormationTemplate::Loose, zone.center - right * FLANK_OFFSET),
+    };
+
+    Formation {
+        template,
+        anchor: zone.clamp(anchor),
+        facing: forward,
+        order: FormationOrder::Hold,
+        fire_at_will: true,
+    }
+}
+
+// Deployment events
+/// Move a unit into another formation group
+#[derive(Event, Debug, Clone)]
+pub struct AssignFormationGroup {
+    pub entity: Entity,
+    pub group: FormationGroup,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct SetFormationTemplate {
+    pub side: BattleSide,
+    pub group: FormationGroup,
+    pub template: FormationTemplate,
+}
+
+/// Drag a formation's anchor; it is kept inside the side's deployment zone
+#[derive(Event, Debug, Clone)]
+pub struct MoveFormation {
+    pub side: BattleSide,
+    pub group: FormationGroup,
+    pub anchor: Vec3,
+}
+
+/// The player is happy with their deployment and wants to fight
+#[derive(Event, Debug, Clone)]
+pub struct DeploymentReady;
+
+/// Units of one formation in a stable order, so slots don't shuffle between frames
+pub(super) fn group_units<T>(
+    units: impl Iterator<Item = (Entity, BattleSide, FormationGroup, T)>,
+) -> HashMap<(BattleSide, FormationGroup), Vec<(Entity, T)>> {
+    let mut groups: HashMap<(BattleSide, FormationGroup), Vec<(Entity, T)>> = HashMap::new();
+    for (entity, side, group, data) in units {
+        groups.entry((side, group)).or_default().push((entity, data));
+    }
+    for members in groups.values_mut() {
+        members.sort_by_key(|(entity, _)| *entity);
+    }
+    groups
+}
+
+pub(super) fn deploy_troops(
+    mut commands: Commands,
+    zones: Res<DeploymentZones>,
+    mut formations: ResMut<Formations>,
+    mut assign_events: EventReader<AssignFormationGroup>,
+    mut template_events: EventReader<SetFormationTemplate>,
+    mut move_events: EventReader<MoveFormation>,
+    mut ready_events: EventReader<DeploymentReady>,
+    mut next_combat_state: ResMut<NextState<CombatState>>,
+    mut units: Query<(Entity, &BattleSide, &FormationGroup, &mut Transform, Has<Shield>)>,
+) {
+    for event in assign_events.read() {
+        commands.entity(event.entity).insert(event.group);
+    }
+
+    let groups = group_units(
+        units.iter().map(|(entity, side, group, _, shielded)| (entity, *side, *group, shielded)),
+    );
+
+    // Groups nobody has arranged yet get the same layout the AI uses
+    for ((side, group), members) in groups.iter() {
+        if formations.get(*side, *group).is_none() {
+            let shielded = members.iter().filter(|(_, shielded)| *shielded).count();
+            let ratio = shielded as f32 / members.len() as f32;
+            formations.insert(*side, *group, auto_formation(zones.get(*side), *group, ratio));
+        }
+    }
+
+    for event in template_events.read() {
+        if let Some(formation) = formations.get_mut(event.side, event.group) {
+            formation.template = event.template;
+        }
+    }
+    for event in move_events.read() {
+        if let Some(formation) = formations.get_mut(event.side, event.group) {
+            formation.anchor = zones.get(event.side).clamp(event.anchor);
+        }
+    }
+
+    for ((side, group), members) in groups.iter() {
+        let Some(formation) = formations.get(*side, *group) else {
+            continue;
+        };
+        let offsets = formation_offsets(formation.template, members.len());
+        for ((entity, _), offset) in members.iter().zip(offsets) {
+            let Ok((.., mut transform, _)) = units.get_mut(*entity) else {
+                continue;
+            };
+            let position = slot_position(formation.anchor, formation.facing, offset);
+            transform.translation = Vec3::new(position.x, transform.translation.y, position.z);
+            let look_target = transform.translation + formation.facing;
+            transform.look_at(look_target, Vec3::Y);
+        }
+    }
+
+    if ready_events.read().last().is_some() {
+        next_combat_state.set(CombatState::Active);
+    }
+}
+
+pub(super) fn reset_formations(mut formations: ResMut<Formations>) {
+    formations.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{combat_test_app, spawn_swordsman, run_test_app};
+
+    #[test]
+    fn test_formation_templates_place_every_unit() {
+        for template in [
+            FormationTemplate::Line,
+            FormationTemplate::ShieldWall,
+            FormationTemplate::Loose,
+            FormationTemplate::Wedge,
+            FormationTemplate::Column,
+        ] {
+            assert_eq!(formation_offsets(template, 17).len(), 17);
+        }
+
+        // A wedge leads with a single unit
+        let wedge = formation_offsets(FormationTemplate::Wedge, 9);
+        assert_eq!(wedge.iter().filter(|offset| offset.y == 0.0).count(), 1);
+
+        // A shield wall packs the same men tighter than a loose formation
+        let width = |offsets: Vec<Vec2>| offsets.iter().map(|offset| offset.x.abs()).fold(0.0, f32::max);
+        assert!(width(formation_offsets(FormationTemplate::ShieldWall, 12)) < width(formation_offsets(FormationTemplate::Loose, 12)));
+    }
+
+    #[test]
+    fn test_deployment_positions_groups_and_starts_battle() {
+        let mut app = combat_test_app();
+        let mut infantry = Vec::new();
+        for _ in 0..6 {
+            let unit = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+            app.world_mut().entity_mut(unit).insert((BattleSide::Defender, FormationGroup::Infantry));
+            infantry.push(unit);
+        }
+        run_test_app(&mut app, 1);
+
+        let zone = app.world().resource::<DeploymentZones>().defender.clone();
+        for unit in &infantry {
+            let position = app.world().get::<Transform>(*unit).unwrap().translation;
+            assert!(zone.clamp(position).distance(position) < 1e-4);
+        }
+        let template = app.world().resource::<Formations>()
+            .get(BattleSide::Defender, FormationGroup::Infantry)
+            .unwrap()
+            .template;
+        assert_eq!(template, FormationTemplate::Line);
+
+        // Anchors dragged outside the zone are pulled back in
+        app.world_mut().send_event(MoveFormation {
+            side: BattleSide::Defender,
+            group: FormationGroup::Infantry,
+            anchor: Vec3::new(0.0, 0.0, 500.0),
+        });
+        run_test_app(&mut app, 1);
+        let anchor = app.world().resource::<Formations>()
+            .get(BattleSide::Defender, FormationGroup::Infantry)
+            .unwrap()
+            .anchor;
+        assert_eq!(anchor, zone.clamp(Vec3::new(0.0, 0.0, 500.0)));
+
+        app.world_mut().send_event(DeploymentReady);
+        run_test_app(&mut app, 2);
+        assert_eq!(app.world().resource::<State<CombatState>>().get(), &CombatState::Active);
+    }
+}
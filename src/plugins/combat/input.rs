@@ -0,0 +1,387 @@
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::core::components::{
+    Health, Weapon, CharacterController, BattleSide, AttackDirection, Blocking, Sprinting,
+    Crouching, WeaponLoadout, Staggered, Mount, Exhausted,
+};
+
+use super::{WeaponRules, AttackEvent, KickEvent, KICK_REACH, within_arc};
+use super::ai::BASE_SWING_INTERVAL;
+use super::mounts::ToggleMount;
+use crate::plugins::stamina::EXHAUSTED_SWING_SPEED_MULTIPLIER;
+
+/// Seconds of wind-up for a fully charged swing with a weapon of speed 1.0
+pub const FULL_WINDUP_TIME: f32 = 0.6;
+pub const SPRINT_SPEED_MULTIPLIER: f32 = 1.6;
+pub const CROUCH_SPEED_MULTIPLIER: f32 = 0.5;
+/// Scales raw mouse motion down to a turn rate
+pub const MOUSE_TURN_SENSITIVITY: f32 = 0.1;
+/// Widest angle off the player's facing a swing or kick still finds a target in
+pub const PLAYER_ATTACK_ARC: f32 = 120.0;
+
+/// Everything the player can do with their avatar in battle
+#[derive(Actionlike, Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
+pub enum CombatAction {
+    /// Walk relative to the way the avatar faces: `y` forwards, `x` to the right
+    #[actionlike(DualAxis)]
+    Move,
+    /// Turn rate, positive to the right
+    #[actionlike(Axis)]
+    Turn,
+    AttackOverhead,
+    AttackLeft,
+    AttackRight,
+    AttackThrust,
+    BlockOverhead,
+    BlockLeft,
+    BlockRight,
+    BlockThrust,
+    Kick,
+    SwitchWeapon,
+    Sprint,
+    Crouch,
+    /// Climb onto a nearby horse or get off the current one
+    ToggleMount,
+}
+
+impl CombatAction {
+    pub const ATTACKS: [(CombatAction, AttackDirection); 4] = [
+        (CombatAction::AttackOverhead, AttackDirection::Overhead),
+        (CombatAction::AttackLeft, AttackDirection::Left),
+        (CombatAction::AttackRight, AttackDirection::Right),
+        (CombatAction::AttackThrust, AttackDirection::Thrust),
+    ];
+
+    pub const BLOCKS: [(CombatAction, AttackDirection); 4] = [
+        (CombatAction::BlockOverhead, AttackDirection::Overhead),
+        (CombatAction::BlockLeft, AttackDirection::Left),
+        (CombatAction::BlockRight, AttackDirection::Right),
+        (CombatAction::BlockThrust, AttackDirection::Thrust),
+    ];
+
+    /// Every action that is simply held down or not
+    pub const BUTTONS: [CombatAction; 13] = [
+        CombatAction::AttackOverhead,
+        CombatAction::AttackLeft,
+        CombatAction::AttackRight,
+        CombatAction::AttackThrust,
+        CombatAction::BlockOverhead,
+        CombatAction::BlockLeft,
+        CombatAction::BlockRight,
+        CombatAction::BlockThrust,
+        CombatAction::Kick,
+        CombatAction::SwitchWeapon,
+        CombatAction::Sprint,
+        CombatAction::Crouch,
+        CombatAction::ToggleMount,
+    ];
+
+    /// Keyboard and mouse, with a gamepad layout alongside
+    pub fn default_input_map() -> InputMap<CombatAction> {
+        InputMap::default()
+            .with_dual_axis(CombatAction::Move, VirtualDPad::wasd())
+            .with_dual_axis(CombatAction::Move, GamepadStick::LEFT)
+            .with_axis(CombatAction::Turn, MouseMoveAxis::X.sensitivity(MOUSE_TURN_SENSITIVITY))
+            .with_axis(CombatAction::Turn, GamepadControlAxis::RIGHT_X)
+            // Swings on the arrow keys, with the usual right-to-left cut on the left mouse button
+            .with(CombatAction::AttackOverhead, KeyCode::ArrowUp)
+            .with(CombatAction::AttackLeft, KeyCode::ArrowLeft)
+            .with(CombatAction::AttackRight, KeyCode::ArrowRight)
+            .with(CombatAction::AttackRight, MouseButton::Left)
+            .with(CombatAction::AttackThrust, KeyCode::ArrowDown)
+            .with(CombatAction::AttackOverhead, GamepadButton::North)
+            .with(CombatAction::AttackLeft, GamepadButton::West)
+            .with(CombatAction::AttackRight, GamepadButton::RightTrigger2)
+            .with(CombatAction::AttackThrust, GamepadButton::South)
+            // Guards on IJKL, a high guard on the right mouse button, left trigger plus d-pad on a pad
+            .with(CombatAction::BlockOverhead, KeyCode::KeyI)
+            .with(CombatAction::BlockLeft, KeyCode::KeyJ)
+            .with(CombatAction::BlockRight, KeyCode::KeyL)
+            .with(CombatAction::BlockThrust, KeyCode::KeyK)
+            .with(CombatAction::BlockOverhead, MouseButton::Right)
+            .with(CombatAction::BlockOverhead, ButtonlikeChord::new([GamepadButton::LeftTrigger2, GamepadButton::DPadUp]))
+            .with(CombatAction::BlockLeft, ButtonlikeChord::new([GamepadButton::LeftTrigger2, GamepadButton::DPadLeft]))
+            .with(CombatAction::BlockRight, ButtonlikeChord::new([GamepadButton::LeftTrigger2, GamepadButton::DPadRight]))
+            .with(CombatAction::BlockThrust, ButtonlikeChord::new([GamepadButton::LeftTrigger2, GamepadButton::DPadDown]))
+            .with(CombatAction::Kick, KeyCode::KeyE)
+            .with(CombatAction::Kick, GamepadButton::East)
+            .with(CombatAction::SwitchWeapon, KeyCode::KeyQ)
+            .with(CombatAction::SwitchWeapon, GamepadButton::LeftTrigger)
+            .with(CombatAction::Sprint, KeyCode::ShiftLeft)
+            .with(CombatAction::Sprint, GamepadButton::LeftThumb)
+            .with(CombatAction::Crouch, KeyCode::ControlLeft)
+            .with(CombatAction::Crouch, GamepadButton::RightThumb)
+            .with(CombatAction::ToggleMount, KeyCode::KeyF)
+            .with(CombatAction::ToggleMount, GamepadButton::Select)
+    }
+}
+
+/// Swing the player is winding up, and how long until they may start another
+#[derive(Component, Debug, Clone, Default)]
+pub struct PlayerCombatState {
+    /// Direction of the held swing and seconds it has been held
+    pub windup: Option<(AttackDirection, f32)>,
+    pub cooldown: f32,
+}
+
+/// Input map and state for an avatar the player fights with
+pub fn player_input_bundle() -> impl Bundle {
+    (
+        CombatAction::default_input_map(),
+        ActionState::<CombatAction>::default(),
+        PlayerCombatState::default(),
+    )
+}
+
+pub(super) fn process_combat_input(
+    mut commands: Commands,
+    time: Res<Time>,
+    rules: Res<WeaponRules>,
+    mut attack_events: EventWriter<AttackEvent>,
+    mut kick_events: EventWriter<KickEvent>,
+    mut mount_toggles: EventWriter<ToggleMount>,
+    mut players: Query<(
+        Entity,
+        &ActionState<CombatAction>,
+        &mut PlayerCombatState,
+        &mut Transform,
+        &mut Weapon,
+        &CharacterController,
+        &Health,
+        &BattleSide,
+        Option<&mut WeaponLoadout>,
+        Option<&Blocking>,
+        Option<&Mount>,
+        Has<Sprinting>,
+        Has<Crouching>,
+        Has<Staggered>,
+        Has<Exhausted>,
+    )>,
+    others: Query<(Entity, &Transform, &Health, &BattleSide), Without<ActionState<CombatAction>>>,
+) {
+    let delta = time.delta_secs();
+
+    for (
+        entity,
+        actions,
+        mut state,
+        mut transform,
+        mut weapon,
+        controller,
+        health,
+        side,
+        loadout,
+        blocking,
+        mount,
+        sprinting,
+        crouching,
+        staggered,
+        exhausted,
+    ) in players.iter_mut() {
+        state.cooldown = (state.cooldown - delta).max(0.0);
+        if health.current <= 0.0 || staggered {
+            state.windup = None;
+            continue;
+        }
+
+        if actions.just_pressed(&CombatAction::ToggleMount) {
+            mount_toggles.send(ToggleMount { rider: entity });
+        }
+
+        // Stance; there is no crouching in the saddle
+        let crouch = actions.pressed(&CombatAction::Crouch) && mount.is_none();
+        let sprint = actions.pressed(&CombatAction::Sprint) && !crouch && !exhausted;
+        match (sprint, sprinting) {
+            (true, false) => { commands.entity(entity).insert(Sprinting); }
+            (false, true) => { commands.entity(entity).remove::<Sprinting>(); }
+            _ => {}
+        }
+        match (crouch, crouching) {
+            (true, false) => { commands.entity(entity).insert(Crouching); }
+            (false, true) => { commands.entity(entity).remove::<Crouching>(); }
+            _ => {}
+        }
+
+        // Movement and turning; a horse sets the pace and how sharply we can turn
+        let rotation_speed = mount.map_or(controller.rotation_speed, |mount| mount.maneuverability);
+        let turn = actions.clamped_value(&CombatAction::Turn);
+        transform.rotate_y(-turn * rotation_speed * delta);
+        let input = actions.clamped_axis_pair(&CombatAction::Move);
+        let forward = Vec3::new(transform.forward().x, 0.0, transform.forward().z).normalize_or_zero();
+        let right = forward.cross(Vec3::Y);
+        let mut speed = mount.map_or(controller.movement_speed, |mount| mount.speed);
+        if sprint {
+            speed *= SPRINT_SPEED_MULTIPLIER;
+        } else if crouch {
+            speed *= CROUCH_SPEED_MULTIPLIER;
+        }
+        transform.translation += (right * input.x + forward * input.y).clamp_length_max(1.0) * speed * delta;
+
+        // Changing weapons abandons whatever swing was coming
+        if actions.just_pressed(&CombatAction::SwitchWeapon) {
+            if let Some(mut loadout) = loadout.filter(|loadout| loadout.slots.len() > 1) {
+                loadout.equipped = (loadout.equipped + 1) % loadout.slots.len();
+                *weapon = loadout.slots[loadout.equipped].clone();
+                state.windup = None;
+            }
+        }
+
+        // Holding a guard takes priority over attacking
+        let guard = CombatAction::BLOCKS
+            .iter()
+            .find(|(action, _)| actions.pressed(action))
+            .map(|(_, direction)| *direction);
+        match (guard, blocking) {
+            (Some(direction), current) if current.map(|blocking| blocking.direction) != Some(direction) => {
+                commands.entity(entity).insert(Blocking { direction, held: 0.0 });
+            }
+            (None, Some(_)) => {
+                commands.entity(entity).remove::<Blocking>();
+            }
+            _ => {}
+        }
+        if guard.is_some() {
+            state.windup = None;
+            continue;
+        }
+
+        let rule = rules.get(weapon.weapon_type);
+        let reach = rule.ranged.as_ref().map_or(weapon.reach + rule.reach_bonus, |ranged| ranged.max_range);
+        let mut swing_speed = weapon.speed.max(0.1);
+        if exhausted {
+            swing_speed *= EXHAUSTED_SWING_SPEED_MULTIPLIER;
+        }
+
+        if actions.just_pressed(&CombatAction::Kick) && state.cooldown <= 0.0 {
+            if let Some(target) = pick_target(&transform, *side, KICK_REACH, &others) {
+                kick_events.send(KickEvent { attacker: entity, target });
+            }
+            state.windup = None;
+            state.cooldown = BASE_SWING_INTERVAL / swing_speed;
+            continue;
+        }
+
+        // Swings wind up while the button is held and land when it is let go
+        match state.windup {
+            None if state.cooldown <= 0.0 => {
+                state.windup = CombatAction::ATTACKS
+                    .iter()
+                    .find(|(action, _)| actions.just_pressed(action))
+                    .map(|(_, direction)| (*direction, 0.0));
+            }
+            None => {}
+            Some((direction, held)) => {
+                let (action, _) = CombatAction::ATTACKS
+                    .iter()
+                    .find(|(_, attack)| *attack == direction)
+                    .expect("every direction has an attack");
+                if actions.pressed(action) {
+                    state.windup = Some((direction, held + delta));
+                    continue;
+                }
+
+                if let Some(target) = pick_target(&transform, *side, reach, &others) {
+                    attack_events.send(AttackEvent {
+                        attacker: entity,
+                        target,
+                        direction,
+                        timing: (held * swing_speed / FULL_WINDUP_TIME).clamp(0.0, 1.0),
+                    });
+                }
+                state.windup = None;
+                state.cooldown = BASE_SWING_INTERVAL / swing_speed;
+            }
+        }
+    }
+}
+
+/// Nearest living enemy in front of the player and within `reach`
+fn pick_target(
+    transform: &Transform,
+    side: BattleSide,
+    reach: f32,
+    others: &Query<(Entity, &Transform, &Health, &BattleSide), Without<ActionState<CombatAction>>>,
+) -> Option<Entity> {
+    let forward = *transform.forward();
+    others
+        .iter()
+        .filter(|(_, _, health, other_side)| health.current > 0.0 && **other_side != side)
+        .filter_map(|(entity, other, ..)| {
+            let offset = (other.translation - transform.translation).with_y(0.0);
+            let distance = offset.length();
+            (distance <= reach && within_arc(forward.with_y(0.0), offset, PLAYER_ATTACK_ARC))
+                .then_some((entity, distance))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)))
+        .map(|(entity, _)| entity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Player, Stamina, WeaponType};
+    use crate::test_support::{combat_test_app, spawn_swordsman, enlist, start_battle, run_test_app};
+
+    // Attacker-side swordsman driven by the default bindings, facing -Z
+    fn spawn_player(app: &mut App, position: Vec3) -> Entity {
+        let player = spawn_swordsman(app, 10, position);
+        enlist(app, player, BattleSide::Attacker, "vlandia");
+        app.world_mut().entity_mut(player).insert((
+            Player,
+            CharacterController { movement_speed: 4.0, rotation_speed: 3.0 },
+            player_input_bundle(),
+        ));
+        player
+    }
+
+    #[test]
+    fn test_player_swing_lands_on_release() {
+        let mut app = combat_test_app();
+        let player = spawn_player(&mut app, Vec3::ZERO);
+        let enemy = spawn_swordsman(&mut app, 10, Vec3::new(0.0, 0.0, -1.0));
+        enlist(&mut app, enemy, BattleSide::Defender, "looters");
+        start_battle(&mut app);
+
+        // Winding up does nothing until the button is let go
+        KeyCode::ArrowRight.press(app.world_mut());
+        run_test_app(&mut app, 30);
+        assert_eq!(app.world().get::<Health>(enemy).unwrap().current, 100.0);
+
+        KeyCode::ArrowRight.release(app.world_mut());
+        run_test_app(&mut app, 2);
+        assert!(app.world().get::<Health>(enemy).unwrap().current < 100.0);
+        assert!(app.world().get::<Stamina>(player).unwrap().current < 100.0);
+    }
+
+    #[test]
+    fn test_player_moves_blocks_and_switches_weapon() {
+        let mut app = combat_test_app();
+        let player = spawn_player(&mut app, Vec3::ZERO);
+        app.world_mut().entity_mut(player).insert(WeaponLoadout {
+            slots: vec![
+                Weapon { damage: 20.0, speed: 1.0, reach: 1.5, weapon_type: WeaponType::OneHandedSword },
+                Weapon { damage: 22.0, speed: 0.9, reach: 2.2, weapon_type: WeaponType::Spear },
+            ],
+            equipped: 0,
+        });
+        start_battle(&mut app);
+
+        KeyCode::KeyW.press(app.world_mut());
+        run_test_app(&mut app, 30);
+        KeyCode::KeyW.release(app.world_mut());
+        assert!(app.world().get::<Transform>(player).unwrap().translation.z < -1.0);
+
+        KeyCode::KeyJ.press(app.world_mut());
+        run_test_app(&mut app, 2);
+        assert_eq!(app.world().get::<Blocking>(player).unwrap().direction, AttackDirection::Left);
+        KeyCode::KeyJ.release(app.world_mut());
+        run_test_app(&mut app, 2);
+        assert!(app.world().get::<Blocking>(player).is_none());
+
+        KeyCode::KeyQ.press(app.world_mut());
+        run_test_app(&mut app, 2);
+        assert_eq!(app.world().get::<Weapon>(player).unwrap().weapon_type, WeaponType::Spear);
+    }
+}
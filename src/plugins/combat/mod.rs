@@ -0,0 +1,770 @@
+use bevy::prelude::*;
+use bevy_egui::EguiUserTextures;
+use leafwing_input_manager::prelude::InputManagerPlugin;
+use crate::core::states::{GameState, CombatState, BattleResult, check_combat_victory};
+use crate::core::components::{
+    Health, Stamina, Weapon, CombatAI, CharacterStats, AttackDirection,
+    WeaponType, Shield, Reload, Ammo, Bracing, Charging, BattleSide, Morale, FormationGroup,
+    Blocking, Staggered, Mount, Armor, KnockedOut, DamageType, BodyPart,
+};
+
+mod weapon_rules;
+mod projectiles;
+mod ai;
+mod morale;
+mod deployment;
+mod orders;
+mod scene;
+mod aftermath;
+mod input;
+mod defence;
+mod mounts;
+mod armor;
+mod animation;
+mod siege;
+mod auto_resolve;
+mod replay;
+mod stats;
+
+pub use weapon_rules::{WeaponRules, WeaponRule};
+pub use ai::CombatantSnapshot;
+pub use deployment::{
+    Formations, DeploymentZones, AssignFormationGroup, SetFormationTemplate, MoveFormation,
+    DeploymentReady,
+};
+pub use orders::{FormationCommand, IssueOrder, SelectedFormation};
+pub use scene::{BattleSetup, BattleParty, BattleLeader, DEFAULT_FIELD_SIZE};
+pub use input::{CombatAction, PlayerCombatState};
+pub use defence::{BlockKind, resolve_block};
+pub use mounts::{ToggleMount, MountKilled};
+pub use animation::CombatAnimation;
+pub use siege::{SiegeBattle, SiegeWalls, GateBreached};
+pub use auto_resolve::{AutoResolvedBattle, auto_resolve};
+pub use replay::{ReplayRecorder, ReplayPlayback, PlayReplay};
+pub use stats::BattleStats;
+pub use aftermath::{BattleAftermath, AftermathConfirmed};
+
+/// Flat stamina spent on every swing regardless of weapon
+pub const BASE_SWING_STAMINA_COST: f32 = 5.0;
+/// Extra stamina per point of weapon damage
+pub const SWING_STAMINA_PER_DAMAGE: f32 = 0.25;
+/// Damage multiplier gained per point of strength
+pub const STRENGTH_DAMAGE_BONUS: f32 = 0.02;
+/// Fraction of full damage dealt by an uncharged (timing 0.0) swing
+pub const MIN_SWING_TIMING_FACTOR: f32 = 0.5;
+/// A kick ignores shields but barely hurts
+pub const KICK_DAMAGE: f32 = 5.0;
+pub const KICK_STAMINA_COST: f32 = 8.0;
+pub const KICK_REACH: f32 = 1.2;
+/// How far a kick shoves its target back
+pub const KICK_KNOCKBACK: f32 = 1.0;
+
+pub struct CombatPlugin;
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            // Register the combat substate
+            .add_sub_state::<CombatState>()
+            
+            // Player controls for their avatar on the field
+            .add_plugins(InputManagerPlugin::<CombatAction>::default())
+            
+            // Register combat-specific components
+            .register_type::<Health>()
+            .register_type::<Stamina>()
+            .register_type::<Weapon>()
+            .register_type::<CombatAI>()
+            .register_type::<Shield>()
+            .register_type::<Reload>()
+            .register_type::<Ammo>()
+            .register_type::<BattleSide>()
+            .register_type::<Morale>()
+            .register_type::<FormationGroup>()
+            .register_type::<Blocking>()
+            .register_type::<Staggered>()
+            .register_type::<Mount>()
+            
+            // Designer-tuned weapon behaviour
+            .init_resource::<WeaponRules>()
+            .init_resource::<DeploymentZones>()
+            .init_resource::<Formations>()
+            .init_resource::<SelectedFormation>()
+            
+            // Combat events
+            .add_event::<AttackEvent>()
+            .add_event::<KickEvent>()
+            .add_event::<ToggleMount>()
+            .add_event::<MountKilled>()
+            .add_event::<DamageApplied>()
+            .add_event::<AttackBlocked>()
+            .add_event::<FireProjectile>()
+            .add_event::<DamageTaken>()
+            .add_event::<ProjectileHit>()
+            .add_event::<UnitDied>()
+            .add_event::<BattleResult>()
+            .add_event::<AssignFormationGroup>()
+            .add_event::<SetFormationTemplate>()
+            .add_event::<MoveFormation>()
+            .add_event::<DeploymentReady>()
+            .add_event::<IssueOrder>()
+            .add_event::<AftermathConfirmed>()
+            .add_event::<GateBreached>()
+            .add_event::<PlayReplay>()
+            
+            // Systems that run only in Combat state
+            .add_systems(
+                Update, 
+                (
+                    (
+                        (tick_reloads, defence::tick_guards, projectiles::expire_projectiles),
+                        (mounts::track_momentum, mounts::handle_mount_toggles),
+                        (process_attacks, process_kicks, mounts::charge_impacts, projectiles::handle_projectile_hits),
+                        defence::handle_blocks,
+                        (handle_damage, handle_shield_hits, projectiles::spawn_projectiles),
+                    ).chain(),
+                    (morale::update_morale, morale::flee_routing_units)
+                        .chain()
+                        .after(handle_damage),
+                )
+                .run_if(in_state(GameState::Combat))
+            )
+            .add_systems(Startup, replay::play_requested_replay)
+            .add_systems(Update, replay::start_replay.run_if(not(in_state(GameState::Combat))))
+            
+            // Systems for different combat substates
+            .add_systems(
+                Update,
+                (
+                    replay::skip_deployment
+                        .before(deployment::deploy_troops)
+                        .run_if(resource_exists::<ReplayPlayback>),
+                    deployment::deploy_troops,
+                )
+                    .run_if(in_state(CombatState::Preparation))
+            )
+            .add_systems(
+                Update,
+                (
+                    input::process_combat_input.before(mounts::track_momentum),
+                    animation::update_combat_animations
+                        .after(input::process_combat_input)
+                        .after(ai::update_combat_ai)
+                        .before(mounts::track_momentum),
+                    (
+                        orders::handle_order_input
+                            .run_if(resource_exists::<ButtonInput<KeyCode>>.and(not(resource_exists::<ReplayPlayback>))),
+                        orders::apply_orders,
+                        orders::update_formation_directives,
+                        ai::update_combat_ai,
+                    )
+                        .chain()
+                        .before(mounts::track_momentum),
+                    (check_combat_victory, aftermath::prepare_aftermath)
+                        .chain()
+                        .after(morale::update_morale),
+                )
+                .run_if(in_state(CombatState::Active))
+            )
+            .add_systems(
+                Update,
+                (
+                    (siege::advance_siege_engines, siege::enforce_walls)
+                        .chain()
+                        .after(input::process_combat_input)
+                        .after(ai::update_combat_ai)
+                        .before(mounts::track_momentum)
+                        .run_if(resource_exists::<SiegeWalls>),
+                    siege::record_siege_result
+                        .after(check_combat_victory)
+                        .run_if(resource_exists::<SiegeBattle>),
+                )
+                .run_if(in_state(CombatState::Active))
+            )
+            .add_systems(
+                Update,
+                (
+                    stats::collect_battle_stats
+                        .after(handle_damage)
+                        .run_if(resource_exists::<BattleStats>),
+                    replay::play_back_frame
+                        .before(input::process_combat_input)
+                        .before(orders::handle_order_input)
+                        .run_if(resource_exists::<ReplayPlayback>),
+                    replay::record_frame
+                        .after(input::process_combat_input)
+                        .after(ai::update_combat_ai)
+                        .run_if(resource_exists::<ReplayRecorder>),
+                )
+                .run_if(in_state(CombatState::Active))
+            )
+            .add_systems(
+                Update,
+                aftermath::handle_victory_screen
+                    .run_if(in_state(CombatState::Victory).and(resource_exists::<EguiUserTextures>))
+            )
+            .add_systems(
+                Update,
+                aftermath::handle_defeat_screen
+                    .run_if(in_state(CombatState::Defeat).and(resource_exists::<EguiUserTextures>))
+            )
+            .add_systems(
+                Update,
+                (
+                    aftermath::apply_battle_aftermath.run_if(not(resource_exists::<ReplayPlayback>)),
+                    replay::finish_playback.run_if(resource_exists::<ReplayPlayback>),
+                    siege::apply_siege_outcome.run_if(resource_exists::<SiegeBattle>),
+                )
+                    .run_if(in_state(CombatState::Victory).or(in_state(CombatState::Defeat)))
+            )
+            
+            // Systems for entering/exiting combat
+            .add_systems(
+                OnEnter(GameState::Combat),
+                (
+                    scene::setup_combat_scene.run_if(not(resource_exists::<AutoResolvedBattle>)),
+                    auto_resolve::present_auto_resolved.run_if(resource_exists::<AutoResolvedBattle>),
+                    siege::setup_fortifications
+                        .run_if(resource_exists::<SiegeBattle>.and(resource_exists::<BattleSetup>)),
+                    replay::begin_playback.run_if(resource_exists::<ReplayPlayback>),
+                    replay::begin_recording.run_if(
+                        resource_exists::<BattleSetup>
+                            .and(not(resource_exists::<AutoResolvedBattle>))
+                            .and(not(resource_exists::<ReplayPlayback>)),
+                    ),
+                )
+                    .chain()
+            )
+            .add_systems(
+                OnEnter(CombatState::Active),
+                (
+                    siege::man_ramparts.run_if(resource_exists::<SiegeWalls>),
+                    replay::record_deployment.run_if(resource_exists::<ReplayRecorder>),
+                    replay::restore_deployment.run_if(resource_exists::<ReplayPlayback>),
+                    stats::start_battle_stats,
+                )
+                    .chain()
+            )
+            .add_systems(
+                OnEnter(CombatState::Victory),
+                (
+                    replay::save_recording.run_if(resource_exists::<ReplayRecorder>),
+                    stats::compile_battle_report.run_if(resource_exists::<BattleStats>),
+                )
+            )
+            .add_systems(
+                OnEnter(CombatState::Defeat),
+                (
+                    replay::save_recording.run_if(resource_exists::<ReplayRecorder>),
+                    stats::compile_battle_report.run_if(resource_exists::<BattleStats>),
+                )
+            )
+            // Everything spawned for the battle is `StateScoped` and despawned by Bevy on exit
+            .add_systems(
+                OnExit(GameState::Combat),
+                (
+                    deployment::reset_formations,
+                    siege::clear_siege,
+                    auto_resolve::clear_auto_resolved,
+                    replay::clear_replay,
+                    stats::clear_battle_stats,
+                )
+            );
+    }
+}
+
+// Combat events
+/// A combatant commits to a melee swing against a target
+#[derive(Event, Debug, Clone)]
+pub struct AttackEvent {
+    pub attacker: Entity,
+    pub target: Entity,
+    pub direction: AttackDirection,
+    /// How far through the wind-up the swing was released, 0.0 (instant) to 1.0 (fully charged)
+    pub timing: f32,
+}
+
+/// A kick aimed at a target in front of the kicker
+#[derive(Event, Debug, Clone)]
+pub struct KickEvent {
+    pub attacker: Entity,
+    pub target: Entity,
+}
+
+/// A resolved hit, consumed by `handle_damage` to reduce the target's health
+#[derive(Event, Debug, Clone)]
+pub struct DamageApplied {
+    pub attacker: Entity,
+    pub target: Entity,
+    pub direction: AttackDirection,
+    pub amount: f32,
+    pub damage_type: DamageType,
+    /// Where the hit landed, which decides the armor it has to get through
+    pub location: BodyPart,
+}
+
+/// Health a hit actually took off its target, after armor and the horse's share
+#[derive(Event, Debug, Clone)]
+pub struct DamageTaken {
+    pub attacker: Entity,
+    pub target: Entity,
+    pub amount: f32,
+}
+
+/// A combatant's health just reached zero
+#[derive(Event, Debug, Clone)]
+pub struct UnitDied {
+    pub entity: Entity,
+    pub killer: Entity,
+}
+
+/// A hit stopped by the target's shield, guard or counter-swing
+#[derive(Event, Debug, Clone)]
+pub struct AttackBlocked {
+    pub attacker: Entity,
+    pub target: Entity,
+    pub direction: AttackDirection,
+    pub kind: BlockKind,
+    /// Damage the hit would have dealt
+    pub damage: f32,
+    pub shield_damage: f32,
+}
+
+/// A bow or crossbow loosed a shot at a target
+#[derive(Event, Debug, Clone)]
+pub struct FireProjectile {
+    pub shooter: Entity,
+    pub target: Entity,
+    pub damage: f32,
+    pub speed: f32,
+}
+
+/// A shot reached the combatant it struck, whether or not a shield caught it
+#[derive(Event, Debug, Clone)]
+pub struct ProjectileHit {
+    pub shooter: Entity,
+    pub target: Entity,
+}
+
+/// Damage dealt by a melee swing, before any defence is applied
+pub fn melee_damage(weapon: &Weapon, stats: Option<&CharacterStats>, timing: f32) -> f32 {
+    let strength = stats.map_or(0.0, |stats| stats.strength as f32);
+    let strength_factor = 1.0 + strength * STRENGTH_DAMAGE_BONUS;
+    // Faster weapons carry more momentum; a speed of 1.0 is neutral
+    let speed_factor = 0.5 + 0.5 * weapon.speed.max(0.0);
+    let timing_factor = MIN_SWING_TIMING_FACTOR + (1.0 - MIN_SWING_TIMING_FACTOR) * timing.clamp(0.0, 1.0);
+    
+    weapon.damage * strength_factor * speed_factor * timing_factor
+}
+
+/// Damage carried by a projectile fired from the given weapon
+pub fn ranged_damage(weapon: &Weapon, rule: &WeaponRule, stats: Option<&CharacterStats>) -> f32 {
+    let strength = stats.map_or(0.0, |stats| stats.strength as f32);
+    let scaling = rule.ranged.as_ref().map_or(0.0, |ranged| ranged.strength_scaling);
+    let strength_factor = 1.0 + strength * STRENGTH_DAMAGE_BONUS * scaling;
+    
+    weapon.damage * rule.damage_multiplier * strength_factor
+}
+
+/// Stamina spent by a single swing of the given weapon
+pub fn swing_stamina_cost(weapon: &Weapon) -> f32 {
+    BASE_SWING_STAMINA_COST + weapon.damage * SWING_STAMINA_PER_DAMAGE
+}
+
+// Combat systems
+fn tick_reloads(time: Res<Time>, mut query: Query<&mut Reload>) {
+    for mut reload in query.iter_mut() {
+        reload.remaining = (reload.remaining - time.delta_secs()).max(0.0);
+    }
+}
+
+fn process_attacks(
+    mut commands: Commands,
+    rules: Res<WeaponRules>,
+    mut attack_events: EventReader<AttackEvent>,
+    mut damage_events: EventWriter<DamageApplied>,
+    mut blocked_events: EventWriter<AttackBlocked>,
+    mut fire_events: EventWriter<FireProjectile>,
+    mut attackers: Query<(
+        &Weapon,
+        &mut Stamina,
+        &Health,
+        Option<&CharacterStats>,
+        Option<&Transform>,
+        Option<&Reload>,
+        Option<&mut Ammo>,
+        Has<Bracing>,
+        Has<Staggered>,
+        Option<&mut CombatAnimation>,
+    )>,
+    targets: Query<(
+        Entity,
+        &Health,
+        Option<&Transform>,
+        Option<&Shield>,
+        Has<Charging>,
+        Option<&Blocking>,
+        Option<&PlayerCombatState>,
+    )>,
+) {
+    let shield_rule = rules.get(WeaponType::Shield);
+    
+    for attack in attack_events.read() {
+        let Ok((weapon, mut stamina, attacker_health, stats, attacker_transform, reload, ammo, bracing, staggered, animation)) =
+            attackers.get_mut(attack.attacker)
+        else {
+            continue;
+        };
+        // Animated combatants wind up first; their swing lands only once it reaches release
+        if let Some(mut animation) = animation {
+            if !animation.take_release(attack) {
+                continue;
+            }
+        }
+        let Ok((_, target_health, target_transform, target_shield, target_charging, target_guard, target_player)) =
+            targets.get(attack.target)
+        else {
+            continue;
+        };
+        
+        // The dead don't swing and can't be hit again; staggered units can't swing either
+        if attacker_health.current <= 0.0 || target_health.current <= 0.0 || staggered {
+            continue;
+        }
+        
+        // Too tired to swing
+        let rule = rules.get(weapon.weapon_type);
+        let cost = swing_stamina_cost(weapon) * rule.stamina_multiplier;
+        if stamina.current < cost {
+            continue;
+        }
+        
+        // Bows and crossbows loose a projectile and then have to reload
+        if let Some(ranged) = &rule.ranged {
+            if reload.is_some_and(|reload| reload.remaining > 0.0) {
+                continue;
+            }
+            if let (Some(from), Some(to)) = (attacker_transform, target_transform) {
+                if from.translation.distance(to.translation) > ranged.max_range {
+                    continue;
+                }
+            }
+            // Units without an `Ammo` component never run dry
+            if let Some(mut ammo) = ammo {
+                if ammo.current == 0 {
+                    continue;
+                }
+                ammo.current -= 1;
+            }
+            stamina.current -= cost;
+            commands.entity(attack.attacker).insert(Reload { remaining: ranged.reload_time });
+            fire_events.send(FireProjectile {
+                shooter: attack.attacker,
+                target: attack.target,
+                damage: ranged_damage(weapon, rule, stats),
+                speed: ranged.projectile_speed,
+            });
+            continue;
+        }
+        stamina.current -= cost;
+        
+        // A swing that doesn't reach still costs stamina
+        let reach = weapon.reach + rule.reach_bonus;
+        if let (Some(from), Some(to)) = (attacker_transform, target_transform) {
+            if from.translation.distance(to.translation) > reach {
+                continue;
+            }
+        }
+        
+        let mut damage = melee_damage(weapon, stats, attack.timing) * rule.damage_multiplier;
+        if bracing && target_charging {
+            damage *= rule.brace_multiplier;
+        }
+        let attacker_position = attacker_transform.map(|transform| transform.translation);
+        let damage_type = weapon.weapon_type.damage_type(attack.direction);
+        let block = resolve_block(
+            attack.direction,
+            target_guard,
+            target_player.and_then(|player| player.windup),
+            shield_blocks(shield_rule, target_shield, target_transform, attacker_position),
+            facing(target_transform, attacker_position),
+        );
+        resolve_melee_hit(
+            attack,
+            attack.target,
+            damage,
+            damage_type,
+            block,
+            shield_rule,
+            &mut damage_events,
+            &mut blocked_events,
+        );
+        
+        // Heavy weapons carry through to enemies standing in the swing arc
+        if rule.cleave_targets == 0 {
+            continue;
+        }
+        let (Some(from), Some(to)) = (attacker_transform, target_transform) else {
+            continue;
+        };
+        let swing_forward = to.translation - from.translation;
+        let mut cleaved: Vec<_> = targets
+            .iter()
+            .filter(|(entity, health, ..)| {
+                *entity != attack.attacker && *entity != attack.target && health.current > 0.0
+            })
+            .filter_map(|(entity, _, transform, shield, _, guard, player)| {
+                let offset = transform?.translation - from.translation;
+                (offset.length() <= reach && within_arc(swing_forward, offset, rule.cleave_arc))
+                    .then_some((entity, offset.length(), transform, shield, guard, player))
+            })
+            .collect();
+        cleaved.sort_by(|a, b| a.1.total_cmp(&b.1));
+        
+        for (entity, _, transform, shield, guard, player) in cleaved.into_iter().take(rule.cleave_targets as usize) {
+            damage *= rule.cleave_falloff;
+            let block = resolve_block(
+                attack.direction,
+                guard,
+                player.and_then(|player| player.windup),
+                shield_blocks(shield_rule, shield, transform, attacker_position),
+                facing(transform, attacker_position),
+            );
+            resolve_melee_hit(attack, entity, damage, damage_type, block, shield_rule, &mut damage_events, &mut blocked_events);
+        }
+    }
+}
+
+/// Kicks shove the target back, bypass shields and knock spearmen out of their brace
+fn process_kicks(
+    mut commands: Commands,
+    mut kick_events: EventReader<KickEvent>,
+    mut damage_events: EventWriter<DamageApplied>,
+    mut combatants: Query<(&mut Transform, &Health, Option<&mut Stamina>)>,
+) {
+    for kick in kick_events.read() {
+        let Ok([(attacker_transform, attacker_health, attacker_stamina), (mut target_transform, target_health, _)]) =
+            combatants.get_many_mut([kick.attacker, kick.target])
+        else {
+            continue;
+        };
+        if attacker_health.current <= 0.0 || target_health.current <= 0.0 {
+            continue;
+        }
+        let Some(mut stamina) = attacker_stamina.filter(|stamina| stamina.current >= KICK_STAMINA_COST) else {
+            continue;
+        };
+        stamina.current -= KICK_STAMINA_COST;
+
+        let offset = (target_transform.translation - attacker_transform.translation).with_y(0.0);
+        if offset.length() > KICK_REACH {
+            continue;
+        }
+        target_transform.translation += offset.normalize_or_zero() * KICK_KNOCKBACK;
+        commands.entity(kick.target).remove::<Bracing>();
+        damage_events.send(DamageApplied {
+            attacker: kick.attacker,
+            target: kick.target,
+            direction: AttackDirection::Thrust,
+            amount: KICK_DAMAGE,
+            damage_type: DamageType::Blunt,
+            location: BodyPart::Body,
+        });
+    }
+}
+
+/// Whether `offset` lies inside an arc of `arc_degrees` centred on `forward`
+fn within_arc(forward: Vec3, offset: Vec3, arc_degrees: f32) -> bool {
+    if forward.length_squared() == 0.0 || offset.length_squared() == 0.0 {
+        return true;
+    }
+    forward.angle_between(offset).to_degrees() <= arc_degrees / 2.0
+}
+
+/// A shield stops the hit if it is intact and facing the attacker
+fn shield_blocks(
+    shield_rule: &WeaponRule,
+    shield: Option<&Shield>,
+    defender: Option<&Transform>,
+    attacker_position: Option<Vec3>,
+) -> bool {
+    let (Some(shield), Some(defender), Some(attacker_position)) = (shield, defender, attacker_position) else {
+        return false;
+    };
+    shield.durability > 0.0
+        && shield_rule.block_arc > 0.0
+        && within_arc(*defender.forward(), attacker_position - defender.translation, shield_rule.block_arc)
+}
+
+/// Whether the defender faces the attacker closely enough to guard against them
+fn facing(defender: Option<&Transform>, attacker_position: Option<Vec3>) -> bool {
+    let (Some(defender), Some(attacker_position)) = (defender, attacker_position) else {
+        return true;
+    };
+    within_arc(*defender.forward(), attacker_position - defender.translation, defence::WEAPON_BLOCK_ARC)
+}
+
+fn resolve_melee_hit(
+    attack: &AttackEvent,
+    target: Entity,
+    damage: f32,
+    damage_type: DamageType,
+    block: Option<BlockKind>,
+    shield_rule: &WeaponRule,
+    damage_events: &mut EventWriter<DamageApplied>,
+    blocked_events: &mut EventWriter<AttackBlocked>,
+) {
+    if let Some(kind) = block {
+        let shield_damage = match kind {
+            BlockKind::Shield => damage * shield_rule.durability_damage_multiplier,
+            _ => 0.0,
+        };
+        blocked_events.send(AttackBlocked {
+            attacker: attack.attacker,
+            target,
+            direction: attack.direction,
+            kind,
+            damage,
+            shield_damage,
+        });
+    } else {
+        damage_events.send(DamageApplied {
+            attacker: attack.attacker,
+            target,
+            direction: attack.direction,
+            amount: damage,
+            damage_type,
+            location: armor::melee_hit_location(attack.direction),
+        });
+    }
+}
+
+fn handle_damage(
+    mut commands: Commands,
+    mut damage_events: EventReader<DamageApplied>,
+    mut taken_events: EventWriter<DamageTaken>,
+    mut death_events: EventWriter<UnitDied>,
+    mut mount_deaths: EventWriter<MountKilled>,
+    mut query: Query<(&mut Health, Option<&mut Mount>, Option<&Armor>)>,
+) {
+    for event in damage_events.read() {
+        if let Ok((mut health, mount, armor)) = query.get_mut(event.target) {
+            let mut amount = event.amount;
+            // Riders share hits with their horse; if it goes down they are thrown
+            if let Some(mut mount) = mount.filter(|mount| mount.health > 0.0) {
+                let to_mount = amount * mounts::MOUNT_HIT_SHARE;
+                amount -= to_mount;
+                mount.health = (mount.health - to_mount).max(0.0);
+                if mount.health <= 0.0 {
+                    amount += mounts::FALL_DAMAGE;
+                    commands
+                        .entity(event.target)
+                        .remove::<(Mount, Charging)>()
+                        .insert(Staggered { remaining: mounts::FALL_STAGGER_TIME });
+                    mount_deaths.send(MountKilled { rider: event.target });
+                }
+            }
+            if let Some(armor) = armor {
+                amount = armor::armored_damage(amount, event.damage_type, armor.protection(event.location));
+            }
+
+            let was_alive = health.current > 0.0;
+            let before = health.current;
+            health.current = (health.current - amount).max(0.0);
+            if before > health.current {
+                taken_events.send(DamageTaken {
+                    attacker: event.attacker,
+                    target: event.target,
+                    amount: before - health.current,
+                });
+            }
+            if was_alive && health.current <= 0.0 {
+                // Crushing blows leave the victim senseless rather than dead
+                if event.damage_type == DamageType::Blunt {
+                    commands.entity(event.target).insert(KnockedOut);
+                }
+                death_events.send(UnitDied {
+                    entity: event.target,
+                    killer: event.attacker,
+                });
+            }
+        }
+    }
+}
+
+fn handle_shield_hits(
+    mut blocked_events: EventReader<AttackBlocked>,
+    mut query: Query<&mut Shield>,
+) {
+    for event in blocked_events.read() {
+        if let Ok(mut shield) = query.get_mut(event.target) {
+            shield.durability = (shield.durability - event.shield_damage).max(0.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{combat_test_app, spawn_swordsman, run_test_app};
+
+    #[test]
+    fn test_attack_applies_damage_and_stamina_cost() {
+        let mut app = combat_test_app();
+        let attacker = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+        let target = spawn_swordsman(&mut app, 10, Vec3::new(1.0, 0.0, 0.0));
+
+        app.world_mut().send_event(AttackEvent {
+            attacker,
+            target,
+            direction: AttackDirection::Overhead,
+            timing: 1.0,
+        });
+        run_test_app(&mut app, 1);
+
+        // 20 damage * (1 + 10 * 0.02) strength * 1.0 speed * 1.0 timing
+        let health = app.world().get::<Health>(target).unwrap();
+        assert!((health.current - 76.0).abs() < 1e-4);
+
+        // 5 base + 20 * 0.25
+        let stamina = app.world().get::<Stamina>(attacker).unwrap();
+        assert!((stamina.current - 90.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_attack_out_of_reach_misses() {
+        let mut app = combat_test_app();
+        let attacker = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+        let target = spawn_swordsman(&mut app, 10, Vec3::new(5.0, 0.0, 0.0));
+
+        app.world_mut().send_event(AttackEvent {
+            attacker,
+            target,
+            direction: AttackDirection::Left,
+            timing: 1.0,
+        });
+        run_test_app(&mut app, 1);
+
+        assert_eq!(app.world().get::<Health>(target).unwrap().current, 100.0);
+        assert!(app.world().get::<Stamina>(attacker).unwrap().current < 100.0);
+    }
+
+    #[test]
+    fn test_exhausted_attacker_cannot_swing() {
+        let mut app = combat_test_app();
+        let attacker = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+        let target = spawn_swordsman(&mut app, 10, Vec3::new(1.0, 0.0, 0.0));
+        app.world_mut().get_mut::<Stamina>(attacker).unwrap().current = 1.0;
+
+        app.world_mut().send_event(AttackEvent {
+            attacker,
+            target,
+            direction: AttackDirection::Thrust,
+            timing: 0.5,
+        });
+        run_test_app(&mut app, 1);
+
+        assert_eq!(app.world().get::<Health>(target).unwrap().current, 100.0);
+        assert_eq!(app.world().get::<Stamina>(attacker).unwrap().current, 1.0);
+    }
+}
@@ -0,0 +1,180 @@
+use bevy::prelude::*;
+
+use crate::core::components::{Health, Morale, BattleSide, Routing, CharacterController, Player};
+
+use super::UnitDied;
+use super::ai::DEFAULT_AI_MOVE_SPEED;
+
+/// Morale every ally loses when someone on their side falls
+pub const ALLY_DEATH_MORALE_LOSS: f32 = 2.0;
+/// Extra morale lost by allies who saw it happen up close
+pub const NEARBY_DEATH_MORALE_LOSS: f32 = 8.0;
+pub const NEARBY_DEATH_RADIUS: f32 = 10.0;
+/// Morale regained by the other side for every enemy that falls
+pub const ENEMY_DEATH_MORALE_GAIN: f32 = 1.0;
+/// Units at or below this morale break and run
+pub const ROUT_MORALE: f32 = 0.0;
+/// Routing units past this distance from the centre have left the field
+pub const BATTLEFIELD_RADIUS: f32 = 150.0;
+/// Panic lends speed to fleeing units
+pub const ROUT_SPEED_MULTIPLIER: f32 = 1.25;
+
+/// Troops lose heart as their side falls around them. The player's nerve is
+/// their own business, so only troops ever rout.
+pub(super) fn update_morale(
+    mut commands: Commands,
+    mut deaths: EventReader<UnitDied>,
+    fallen: Query<(&BattleSide, &Transform)>,
+    mut units: Query<(Entity, &mut Morale, &BattleSide, &Transform, &Health), (Without<Routing>, Without<Player>)>,
+) {
+    for death in deaths.read() {
+        let Ok((fallen_side, fallen_transform)) = fallen.get(death.entity) else {
+            continue;
+        };
+
+        for (entity, mut morale, side, transform, health) in units.iter_mut() {
+            if entity == death.entity || health.current <= 0.0 {
+                continue;
+            }
+            if side == fallen_side {
+                let mut loss = ALLY_DEATH_MORALE_LOSS;
+                if transform.translation.distance(fallen_transform.translation) <= NEARBY_DEATH_RADIUS {
+                    loss += NEARBY_DEATH_MORALE_LOSS;
+                }
+                morale.current -= loss;
+            } else {
+                morale.current = (morale.current + ENEMY_DEATH_MORALE_GAIN).min(morale.max);
+            }
+        }
+    }
+
+    for (entity, morale, _, _, health) in units.iter() {
+        if health.current > 0.0 && morale.current <= ROUT_MORALE {
+            commands.entity(entity).insert(Routing);
+        }
+    }
+}
+
+pub(super) fn flee_routing_units(
+    time: Res<Time>,
+    mut routing: Query<
+        (&mut Transform, &Health, &BattleSide, Option<&CharacterController>),
+        (With<Routing>, Without<Player>),
+    >,
+    standing: Query<(&Transform, &Health, &BattleSide), Without<Routing>>,
+) {
+    for (mut transform, health, side, controller) in routing.iter_mut() {
+        if health.current <= 0.0 || transform.translation.length() >= BATTLEFIELD_RADIUS {
+            continue;
+        }
+
+        // Run directly away from the enemy's centre of mass, or outwards if there is none
+        let (sum, count) = standing
+            .iter()
+            .filter(|(_, health, enemy_side)| health.current > 0.0 && **enemy_side != *side)
+            .fold((Vec3::ZERO, 0), |(sum, count), (enemy, ..)| (sum + enemy.translation, count + 1));
+        let threat = if count > 0 { sum / count as f32 } else { Vec3::ZERO };
+        let mut away = transform.translation - threat;
+        away.y = 0.0;
+        let direction = away.try_normalize().unwrap_or(Vec3::X);
+
+        let speed = controller.map_or(DEFAULT_AI_MOVE_SPEED, |controller| controller.movement_speed);
+        transform.translation += direction * speed * ROUT_SPEED_MULTIPLIER * time.delta_secs();
+        let look_target = transform.translation + direction;
+        transform.look_at(look_target, Vec3::Y);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Player, CombatState, BattleResult};
+    use crate::test_support::{combat_test_app, spawn_swordsman, enlist, kill, start_battle, run_test_app};
+
+    #[test]
+    fn test_allies_rout_after_nearby_death() {
+        let mut app = combat_test_app();
+        let enemy = spawn_swordsman(&mut app, 10, Vec3::new(0.0, 0.0, -5.0));
+        enlist(&mut app, enemy, BattleSide::Defender, "sturgia");
+        let victim = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+        enlist(&mut app, victim, BattleSide::Attacker, "vlandia");
+        let witness = spawn_swordsman(&mut app, 10, Vec3::new(2.0, 0.0, 0.0));
+        enlist(&mut app, witness, BattleSide::Attacker, "vlandia");
+
+        kill(&mut app, enemy, victim);
+        run_test_app(&mut app, 2);
+
+        assert!(app.world().get::<Routing>(witness).is_some());
+        assert!(app.world().get::<Morale>(enemy).unwrap().current > 10.0);
+
+        // Fleeing carries them away from the enemy
+        let before = app.world().get::<Transform>(witness).unwrap().translation;
+        run_test_app(&mut app, 30);
+        let after = app.world().get::<Transform>(witness).unwrap().translation;
+        assert!(after.distance(Vec3::new(0.0, 0.0, -5.0)) > before.distance(Vec3::new(0.0, 0.0, -5.0)));
+    }
+
+    #[test]
+    fn test_victory_publishes_battle_result() {
+        let mut app = combat_test_app();
+        let player = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+        enlist(&mut app, player, BattleSide::Attacker, "player");
+        app.world_mut().entity_mut(player).insert(Player);
+        let enemy = spawn_swordsman(&mut app, 10, Vec3::new(0.0, 0.0, -30.0));
+        enlist(&mut app, enemy, BattleSide::Defender, "looters");
+        start_battle(&mut app);
+
+        kill(&mut app, player, enemy);
+        run_test_app(&mut app, 2);
+
+        assert_eq!(app.world().resource::<State<CombatState>>().get(), &CombatState::Victory);
+        let results = app.world().resource::<Events<BattleResult>>();
+        let mut cursor = results.get_cursor();
+        let result = cursor.read(results).next().expect("battle result sent");
+        assert_eq!(result.winner, BattleSide::Attacker);
+        assert_eq!(result.casualties.get("looters"), Some(&1));
+    }
+
+    #[test]
+    fn test_routed_player_side_is_defeated() {
+        let mut app = combat_test_app();
+        let player = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+        enlist(&mut app, player, BattleSide::Defender, "player");
+        app.world_mut().entity_mut(player).insert(Player);
+        let troop = spawn_swordsman(&mut app, 10, Vec3::new(2.0, 0.0, 0.0));
+        enlist(&mut app, troop, BattleSide::Defender, "player");
+        let enemy = spawn_swordsman(&mut app, 10, Vec3::new(0.0, 0.0, -30.0));
+        enlist(&mut app, enemy, BattleSide::Attacker, "looters");
+        start_battle(&mut app);
+
+        // Seeing the player cut down breaks the troop beside them, and with
+        // nobody left to hold the field the battle is lost
+        kill(&mut app, enemy, player);
+        run_test_app(&mut app, 2);
+
+        assert!(app.world().get::<Routing>(troop).is_some());
+        assert_eq!(app.world().resource::<State<CombatState>>().get(), &CombatState::Defeat);
+    }
+
+    #[test]
+    fn test_player_never_routs() {
+        let mut app = combat_test_app();
+        let enemy = spawn_swordsman(&mut app, 10, Vec3::new(0.0, 0.0, -5.0));
+        enlist(&mut app, enemy, BattleSide::Defender, "sturgia");
+        let victim = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+        enlist(&mut app, victim, BattleSide::Attacker, "vlandia");
+        let player = spawn_swordsman(&mut app, 10, Vec3::new(2.0, 0.0, 0.0));
+        enlist(&mut app, player, BattleSide::Attacker, "vlandia");
+        app.world_mut().entity_mut(player).insert(Player);
+        let start = app.world().get::<Transform>(player).unwrap().translation;
+
+        kill(&mut app, enemy, victim);
+        run_test_app(&mut app, 2);
+        assert!(app.world().get::<Routing>(player).is_none());
+
+        // Even shaken into a rout, the player stays in control of their feet
+        app.world_mut().entity_mut(player).insert(Routing);
+        run_test_app(&mut app, 10);
+        assert_eq!(app.world().get::<Transform>(player).unwrap().translation, start);
+    }
+}
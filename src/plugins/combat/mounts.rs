@@ -0,0 +1,437 @@
+use bevy::prelude::*;
+
+use crate::core::components::{
+    Health, Weapon, WeaponType, Mount, BattleSide, Charging, Staggered, AttackDirection,
+    DamageType, BodyPart,
+};
+use crate::core::states::GameState;
+
+use super::{WeaponRules, DamageApplied, within_arc};
+use super::ai::{AiDecision, CombatantSnapshot, POSITION_TOLERANCE};
+
+/// Speed a rider has to reach before the charge counts: lances couch and horses trample
+pub const CHARGE_MIN_SPEED: f32 = 6.0;
+/// Closing speed at which a lance or trample deals its listed damage
+pub const CHARGE_REFERENCE_SPEED: f32 = 10.0;
+/// Riders need this long to line up another lance strike or trample
+pub const CHARGE_RECOVERY_TIME: f32 = 1.5;
+/// Width in degrees of the cone in front of a rider that a charge hits
+pub const CHARGE_ARC: f32 = 60.0;
+/// How close a horse has to get to ride someone down
+pub const TRAMPLE_RADIUS: f32 = 1.2;
+pub const TRAMPLE_STAGGER_TIME: f32 = 0.8;
+/// Share of a hit on a rider that lands on the horse instead
+pub const MOUNT_HIT_SHARE: f32 = 0.5;
+/// Damage a rider takes from being thrown when the horse goes down
+pub const FALL_DAMAGE: f32 = 10.0;
+pub const FALL_STAGGER_TIME: f32 = 1.5;
+/// How close a riderless horse has to be to climb on
+pub const MOUNT_REACH: f32 = 2.0;
+/// How far past the enemy a charging rider aims, so the charge carries through
+pub const CAVALRY_OVERSHOOT: f32 = 8.0;
+/// Distance at which a rider counts as having ridden through the enemy
+pub const CAVALRY_PASS_DISTANCE: f32 = 2.5;
+/// How far cavalry ride out before wheeling round for another charge
+pub const CAVALRY_RUNUP_DISTANCE: f32 = 25.0;
+/// Riders slow down within this distance of where they are heading
+pub const RIDER_BRAKING_DISTANCE: f32 = 5.0;
+
+/// Where in its charge cycle a cavalry unit is
+#[derive(Component, Debug, Clone, Copy, PartialEq, Default)]
+pub enum CavalryPhase {
+    /// Ride through the nearest enemy
+    #[default]
+    Charge,
+    /// Ride out to `rally` to line up the next charge
+    Wheel { rally: Vec3 },
+}
+
+/// Velocity measured from how far a combatant moved last frame
+#[derive(Component, Debug, Clone, Default)]
+pub struct Momentum {
+    pub velocity: Vec3,
+    last_position: Option<Vec3>,
+}
+
+impl Momentum {
+    /// Standing still at `position`
+    pub fn resting_at(position: Vec3) -> Self {
+        Self {
+            velocity: Vec3::ZERO,
+            last_position: Some(position),
+        }
+    }
+}
+
+/// Time until a rider can land another charge
+#[derive(Component, Debug, Clone)]
+pub struct ChargeRecovery {
+    pub remaining: f32,
+}
+
+/// A horse without a rider, waiting to be mounted
+#[derive(Component, Debug, Clone)]
+pub struct LooseMount {
+    pub mount: Mount,
+}
+
+/// Climb onto a nearby horse, or get off the current one
+#[derive(Event, Debug, Clone)]
+pub struct ToggleMount {
+    pub rider: Entity,
+}
+
+/// A rider's horse was killed under them
+#[derive(Event, Debug, Clone)]
+pub struct MountKilled {
+    pub rider: Entity,
+}
+
+/// Horse ridden by troops of the given tier
+pub fn troop_mount(tier: u8) -> Mount {
+    let health = 150.0 + tier as f32 * 20.0;
+    Mount {
+        speed: 9.0 + tier as f32 * 0.5,
+        charge_damage: 15.0 + tier as f32 * 3.0,
+        health,
+        max_health: health,
+        maneuverability: 1.5,
+    }
+}
+
+/// Damage of a charge hit with `base_damage` at the given closing speed
+pub fn charge_damage(base_damage: f32, closing_speed: f32) -> f32 {
+    base_damage * (closing_speed / CHARGE_REFERENCE_SPEED).max(0.0)
+}
+
+/// Cavalry don't trade blows: they ride through the nearest enemy, wheel away
+/// at an angle and come round again, so the path traces a loop
+pub fn decide_cavalry_action(
+    me: &CombatantSnapshot,
+    phase: &mut CavalryPhase,
+    battlefield: &[CombatantSnapshot],
+) -> AiDecision {
+    // The fallen ride nowhere
+    if !me.is_alive() {
+        return AiDecision::Idle;
+    }
+    let nearest = battlefield
+        .iter()
+        .filter(|other| other.is_alive() && me.is_enemy_of(other))
+        .min_by(|a, b| {
+            me.position.distance(a.position)
+                .total_cmp(&me.position.distance(b.position))
+                .then(a.entity.cmp(&b.entity))
+        });
+    let Some(nearest) = nearest else {
+        return AiDecision::Idle;
+    };
+
+    let away = (me.position - nearest.position).with_y(0.0).normalize_or(Vec3::X);
+    match *phase {
+        CavalryPhase::Charge if me.position.distance(nearest.position) <= CAVALRY_PASS_DISTANCE => {
+            // Wheel off to one side so the next run comes in from a new angle
+            let rally = nearest.position + Quat::from_rotation_y(std::f32::consts::FRAC_PI_4) * away * CAVALRY_RUNUP_DISTANCE;
+            *phase = CavalryPhase::Wheel { rally };
+            AiDecision::MoveTo { target: nearest.entity, position: rally }
+        }
+        CavalryPhase::Charge => AiDecision::MoveTo {
+            target: nearest.entity,
+            position: nearest.position - away * CAVALRY_OVERSHOOT,
+        },
+        CavalryPhase::Wheel { rally } if me.position.with_y(0.0).distance(rally.with_y(0.0)) <= RIDER_BRAKING_DISTANCE => {
+            *phase = CavalryPhase::Charge;
+            AiDecision::MoveTo {
+                target: nearest.entity,
+                position: nearest.position - away * CAVALRY_OVERSHOOT,
+            }
+        }
+        CavalryPhase::Wheel { rally } => AiDecision::MoveTo { target: nearest.entity, position: rally },
+    }
+}
+
+/// Steer a horse towards `position`: it turns no faster than its
+/// maneuverability allows and always moves the way it faces
+pub(super) fn ride_towards(transform: &mut Transform, position: Vec3, mount: &Mount, delta: f32) {
+    let offset = (position - transform.translation).with_y(0.0);
+    let distance = offset.length();
+    if distance <= POSITION_TOLERANCE {
+        return;
+    }
+
+    let forward = transform.forward().with_y(0.0).normalize_or(Vec3::NEG_Z);
+    let wanted = offset / distance;
+    let turn = forward.cross(wanted).y.atan2(forward.dot(wanted));
+    let max_turn = mount.maneuverability * delta;
+    transform.rotate_y(turn.clamp(-max_turn, max_turn));
+
+    let forward = transform.forward().with_y(0.0).normalize_or(Vec3::NEG_Z);
+    let speed = mount.speed * (distance / RIDER_BRAKING_DISTANCE).clamp(0.2, 1.0);
+    transform.translation += forward * (speed * delta).min(distance);
+}
+
+pub(super) fn track_momentum(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut combatants: Query<(Entity, &Transform, Option<&mut Momentum>), With<Health>>,
+) {
+    let delta = time.delta_secs();
+    for (entity, transform, momentum) in combatants.iter_mut() {
+        match momentum {
+            Some(mut momentum) => {
+                if let (Some(last), true) = (momentum.last_position, delta > 0.0) {
+                    momentum.velocity = (transform.translation - last) / delta;
+                }
+                momentum.last_position = Some(transform.translation);
+            }
+            None => {
+                commands.entity(entity).insert(Momentum {
+                    velocity: Vec3::ZERO,
+                    last_position: Some(transform.translation),
+                });
+            }
+        }
+    }
+}
+
+/// Riders at speed are `Charging`: a couched lance skewers the first enemy in
+/// reach, and any horse tramples whoever it runs into
+pub(super) fn charge_impacts(
+    mut commands: Commands,
+    time: Res<Time>,
+    rules: Res<WeaponRules>,
+    mut damage_events: EventWriter<DamageApplied>,
+    mut riders: Query<(
+        Entity,
+        &Mount,
+        &Momentum,
+        &Transform,
+        &Weapon,
+        &Health,
+        &BattleSide,
+        Option<&mut ChargeRecovery>,
+        Has<Charging>,
+        Has<Staggered>,
+    )>,
+    targets: Query<(Entity, &Transform, &Health, &BattleSide, Option<&Momentum>)>,
+) {
+    for (entity, mount, momentum, transform, weapon, health, side, recovery, charging, staggered) in riders.iter_mut() {
+        let at_speed = health.current > 0.0 && !staggered && momentum.velocity.length() >= CHARGE_MIN_SPEED;
+        match (at_speed, charging) {
+            (true, false) => { commands.entity(entity).insert(Charging); }
+            (false, true) => { commands.entity(entity).remove::<Charging>(); }
+            _ => {}
+        }
+
+        let mut recovery = recovery;
+        if let Some(recovery) = recovery.as_mut() {
+            recovery.remaining = (recovery.remaining - time.delta_secs()).max(0.0);
+        }
+        if !at_speed || recovery.as_ref().is_some_and(|recovery| recovery.remaining > 0.0) {
+            continue;
+        }
+
+        let couched = weapon.weapon_type == WeaponType::Spear;
+        let rule = rules.get(weapon.weapon_type);
+        let reach = if couched { weapon.reach + rule.reach_bonus } else { TRAMPLE_RADIUS };
+        let heading = momentum.velocity.with_y(0.0);
+
+        let hit = targets
+            .iter()
+            .filter(|(target, _, target_health, target_side, _)| {
+                *target != entity && target_health.current > 0.0 && *target_side != side
+            })
+            .filter_map(|(target, target_transform, _, _, target_momentum)| {
+                let offset = (target_transform.translation - transform.translation).with_y(0.0);
+                if offset.length() > reach || !within_arc(heading, offset, CHARGE_ARC) {
+                    return None;
+                }
+                // Only the speed at which the two close on each other counts
+                let their_velocity = target_momentum.map_or(Vec3::ZERO, |momentum| momentum.velocity);
+                let closing = (momentum.velocity - their_velocity).dot(offset.normalize_or_zero());
+                (closing >= CHARGE_MIN_SPEED).then_some((target, offset.length(), closing))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        let Some((target, _, closing)) = hit else {
+            continue;
+        };
+
+        let base = if couched { weapon.damage * rule.damage_multiplier } else { mount.charge_damage };
+        damage_events.send(DamageApplied {
+            attacker: entity,
+            target,
+            direction: AttackDirection::Thrust,
+            amount: charge_damage(base, closing),
+            // Lances skewer the body; horses bowl men over at the legs
+            damage_type: if couched { DamageType::Pierce } else { DamageType::Blunt },
+            location: if couched { BodyPart::Body } else { BodyPart::Legs },
+        });
+        if !couched {
+            commands.entity(target).insert(Staggered { remaining: TRAMPLE_STAGGER_TIME });
+        }
+        match recovery {
+            Some(mut recovery) => recovery.remaining = CHARGE_RECOVERY_TIME,
+            None => {
+                commands.entity(entity).insert(ChargeRecovery { remaining: CHARGE_RECOVERY_TIME });
+            }
+        }
+    }
+}
+
+pub(super) fn handle_mount_toggles(
+    mut commands: Commands,
+    mut toggles: EventReader<ToggleMount>,
+    riders: Query<(&Transform, &Health, Option<&Mount>)>,
+    loose: Query<(Entity, &Transform, &LooseMount)>,
+) {
+    for toggle in toggles.read() {
+        let Ok((transform, health, mount)) = riders.get(toggle.rider) else {
+            continue;
+        };
+        if health.current <= 0.0 {
+            continue;
+        }
+
+        match mount {
+            // Step off and leave the horse standing beside us
+            Some(mount) => {
+                commands.entity(toggle.rider).remove::<(Mount, Charging)>();
+                commands.spawn((
+                    LooseMount { mount: mount.clone() },
+                    StateScoped(GameState::Combat),
+                    Transform::from_translation(transform.translation + *transform.right() * 1.5),
+                ));
+            }
+            None => {
+                let nearest = loose
+                    .iter()
+                    .map(|(entity, horse, loose)| (entity, horse.translation.distance(transform.translation), loose))
+                    .filter(|(_, distance, _)| *distance <= MOUNT_REACH)
+                    .min_by(|a, b| a.1.total_cmp(&b.1));
+                if let Some((horse, _, loose)) = nearest {
+                    commands.entity(horse).despawn_recursive();
+                    commands.entity(toggle.rider).insert(loose.mount.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{combat_test_app, spawn_soldier, spawn_swordsman, enlist, snapshot, run_test_app};
+
+    #[test]
+    fn test_charge_damage_scales_with_speed() {
+        assert_eq!(charge_damage(30.0, 10.0), 30.0);
+        assert_eq!(charge_damage(30.0, 20.0), 60.0);
+        assert_eq!(charge_damage(30.0, -5.0), 0.0);
+
+        // Better troops ride better horses
+        assert!(troop_mount(4).speed > troop_mount(1).speed);
+        assert!(troop_mount(4).charge_damage > troop_mount(1).charge_damage);
+    }
+
+    #[test]
+    fn test_cavalry_rides_through_and_wheels() {
+        let enemy = snapshot(1, BattleSide::Defender, Vec3::ZERO);
+        let mut phase = CavalryPhase::Charge;
+
+        // Aim past the enemy so the charge carries through
+        let rider = snapshot(0, BattleSide::Attacker, Vec3::new(0.0, 0.0, 20.0));
+        match decide_cavalry_action(&rider, &mut phase, &[rider.clone(), enemy.clone()]) {
+            AiDecision::MoveTo { position, .. } => assert!(position.z < 0.0),
+            other => panic!("expected a charge, got {other:?}"),
+        }
+
+        // Once through, ride out to rally rather than stopping to fight
+        let rider = snapshot(0, BattleSide::Attacker, Vec3::new(0.0, 0.0, 1.0));
+        decide_cavalry_action(&rider, &mut phase, &[rider.clone(), enemy.clone()]);
+        let CavalryPhase::Wheel { rally } = phase else {
+            panic!("expected the rider to wheel away");
+        };
+        assert!(rally.distance(Vec3::ZERO) > 20.0);
+
+        // And come round again from there
+        let rider = snapshot(0, BattleSide::Attacker, rally);
+        decide_cavalry_action(&rider, &mut phase, &[rider.clone(), enemy.clone()]);
+        assert_eq!(phase, CavalryPhase::Charge);
+
+        // A fallen rider charges no more
+        let mut rider = snapshot(0, BattleSide::Attacker, Vec3::new(0.0, 0.0, 20.0));
+        rider.health_ratio = 0.0;
+        let decision = decide_cavalry_action(&rider, &mut phase, &[rider.clone(), enemy]);
+        assert!(matches!(decision, AiDecision::Idle));
+        assert_eq!(phase, CavalryPhase::Charge);
+    }
+
+    // Lancer galloping at `speed` straight through a footman, returning the damage dealt
+    fn lance_charge(speed: f32) -> f32 {
+        let mut app = combat_test_app();
+        let rider = spawn_soldier(&mut app, WeaponType::Spear, 10, Vec3::new(0.0, 0.0, 8.0));
+        enlist(&mut app, rider, BattleSide::Attacker, "vlandia");
+        app.world_mut().entity_mut(rider).insert(troop_mount(3));
+        let target = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+        enlist(&mut app, target, BattleSide::Defender, "looters");
+
+        for _ in 0..60 {
+            app.world_mut().get_mut::<Transform>(rider).unwrap().translation.z -= speed / 60.0;
+            run_test_app(&mut app, 1);
+        }
+        100.0 - app.world().get::<Health>(target).unwrap().current
+    }
+
+    #[test]
+    fn test_couched_lance_hits_harder_at_speed() {
+        let trot = lance_charge(7.0);
+        let gallop = lance_charge(14.0);
+        assert!(trot > 0.0);
+        assert!((gallop / trot - 2.0).abs() < 0.2, "trot {trot}, gallop {gallop}");
+    }
+
+    #[test]
+    fn test_rider_thrown_when_mount_dies() {
+        let mut app = combat_test_app();
+        let attacker = spawn_swordsman(&mut app, 10, Vec3::new(0.0, 0.0, -1.0));
+        let rider = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+        app.world_mut().entity_mut(rider).insert(Mount { health: 10.0, ..troop_mount(2) });
+
+        app.world_mut().send_event(DamageApplied {
+            attacker,
+            target: rider,
+            direction: AttackDirection::Left,
+            amount: 30.0,
+            damage_type: DamageType::Cut,
+            location: BodyPart::Body,
+        });
+        run_test_app(&mut app, 1);
+
+        // Half the blow lands on the horse, which falls; the rider takes the rest plus the fall
+        assert!(app.world().get::<Mount>(rider).is_none());
+        assert!(app.world().get::<Staggered>(rider).is_some());
+        assert!((app.world().get::<Health>(rider).unwrap().current - 75.0).abs() < 1e-3);
+        let mut cursor = app.world().resource::<Events<MountKilled>>().get_cursor();
+        let events = app.world().resource::<Events<MountKilled>>();
+        assert_eq!(cursor.read(events).next().map(|event| event.rider), Some(rider));
+    }
+
+    #[test]
+    fn test_dismount_and_remount() {
+        let mut app = combat_test_app();
+        let rider = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+        app.world_mut().entity_mut(rider).insert(troop_mount(2));
+
+        app.world_mut().send_event(ToggleMount { rider });
+        run_test_app(&mut app, 1);
+        assert!(app.world().get::<Mount>(rider).is_none());
+        let loose = app.world_mut().query::<&LooseMount>().iter(app.world()).count();
+        assert_eq!(loose, 1);
+
+        app.world_mut().send_event(ToggleMount { rider });
+        run_test_app(&mut app, 1);
+        assert!(app.world().get::<Mount>(rider).is_some());
+        let loose = app.world_mut().query::<&LooseMount>().iter(app.world()).count();
+        assert_eq!(loose, 0);
+    }
+}
@@ -0,0 +1,340 @@
+use bevy::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::core::components::{Health, BattleSide, FormationGroup, Player, Routing};
+
+use super::ai::{AiDecision, CombatantSnapshot};
+use super::deployment::{Formations, FormationTemplate, formation_offsets, slot_position, group_units};
+
+/// How far a unit may stray from its slot before it has to reform
+pub const COHESION_RADIUS: f32 = 4.0;
+/// Distance an advancing formation stops short of the enemy's centre
+pub const ADVANCE_STANDOFF: f32 = 5.0;
+/// How far a "fall back" order pulls a formation back
+pub const FALL_BACK_DISTANCE: f32 = 20.0;
+/// Distance behind the player a following formation keeps
+pub const FOLLOW_DISTANCE: f32 = 3.0;
+/// How far ahead of the player a keyboard "move" order points
+pub const MOVE_ORDER_DISTANCE: f32 = 20.0;
+
+/// Standing movement order of a formation
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum FormationOrder {
+    /// Stand at the anchor and only fight what comes into reach
+    Hold,
+    /// Walk to a point and hold there
+    MoveTo(Vec3),
+    /// Break formation and let every soldier pick their own fight
+    Charge,
+    /// Keep station behind the player
+    FollowMe,
+    /// Close on the enemy in formation
+    Advance,
+    /// Give ground, then hold
+    FallBack,
+}
+
+/// Everything a commander can shout at a formation
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum FormationCommand {
+    Move(Vec3),
+    Hold,
+    Charge,
+    FollowMe,
+    Advance,
+    FallBack,
+    FireAtWill,
+    HoldFire,
+    Arrange(FormationTemplate),
+}
+
+/// Order for one formation of a side, or all of them when `group` is `None`
+#[derive(Event, Debug, Clone, Serialize, Deserialize)]
+pub struct IssueOrder {
+    pub side: BattleSide,
+    pub group: Option<FormationGroup>,
+    pub command: FormationCommand,
+}
+
+/// Formations the player's keyboard orders go to
+#[derive(Resource, Debug, Clone, Default)]
+pub struct SelectedFormation(pub Option<FormationGroup>);
+
+/// Where a unit's formation wants it this frame
+#[derive(Component, Debug, Clone)]
+pub struct FormationDirective {
+    pub slot: Vec3,
+    pub facing: Vec3,
+    /// Whether the unit may fight at all outside its immediate reach (charge)
+    pub free: bool,
+    pub may_fire: bool,
+}
+
+/// Narrows an individual AI decision to what the unit's formation allows.
+/// Charging units keep their own judgement; everyone else holds their slot,
+/// fights only what is already in reach and shoots only when allowed.
+pub fn constrain_decision(decision: AiDecision, me: &CombatantSnapshot, directive: &FormationDirective) -> AiDecision {
+    if directive.free {
+        return decision;
+    }
+
+    let out_of_place = me.position.distance(directive.slot) > COHESION_RADIUS;
+    match decision {
+        AiDecision::Attack { .. } if me.is_ranged && !directive.may_fire => reform(directive),
+        AiDecision::Attack { .. } if out_of_place && !me.is_ranged => reform(directive),
+        AiDecision::Attack { .. } | AiDecision::Block { .. } => decision,
+        AiDecision::MoveTo { .. } | AiDecision::Retreat { .. } | AiDecision::Idle => reform(directive),
+        AiDecision::HoldPosition { .. } => decision,
+    }
+}
+
+fn reform(directive: &FormationDirective) -> AiDecision {
+    AiDecision::HoldPosition {
+        position: directive.slot,
+        facing: directive.facing,
+    }
+}
+
+pub(super) fn handle_order_input(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut selected: ResMut<SelectedFormation>,
+    mut order_events: EventWriter<IssueOrder>,
+    player: Query<(&Transform, &BattleSide), With<Player>>,
+) {
+    // Number keys pick a formation, zero picks everyone
+    for (key, group) in [
+        (KeyCode::Digit0, None),
+        (KeyCode::Digit1, Some(FormationGroup::Infantry)),
+        (KeyCode::Digit2, Some(FormationGroup::Archers)),
+        (KeyCode::Digit3, Some(FormationGroup::Cavalry)),
+        (KeyCode::Digit4, Some(FormationGroup::Skirmishers)),
+    ] {
+        if keyboard.just_pressed(key) {
+            selected.0 = group;
+        }
+    }
+
+    let Ok((transform, side)) = player.get_single() else {
+        return;
+    };
+    let move_point = transform.translation + *transform.forward() * MOVE_ORDER_DISTANCE;
+    for (key, command) in [
+        (KeyCode::F1, FormationCommand::Move(move_point)),
+        (KeyCode::F2, FormationCommand::Charge),
+        (KeyCode::F3, FormationCommand::Hold),
+        (KeyCode::F4, FormationCommand::FollowMe),
+        (KeyCode::F5, FormationCommand::Advance),
+        (KeyCode::F6, FormationCommand::FallBack),
+        (KeyCode::F7, FormationCommand::FireAtWill),
+        (KeyCode::F8, FormationCommand::HoldFire),
+    ] {
+        if keyboard.just_pressed(key) {
+            order_events.send(IssueOrder {
+                side: *side,
+                group: selected.0,
+                command,
+            });
+        }
+    }
+}
+
+pub(super) fn apply_orders(
+    mut formations: ResMut<Formations>,
+    mut order_events: EventReader<IssueOrder>,
+) {
+    const ALL_GROUPS: [FormationGroup; 4] = [
+        FormationGroup::Infantry,
+        FormationGroup::Archers,
+        FormationGroup::Cavalry,
+        FormationGroup::Skirmishers,
+    ];
+
+    for event in order_events.read() {
+        let groups = match event.group {
+            Some(group) => vec![group],
+            None => ALL_GROUPS.to_vec(),
+        };
+        for group in groups {
+            let Some(formation) = formations.get_mut(event.side, group) else {
+                continue;
+            };
+            match event.command {
+                FormationCommand::Move(point) => {
+                    let heading = Vec3::new(point.x - formation.anchor.x, 0.0, point.z - formation.anchor.z);
+                    if let Some(heading) = heading.try_normalize() {
+                        formation.facing = heading;
+                    }
+                    formation.anchor = point;
+                    formation.order = FormationOrder::MoveTo(point);
+                }
+                FormationCommand::FallBack => {
+                    formation.anchor -= formation.facing * FALL_BACK_DISTANCE;
+                    formation.order = FormationOrder::FallBack;
+                }
+                FormationCommand::Hold => formation.order = FormationOrder::Hold,
+                FormationCommand::Charge => formation.order = FormationOrder::Charge,
+                FormationCommand::FollowMe => formation.order = FormationOrder::FollowMe,
+                FormationCommand::Advance => formation.order = FormationOrder::Advance,
+                FormationCommand::FireAtWill => formation.fire_at_will = true,
+                FormationCommand::HoldFire => formation.fire_at_will = false,
+                FormationCommand::Arrange(template) => formation.template = template,
+            }
+        }
+    }
+}
+
+pub(super) fn update_formation_directives(
+    mut commands: Commands,
+    mut formations: ResMut<Formations>,
+    units: Query<(Entity, &BattleSide, &FormationGroup, &Health), Without<Routing>>,
+    positions: Query<(&Transform, &BattleSide, &Health), Without<Routing>>,
+    player: Query<(&Transform, &BattleSide), With<Player>>,
+) {
+    let groups = group_units(
+        units
+            .iter()
+            .filter(|(.., health)| health.current > 0.0)
+            .map(|(entity, side, group, _)| (entity, *side, *group, ())),
+    );
+
+    for ((side, group), members) in groups.iter() {
+        let Some(formation) = formations.get_mut(*side, *group) else {
+            continue;
+        };
+
+        // Orders that track something on the field move the anchor every frame
+        match formation.order {
+            FormationOrder::Advance => {
+                let (sum, count) = positions
+                    .iter()
+                    .filter(|(_, enemy_side, health)| **enemy_side != *side && health.current > 0.0)
+                    .fold((Vec3::ZERO, 0), |(sum, count), (transform, ..)| (sum + transform.translation, count + 1));
+                if count > 0 {
+                    let enemy_centre = sum / count as f32;
+                    let heading = Vec3::new(enemy_centre.x - formation.anchor.x, 0.0, enemy_centre.z - formation.anchor.z);
+                    if let Some(heading) = heading.try_normalize() {
+                        formation.facing = heading;
+                        formation.anchor = enemy_centre - heading * ADVANCE_STANDOFF;
+                    }
+                }
+            }
+            FormationOrder::FollowMe => {
+                if let Some((transform, _)) = player.iter().find(|(_, player_side)| *player_side == side) {
+                    let forward = Vec3::new(transform.forward().x, 0.0, transform.forward().z)
+                        .normalize_or(formation.facing);
+                    formation.facing = forward;
+                    formation.anchor = transform.translation - forward * FOLLOW_DISTANCE;
+                }
+            }
+            _ => {}
+        }
+
+        let offsets = formation_offsets(formation.template, members.len());
+        for ((entity, _), offset) in members.iter().zip(offsets) {
+            commands.entity(*entity).insert(FormationDirective {
+                slot: slot_position(formation.anchor, formation.facing, offset),
+                facing: formation.facing,
+                free: formation.order == FormationOrder::Charge,
+                may_fire: formation.fire_at_will,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{CombatAI, AttackDirection};
+    use crate::test_support::{combat_test_app, spawn_swordsman, start_battle, snapshot, kill, run_test_app};
+
+    #[test]
+    fn test_formation_directive_constrains_ai() {
+        let me = snapshot(0, BattleSide::Attacker, Vec3::ZERO);
+        let enemy = snapshot(1, BattleSide::Defender, Vec3::new(10.0, 0.0, 0.0));
+        let holding = FormationDirective {
+            slot: Vec3::new(0.0, 0.0, 1.0),
+            facing: Vec3::NEG_Z,
+            free: false,
+            may_fire: false,
+        };
+
+        // Holding soldiers don't chase
+        let chase = AiDecision::MoveTo { target: enemy.entity, position: Vec3::new(9.0, 0.0, 0.0) };
+        assert_eq!(
+            constrain_decision(chase, &me, &holding),
+            AiDecision::HoldPosition { position: holding.slot, facing: holding.facing }
+        );
+
+        // Archers on hold fire keep their arrows
+        let mut archer = me.clone();
+        archer.is_ranged = true;
+        let shot = AiDecision::Attack { target: enemy.entity, direction: AttackDirection::Thrust };
+        assert!(matches!(constrain_decision(shot, &archer, &holding), AiDecision::HoldPosition { .. }));
+        let firing = FormationDirective { may_fire: true, ..holding.clone() };
+        assert_eq!(constrain_decision(shot, &archer, &firing), shot);
+
+        // Charging releases them to their own judgement
+        let charging = FormationDirective { free: true, ..holding };
+        assert_eq!(constrain_decision(chase, &me, &charging), chase);
+    }
+
+    #[test]
+    fn test_scripted_orders_hold_then_charge() {
+        let mut app = combat_test_app();
+        let soldier = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+        app.world_mut().entity_mut(soldier).insert((
+            CombatAI { aggression: 1.0, preferred_distance: 1.0 },
+            BattleSide::Attacker,
+            FormationGroup::Infantry,
+        ));
+        let enemy = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+        app.world_mut().entity_mut(enemy).insert((BattleSide::Defender, FormationGroup::Infantry));
+        // One deployment frame puts both sides in their zones, far apart
+        run_test_app(&mut app, 1);
+        start_battle(&mut app);
+
+        let deployed = app.world().get::<Transform>(soldier).unwrap().translation;
+        run_test_app(&mut app, 60);
+        let held = app.world().get::<Transform>(soldier).unwrap().translation;
+        assert!(held.distance(deployed) < 0.5);
+
+        app.world_mut().send_event(IssueOrder {
+            side: BattleSide::Attacker,
+            group: Some(FormationGroup::Infantry),
+            command: FormationCommand::Charge,
+        });
+        run_test_app(&mut app, 60);
+        let charged = app.world().get::<Transform>(soldier).unwrap().translation;
+        assert!(charged.distance(deployed) > 2.0);
+    }
+
+    #[test]
+    fn test_fallen_soldier_stays_where_they_fell() {
+        let mut app = combat_test_app();
+        let mut soldiers = Vec::new();
+        for _ in 0..2 {
+            let soldier = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+            app.world_mut().entity_mut(soldier).insert((
+                CombatAI { aggression: 1.0, preferred_distance: 1.0 },
+                BattleSide::Attacker,
+                FormationGroup::Infantry,
+            ));
+            soldiers.push(soldier);
+        }
+        let enemy = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+        app.world_mut().entity_mut(enemy).insert((BattleSide::Defender, FormationGroup::Infantry));
+        run_test_app(&mut app, 1);
+        start_battle(&mut app);
+
+        // Cut down a few paces out of line, with the formation still holding
+        let fallen = soldiers[0];
+        let mut transform = app.world_mut().get_mut::<Transform>(fallen).unwrap();
+        transform.translation += Vec3::new(5.0, 0.0, 0.0);
+        let fell_at = transform.translation;
+        kill(&mut app, enemy, fallen);
+        run_test_app(&mut app, 60);
+
+        let lying = app.world().get::<Transform>(fallen).unwrap().translation;
+        assert!(lying.distance(fell_at) < 0.01);
+    }
+}
@@ -0,0 +1,245 @@
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use bevy_rapier3d::prelude::*;
+use std::f32::consts::FRAC_PI_4;
+
+use crate::core::components::{Health, Shield, WeaponType, AttackDirection, DamageType};
+use crate::core::states::GameState;
+
+use super::{
+    WeaponRules, FireProjectile, DamageApplied, AttackBlocked, BlockKind, ProjectileHit,
+    shield_blocks,
+};
+use super::armor::impact_location;
+
+/// Matches rapier's default world gravity
+pub const PROJECTILE_GRAVITY: f32 = 9.81;
+/// Seconds before a projectile that hit nothing is removed
+pub const PROJECTILE_LIFETIME: f32 = 10.0;
+pub const PROJECTILE_RADIUS: f32 = 0.05;
+/// Distance in front of the shooter a projectile spawns, so it clears their own collider
+pub const PROJECTILE_SPAWN_CLEARANCE: f32 = 1.0;
+
+/// An arrow or bolt in flight
+#[derive(Component, Debug, Clone)]
+pub struct Projectile {
+    pub shooter: Entity,
+    pub damage: f32,
+    /// Where the shot was loosed from, used to decide which way a shield must face
+    pub origin: Vec3,
+    pub lifetime: Timer,
+}
+
+/// Launch velocity that lands a projectile fired at `speed` on `target` under
+/// `gravity`, taking the flatter of the two possible arcs. Targets out of range
+/// get a 45 degree lob, the furthest the shot can carry.
+pub fn ballistic_velocity(origin: Vec3, target: Vec3, speed: f32, gravity: f32) -> Vec3 {
+    let offset = target - origin;
+    let horizontal = Vec3::new(offset.x, 0.0, offset.z);
+    let distance = horizontal.length();
+    if distance < f32::EPSILON || gravity <= 0.0 {
+        return offset.normalize_or_zero() * speed;
+    }
+
+    let speed_sq = speed * speed;
+    let discriminant = speed_sq * speed_sq
+        - gravity * (gravity * distance * distance + 2.0 * offset.y * speed_sq);
+    let angle = if discriminant >= 0.0 {
+        ((speed_sq - discriminant.sqrt()) / (gravity * distance)).atan()
+    } else {
+        FRAC_PI_4
+    };
+
+    horizontal / distance * angle.cos() * speed + Vec3::Y * angle.sin() * speed
+}
+
+pub(super) fn spawn_projectiles(
+    mut commands: Commands,
+    mut fire_events: EventReader<FireProjectile>,
+    transforms: Query<&Transform>,
+) {
+    for event in fire_events.read() {
+        let (Ok(shooter), Ok(target)) = (transforms.get(event.shooter), transforms.get(event.target)) else {
+            continue;
+        };
+
+        let aim = (target.translation - shooter.translation).normalize_or_zero();
+        let origin = shooter.translation + aim * PROJECTILE_SPAWN_CLEARANCE;
+        let velocity = ballistic_velocity(origin, target.translation, event.speed, PROJECTILE_GRAVITY);
+
+        commands.spawn((
+            Projectile {
+                shooter: event.shooter,
+                damage: event.damage,
+                origin,
+                lifetime: Timer::from_seconds(PROJECTILE_LIFETIME, TimerMode::Once),
+            },
+            Transform::from_translation(origin),
+            RigidBody::Dynamic,
+            Collider::ball(PROJECTILE_RADIUS),
+            Velocity::linear(velocity),
+            // Fast enough to tunnel through a soldier in a single step without it
+            Ccd::enabled(),
+            ActiveEvents::COLLISION_EVENTS,
+            StateScoped(GameState::Combat),
+        ));
+    }
+}
+
+pub(super) fn handle_projectile_hits(
+    mut commands: Commands,
+    rules: Res<WeaponRules>,
+    mut collision_events: EventReader<CollisionEvent>,
+    mut damage_events: EventWriter<DamageApplied>,
+    mut blocked_events: EventWriter<AttackBlocked>,
+    mut hit_events: EventWriter<ProjectileHit>,
+    projectiles: Query<(&Projectile, &Transform)>,
+    targets: Query<(&Health, &Transform, Option<&Shield>)>,
+) {
+    let shield_rule = rules.get(WeaponType::Shield);
+    // A projectile can touch several colliders in one step but only hits once
+    let mut spent = HashSet::new();
+
+    for event in collision_events.read() {
+        let CollisionEvent::Started(a, b, _) = event else {
+            continue;
+        };
+        let (entity, other, (projectile, impact)) = match (projectiles.get(*a), projectiles.get(*b)) {
+            (Ok(projectile), _) => (*a, *b, projectile),
+            (_, Ok(projectile)) => (*b, *a, projectile),
+            _ => continue,
+        };
+        if other == projectile.shooter || spent.contains(&entity) {
+            continue;
+        }
+        spent.insert(entity);
+        commands.entity(entity).despawn_recursive();
+
+        // Terrain and props just stop the shot
+        let Ok((health, transform, shield)) = targets.get(other) else {
+            continue;
+        };
+        if health.current <= 0.0 {
+            continue;
+        }
+        hit_events.send(ProjectileHit { shooter: projectile.shooter, target: other });
+
+        if shield_blocks(shield_rule, shield, Some(transform), Some(projectile.origin)) {
+            blocked_events.send(AttackBlocked {
+                attacker: projectile.shooter,
+                target: other,
+                direction: AttackDirection::Thrust,
+                kind: BlockKind::Shield,
+                damage: projectile.damage,
+                shield_damage: projectile.damage * shield_rule.durability_damage_multiplier,
+            });
+        } else {
+            damage_events.send(DamageApplied {
+                attacker: projectile.shooter,
+                target: other,
+                direction: AttackDirection::Thrust,
+                amount: projectile.damage,
+                damage_type: DamageType::Pierce,
+                location: impact_location(transform, impact.translation),
+            });
+        }
+    }
+}
+
+pub(super) fn expire_projectiles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut projectiles: Query<(Entity, &mut Projectile)>,
+) {
+    for (entity, mut projectile) in projectiles.iter_mut() {
+        if projectile.lifetime.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Ammo;
+    use crate::plugins::AttackEvent;
+    use crate::test_support::{combat_test_app, spawn_soldier, spawn_swordsman, run_test_app};
+
+    #[test]
+    fn test_ballistic_velocity_lands_on_target() {
+        let origin = Vec3::ZERO;
+        let target = Vec3::new(30.0, 2.0, -40.0);
+        let velocity = ballistic_velocity(origin, target, 60.0, 9.81);
+
+        assert!((velocity.length() - 60.0).abs() < 1e-3);
+        assert!(velocity.y > 0.0);
+
+        // Follow the arc until it covers the horizontal distance
+        let horizontal = Vec2::new(velocity.x, velocity.z);
+        let time = 50.0 / horizontal.length();
+        let height = velocity.y * time - 0.5 * 9.81 * time * time;
+        assert!((height - 2.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_arrow_hits_target_and_uses_ammo() {
+        let mut app = combat_test_app();
+        let archer = spawn_soldier(&mut app, WeaponType::Bow, 10, Vec3::new(0.0, 1.0, 0.0));
+        app.world_mut().entity_mut(archer).insert(Ammo { current: 3, max: 20 });
+        let target = spawn_swordsman(&mut app, 10, Vec3::new(0.0, 1.0, -20.0));
+        app.world_mut().entity_mut(target).insert(Collider::capsule_y(0.6, 0.4));
+
+        app.world_mut().send_event(AttackEvent {
+            attacker: archer,
+            target,
+            direction: AttackDirection::Thrust,
+            timing: 1.0,
+        });
+        run_test_app(&mut app, 120);
+
+        assert!(app.world().get::<Health>(target).unwrap().current < 100.0);
+        assert_eq!(app.world().get::<Ammo>(archer).unwrap().current, 2);
+        let in_flight = app.world_mut().query::<&Projectile>().iter(app.world()).count();
+        assert_eq!(in_flight, 0);
+    }
+
+    #[test]
+    fn test_empty_quiver_cannot_fire() {
+        let mut app = combat_test_app();
+        let archer = spawn_soldier(&mut app, WeaponType::Bow, 10, Vec3::ZERO);
+        app.world_mut().entity_mut(archer).insert(Ammo { current: 0, max: 20 });
+        let target = spawn_swordsman(&mut app, 10, Vec3::new(0.0, 0.0, -20.0));
+
+        app.world_mut().send_event(AttackEvent {
+            attacker: archer,
+            target,
+            direction: AttackDirection::Thrust,
+            timing: 1.0,
+        });
+        run_test_app(&mut app, 1);
+
+        assert_eq!(app.world().resource::<Events<FireProjectile>>().len(), 0);
+    }
+
+    #[test]
+    fn test_missed_projectile_expires() {
+        let mut app = combat_test_app();
+        let archer = spawn_soldier(&mut app, WeaponType::Bow, 10, Vec3::ZERO);
+        // No collider on the target, so the arrow flies on until its lifetime runs out
+        let target = spawn_swordsman(&mut app, 10, Vec3::new(0.0, 0.0, -20.0));
+
+        app.world_mut().send_event(AttackEvent {
+            attacker: archer,
+            target,
+            direction: AttackDirection::Thrust,
+            timing: 1.0,
+        });
+        run_test_app(&mut app, 5);
+        let in_flight = app.world_mut().query::<&Projectile>().iter(app.world()).count();
+        assert_eq!(in_flight, 1);
+
+        run_test_app(&mut app, 60 * 11);
+        let in_flight = app.world_mut().query::<&Projectile>().iter(app.world()).count();
+        assert_eq!(in_flight, 0);
+    }
+}
@@ -0,0 +1,556 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use bevy::utils::{Duration, HashMap};
+use bevy_rand::prelude::{GlobalEntropy, WyRand};
+use leafwing_input_manager::prelude::{ActionState, InputMap};
+use rand_core::RngCore;
+use serde::{Serialize, Deserialize};
+
+use crate::core::components::{AttackDirection, BattleSide, FormationGroup, Player, SiegeEngine};
+use crate::core::states::GameState;
+use crate::save::SaveGameConfig;
+
+use super::{AftermathConfirmed, BattleAftermath};
+use super::ai::{AiDecision, CombatAIState};
+use super::deployment::{DeploymentReady, Formation, Formations};
+use super::input::CombatAction;
+use super::mounts::Momentum;
+use super::orders::IssueOrder;
+use super::scene::BattleSetup;
+use super::siege::SiegeBattle;
+
+/// Bumped whenever the file layout changes, so old replays can be turned away
+pub const REPLAY_VERSION: u32 = 1;
+/// Folder inside the save directory that replays are written to
+pub const REPLAY_DIRECTORY: &str = "replays";
+pub const REPLAY_EXTENSION: &str = "replay";
+/// Command line flag naming a replay to watch as the game starts
+pub const REPLAY_FLAG: &str = "--replay";
+
+/// Stable number of a combatant, the same every time a battle is set up from
+/// the same `BattleSetup`; replays refer to units by it
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ReplayId(pub u32);
+
+/// Where a unit stood when the fighting started
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeployedUnit {
+    pub id: ReplayId,
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub group: Option<FormationGroup>,
+}
+
+/// An AI decision with its targets given by `ReplayId`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RecordedDecision {
+    Idle,
+    MoveTo { target: ReplayId, position: Vec3 },
+    Attack { target: ReplayId, direction: AttackDirection },
+    Block { target: ReplayId },
+    Retreat { threat: ReplayId, position: Vec3 },
+    HoldPosition { position: Vec3, facing: Vec3 },
+}
+
+impl RecordedDecision {
+    fn record(decision: AiDecision, ids: &HashMap<Entity, ReplayId>) -> Option<Self> {
+        Some(match decision {
+            AiDecision::Idle => Self::Idle,
+            AiDecision::MoveTo { target, position } => Self::MoveTo { target: *ids.get(&target)?, position },
+            AiDecision::Attack { target, direction } => Self::Attack { target: *ids.get(&target)?, direction },
+            AiDecision::Block { target } => Self::Block { target: *ids.get(&target)? },
+            AiDecision::Retreat { threat, position } => Self::Retreat { threat: *ids.get(&threat)?, position },
+            AiDecision::HoldPosition { position, facing } => Self::HoldPosition { position, facing },
+        })
+    }
+
+    fn restore(self, entities: &HashMap<ReplayId, Entity>) -> Option<AiDecision> {
+        Some(match self {
+            Self::Idle => AiDecision::Idle,
+            Self::MoveTo { target, position } => AiDecision::MoveTo { target: *entities.get(&target)?, position },
+            Self::Attack { target, direction } => AiDecision::Attack { target: *entities.get(&target)?, direction },
+            Self::Block { target } => AiDecision::Block { target: *entities.get(&target)? },
+            Self::Retreat { threat, position } => AiDecision::Retreat { threat: *entities.get(&threat)?, position },
+            Self::HoldPosition { position, facing } => AiDecision::HoldPosition { position, facing },
+        })
+    }
+}
+
+/// Everything that went into one frame of a battle. Only AI decisions that
+/// changed since the unit's last one are kept.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReplayFrame {
+    /// Length of the frame, to the nanosecond so playback steps exactly the same
+    pub delta_nanos: u64,
+    /// Buttons the player held down
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub held: Vec<CombatAction>,
+    #[serde(default, skip_serializing_if = "is_zero_axis_pair")]
+    pub movement: Vec2,
+    #[serde(default, skip_serializing_if = "is_zero_axis")]
+    pub turn: f32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub orders: Vec<IssueOrder>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub decisions: Vec<(ReplayId, RecordedDecision)>,
+}
+
+fn is_zero_axis(value: &f32) -> bool {
+    *value == 0.0
+}
+
+fn is_zero_axis_pair(value: &Vec2) -> bool {
+    *value == Vec2::ZERO
+}
+
+/// A fought battle, from its setup and rng seed to every input and AI
+/// decision, enough to play it out again exactly
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    /// Seed the shared rng was reset to as the battle began
+    pub seed: u64,
+    pub setup: BattleSetup,
+    /// Engines the attackers brought, when the battle was an assault on a settlement
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub siege_engines: Option<Vec<SiegeEngine>>,
+    pub deployment: Vec<DeployedUnit>,
+    pub formations: Vec<(BattleSide, FormationGroup, Formation)>,
+    pub frames: Vec<ReplayFrame>,
+}
+
+impl Replay {
+    /// Writes the replay as compact JSON
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let file = File::create(path)?;
+        serde_json::to_writer(BufWriter::new(file), self)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let replay: Replay = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        if replay.version != REPLAY_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("replay version {} is not supported", replay.version),
+            ));
+        }
+        Ok(replay)
+    }
+}
+
+/// The battle being fought, recorded as it goes
+#[derive(Resource, Debug, Clone)]
+pub struct ReplayRecorder {
+    pub replay: Replay,
+    /// Last decision written for each unit
+    decisions: HashMap<ReplayId, RecordedDecision>,
+}
+
+/// A recorded battle being played out again
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    pub replay: Replay,
+    /// Next frame to feed in
+    pub frame: usize,
+    /// Where the game goes once the replay's results screen is dismissed
+    return_to: GameState,
+    /// How time was stepped before playback took it over
+    previous_time_strategy: Option<TimeUpdateStrategy>,
+}
+
+/// Play `replay` back from the start
+#[derive(Event, Debug, Clone)]
+pub struct PlayReplay {
+    pub replay: Replay,
+}
+
+/// The replay named by `--replay <file>` on the command line. A file that isn't
+/// there as given is looked for among the saved replays.
+fn requested_replay_path(mut args: impl Iterator<Item = String>, config: Option<&SaveGameConfig>) -> Option<PathBuf> {
+    args.find(|arg| arg == REPLAY_FLAG)?;
+    let path = PathBuf::from(args.next()?);
+    Some(match config {
+        Some(config) if !path.exists() => config.save_directory.join(REPLAY_DIRECTORY).join(path),
+        _ => path,
+    })
+}
+
+/// Plays the replay asked for on the command line as soon as the game is up
+pub(super) fn play_requested_replay(config: Option<Res<SaveGameConfig>>, mut requests: EventWriter<PlayReplay>) {
+    let Some(path) = requested_replay_path(std::env::args().skip(1), config.as_deref()) else {
+        return;
+    };
+    match Replay::load(&path) {
+        Ok(replay) => {
+            requests.send(PlayReplay { replay });
+        }
+        Err(error) => error!("Failed to load battle replay {}: {}", path.display(), error),
+    }
+}
+
+/// Sets up the recorded battle and switches to combat to play it out
+pub(super) fn start_replay(
+    mut commands: Commands,
+    mut requests: EventReader<PlayReplay>,
+    state: Res<State<GameState>>,
+    mut next_game_state: ResMut<NextState<GameState>>,
+) {
+    let Some(request) = requests.read().last() else {
+        return;
+    };
+    let replay = request.replay.clone();
+    commands.insert_resource(replay.setup.clone());
+    if let Some(engines) = &replay.siege_engines {
+        commands.insert_resource(SiegeBattle { engines: engines.clone(), ..default() });
+    }
+    commands.insert_resource(ReplayPlayback {
+        replay,
+        frame: 0,
+        return_to: *state.get(),
+        previous_time_strategy: None,
+    });
+    next_game_state.set(GameState::Combat);
+}
+
+/// Starts recording a battle that is about to be fought, resetting the shared
+/// rng to a fresh seed so anything random in it can be rolled again the same way
+pub(super) fn begin_recording(
+    mut commands: Commands,
+    setup: Res<BattleSetup>,
+    siege: Option<Res<SiegeBattle>>,
+    rng: Option<ResMut<GlobalEntropy<WyRand>>>,
+) {
+    let seed = rng.map_or(0, |mut rng| {
+        let seed = rng.next_u64();
+        rng.reseed(seed.to_le_bytes());
+        seed
+    });
+    commands.insert_resource(ReplayRecorder {
+        replay: Replay {
+            version: REPLAY_VERSION,
+            seed,
+            setup: setup.clone(),
+            siege_engines: siege.map(|siege| siege.engines.clone()),
+            deployment: Vec::new(),
+            formations: Vec::new(),
+            frames: Vec::new(),
+        },
+        decisions: HashMap::new(),
+    });
+}
+
+/// Takes over the rng, the clock and the player's controls for playback
+pub(super) fn begin_playback(
+    mut commands: Commands,
+    mut playback: ResMut<ReplayPlayback>,
+    rng: Option<ResMut<GlobalEntropy<WyRand>>>,
+    time_strategy: Option<ResMut<TimeUpdateStrategy>>,
+    players: Query<Entity, (With<Player>, With<InputMap<CombatAction>>)>,
+) {
+    if let Some(mut rng) = rng {
+        rng.reseed(playback.replay.seed.to_le_bytes());
+    }
+    // Without an input map the devices can't overwrite what the replay presses
+    for player in players.iter() {
+        commands.entity(player).remove::<InputMap<CombatAction>>();
+    }
+    if let Some(mut strategy) = time_strategy {
+        let delta = playback.replay.frames.first().map_or(0, |frame| frame.delta_nanos);
+        let previous = std::mem::replace(&mut *strategy, TimeUpdateStrategy::ManualDuration(Duration::from_nanos(delta)));
+        playback.previous_time_strategy = Some(previous);
+    }
+}
+
+/// The recorded deployment is restored as the fighting starts, so there's
+/// nothing to arrange
+pub(super) fn skip_deployment(mut ready: EventWriter<DeploymentReady>) {
+    ready.send(DeploymentReady);
+}
+
+pub(super) fn record_deployment(
+    mut recorder: ResMut<ReplayRecorder>,
+    formations: Res<Formations>,
+    units: Query<(&ReplayId, &Transform, Option<&FormationGroup>)>,
+) {
+    let mut deployment: Vec<DeployedUnit> = units
+        .iter()
+        .map(|(id, transform, group)| DeployedUnit {
+            id: *id,
+            translation: transform.translation,
+            rotation: transform.rotation,
+            group: group.copied(),
+        })
+        .collect();
+    deployment.sort_by_key(|unit| unit.id.0);
+    recorder.replay.deployment = deployment;
+    recorder.replay.formations = formations
+        .iter()
+        .map(|(side, group, formation)| (side, group, formation.clone()))
+        .collect();
+}
+
+pub(super) fn restore_deployment(
+    mut commands: Commands,
+    playback: Res<ReplayPlayback>,
+    mut formations: ResMut<Formations>,
+    mut units: Query<(Entity, &ReplayId, &mut Transform)>,
+) {
+    let deployment: HashMap<ReplayId, &DeployedUnit> =
+        playback.replay.deployment.iter().map(|unit| (unit.id, unit)).collect();
+    for (entity, id, mut transform) in units.iter_mut() {
+        let Some(unit) = deployment.get(id) else {
+            continue;
+        };
+        transform.translation = unit.translation;
+        transform.rotation = unit.rotation;
+        // Nobody arrives at their post at a run
+        commands.entity(entity).insert(Momentum::resting_at(unit.translation));
+        if let Some(group) = unit.group {
+            commands.entity(entity).insert(group);
+        }
+    }
+    formations.clear();
+    for (side, group, formation) in &playback.replay.formations {
+        formations.insert(*side, *group, formation.clone());
+    }
+}
+
+/// Writes down the frame just played: its length, the player's controls, the
+/// orders given and whichever AI decisions changed
+pub(super) fn record_frame(
+    time: Res<Time>,
+    mut recorder: ResMut<ReplayRecorder>,
+    mut orders: EventReader<IssueOrder>,
+    players: Query<&ActionState<CombatAction>, With<Player>>,
+    units: Query<(Entity, &ReplayId, Option<&CombatAIState>)>,
+) {
+    let mut frame = ReplayFrame {
+        delta_nanos: time.delta().as_nanos() as u64,
+        orders: orders.read().cloned().collect(),
+        ..default()
+    };
+    if let Ok(actions) = players.get_single() {
+        frame.held = CombatAction::BUTTONS.into_iter().filter(|action| actions.pressed(action)).collect();
+        frame.movement = actions.clamped_axis_pair(&CombatAction::Move);
+        frame.turn = actions.clamped_value(&CombatAction::Turn);
+    }
+
+    let ids: HashMap<Entity, ReplayId> = units.iter().map(|(entity, id, _)| (entity, *id)).collect();
+    let mut decided: Vec<(ReplayId, AiDecision)> = units
+        .iter()
+        .filter_map(|(_, id, state)| state.map(|state| (*id, state.decision)))
+        .collect();
+    decided.sort_by_key(|(id, _)| id.0);
+    for (id, decision) in decided {
+        let Some(recorded) = RecordedDecision::record(decision, &ids) else {
+            continue;
+        };
+        if recorder.decisions.get(&id) != Some(&recorded) {
+            recorder.decisions.insert(id, recorded);
+            frame.decisions.push((id, recorded));
+        }
+    }
+    recorder.replay.frames.push(frame);
+}
+
+/// Feeds the next recorded frame back in: the player's controls, their orders
+/// and the AI's decisions, then sets the clock up for the frame after
+pub(super) fn play_back_frame(
+    mut commands: Commands,
+    mut playback: ResMut<ReplayPlayback>,
+    time_strategy: Option<ResMut<TimeUpdateStrategy>>,
+    mut orders: EventWriter<IssueOrder>,
+    mut players: Query<&mut ActionState<CombatAction>, With<Player>>,
+    mut units: Query<(Entity, &ReplayId, Option<&mut CombatAIState>)>,
+) {
+    let Some(frame) = playback.replay.frames.get(playback.frame).cloned() else {
+        // Out of recorded frames with the battle still going; let go of everything
+        for mut actions in players.iter_mut() {
+            for action in CombatAction::BUTTONS {
+                actions.release(&action);
+            }
+            actions.set_axis_pair(&CombatAction::Move, Vec2::ZERO);
+            actions.set_value(&CombatAction::Turn, 0.0);
+        }
+        return;
+    };
+    playback.frame += 1;
+
+    for mut actions in players.iter_mut() {
+        for action in CombatAction::BUTTONS {
+            if frame.held.contains(&action) {
+                actions.press(&action);
+            } else {
+                actions.release(&action);
+            }
+        }
+        actions.set_axis_pair(&CombatAction::Move, frame.movement);
+        actions.set_value(&CombatAction::Turn, frame.turn);
+    }
+    for order in frame.orders {
+        orders.send(order);
+    }
+
+    let entities: HashMap<ReplayId, Entity> = units.iter().map(|(entity, id, _)| (*id, entity)).collect();
+    for (id, recorded) in frame.decisions {
+        let (Some(&entity), Some(decision)) = (entities.get(&id), recorded.restore(&entities)) else {
+            continue;
+        };
+        let Ok((_, _, state)) = units.get_mut(entity) else {
+            continue;
+        };
+        match state {
+            Some(mut state) => state.decision = decision,
+            None => {
+                commands.entity(entity).insert(CombatAIState { decision, ..default() });
+            }
+        }
+    }
+
+    if let (Some(mut strategy), Some(next)) = (time_strategy, playback.replay.frames.get(playback.frame)) {
+        *strategy = TimeUpdateStrategy::ManualDuration(Duration::from_nanos(next.delta_nanos));
+    }
+}
+
+/// Writes the finished battle's replay next to the saves
+pub(super) fn save_recording(recorder: Res<ReplayRecorder>, config: Option<Res<SaveGameConfig>>) {
+    // Only a game with somewhere to save keeps replays
+    let Some(config) = config else {
+        return;
+    };
+    let directory = config.save_directory.join(REPLAY_DIRECTORY);
+    let stamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+    let path = directory.join(format!("battle_{stamp}.{REPLAY_EXTENSION}"));
+    match fs::create_dir_all(&directory).and_then(|_| recorder.replay.save(&path)) {
+        Ok(()) => info!("Battle replay saved to {}", path.display()),
+        Err(error) => error!("Failed to save battle replay: {}", error),
+    }
+}
+
+/// A replay's results change nothing; dismissing them goes back to wherever
+/// playback was started from
+pub(super) fn finish_playback(
+    mut commands: Commands,
+    mut confirmations: EventReader<AftermathConfirmed>,
+    playback: Res<ReplayPlayback>,
+    mut next_game_state: ResMut<NextState<GameState>>,
+) {
+    if confirmations.read().last().is_none() {
+        return;
+    }
+    commands.remove_resource::<BattleAftermath>();
+    commands.remove_resource::<BattleSetup>();
+    next_game_state.set(playback.return_to.clone());
+}
+
+pub(super) fn clear_replay(
+    mut commands: Commands,
+    playback: Option<ResMut<ReplayPlayback>>,
+    time_strategy: Option<ResMut<TimeUpdateStrategy>>,
+) {
+    if let (Some(mut playback), Some(mut strategy)) = (playback, time_strategy) {
+        if let Some(previous) = playback.previous_time_strategy.take() {
+            *strategy = previous;
+        }
+    }
+    commands.remove_resource::<ReplayRecorder>();
+    commands.remove_resource::<ReplayPlayback>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use leafwing_input_manager::prelude::*;
+    use crate::core::{Health, WeaponType};
+    use crate::plugins::{BattleLeader, FormationCommand};
+    use crate::test_support::{combat_app, test_party, hero_stats, run_test_app};
+
+    // Health and position of everyone on the field, in replay order
+    fn field_snapshot(app: &mut App) -> Vec<(u32, f32, Vec3)> {
+        let world = app.world_mut();
+        let mut units: Vec<(u32, f32, Vec3)> = world
+            .query::<(&ReplayId, &Health, &Transform)>()
+            .iter(world)
+            .map(|(id, health, transform)| (id.0, health.current, transform.translation))
+            .collect();
+        units.sort_by_key(|unit| unit.0);
+        units
+    }
+
+    #[test]
+    fn test_replay_plays_battle_back_the_same() {
+        let mut app = combat_app();
+        let mut player_party = test_party("player", 4, 2);
+        player_party.leader = Some(BattleLeader {
+            name: "Hero".to_string(),
+            stats: hero_stats(),
+            weapon_type: WeaponType::OneHandedSword,
+            is_player: true,
+        });
+        app.insert_resource(BattleSetup {
+            attacker: player_party,
+            defender: test_party("looters", 6, 0),
+            field_size: 60.0,
+        });
+        app.world_mut().resource_mut::<NextState<GameState>>().set(GameState::Combat);
+        run_test_app(&mut app, 2);
+        app.world_mut().send_event(DeploymentReady);
+        run_test_app(&mut app, 2);
+
+        // Run at the enemy swinging, and send everyone else in after a while
+        KeyCode::KeyW.press(app.world_mut());
+        run_test_app(&mut app, 40);
+        KeyCode::ArrowRight.press(app.world_mut());
+        run_test_app(&mut app, 20);
+        KeyCode::ArrowRight.release(app.world_mut());
+        app.world_mut().send_event(IssueOrder {
+            side: BattleSide::Attacker,
+            group: None,
+            command: FormationCommand::Charge,
+        });
+        run_test_app(&mut app, 300);
+        let recorded = field_snapshot(&mut app);
+
+        let replay = app.world().resource::<ReplayRecorder>().replay.clone();
+        assert!(replay.frames.iter().any(|frame| !frame.held.is_empty()));
+        assert!(replay.frames.iter().any(|frame| !frame.orders.is_empty()));
+        assert!(replay.frames.iter().any(|frame| !frame.decisions.is_empty()));
+        assert_eq!(replay.deployment.len(), recorded.len());
+
+        let path = std::env::temp_dir().join("test_replay_plays_battle_back_the_same.replay");
+        replay.save(&path).unwrap();
+        let replay = Replay::load(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        // One frame to pick the replay up and one to deploy, then every recorded frame
+        let mut playback = combat_app();
+        let frames = replay.frames.len();
+        playback.world_mut().send_event(PlayReplay { replay });
+        run_test_app(&mut playback, 2 + frames);
+        assert_eq!(field_snapshot(&mut playback), recorded);
+    }
+
+    #[test]
+    fn test_replay_requested_on_command_line() {
+        let args = |line: &str| line.split(' ').map(str::to_string).collect::<Vec<_>>().into_iter();
+        let config = SaveGameConfig { save_directory: PathBuf::from("saves") };
+
+        assert_eq!(requested_replay_path(args("--windowed"), Some(&config)), None);
+        assert_eq!(requested_replay_path(args("--replay"), Some(&config)), None);
+        // A bare name is one of the saved replays
+        assert_eq!(
+            requested_replay_path(args("--replay battle_7.replay"), Some(&config)),
+            Some(PathBuf::from("saves").join(REPLAY_DIRECTORY).join("battle_7.replay")),
+        );
+        assert_eq!(
+            requested_replay_path(args("--replay battle_7.replay"), None),
+            Some(PathBuf::from("battle_7.replay")),
+        );
+    }
+}
@@ -0,0 +1,365 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::assets::GameAssets;
+use crate::core::components::{
+    Health, Stamina, Weapon, WeaponType, CombatAI, CharacterStats, CharacterController, Player,
+    Faction, BattleSide, Morale, FormationGroup, Shield, Ammo, TroopRoster, WeaponLoadout,
+};
+use crate::core::states::GameState;
+
+use super::WeaponRules;
+use super::input::player_input_bundle;
+use super::mounts::troop_mount;
+use super::armor::troop_armor;
+use super::animation::CombatAnimation;
+use super::replay::ReplayId;
+use super::deployment::{DeploymentZone, DeploymentZones};
+
+pub const DEFAULT_FIELD_SIZE: f32 = 300.0;
+/// Half the height of a soldier's capsule collider, not counting the caps
+pub const UNIT_HALF_HEIGHT: f32 = 0.6;
+pub const UNIT_RADIUS: f32 = 0.35;
+pub const UNIT_MORALE: f32 = 50.0;
+pub const RANGED_AMMO: u32 = 24;
+pub const SHIELD_DURABILITY: f32 = 120.0;
+/// Tier of the gear party leaders carry
+pub const LEADER_TIER: u8 = 6;
+
+/// A party leader who takes the field in person
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BattleLeader {
+    pub name: String,
+    pub stats: CharacterStats,
+    pub weapon_type: WeaponType,
+    pub is_player: bool,
+}
+
+/// One party's contribution to a battle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BattleParty {
+    pub faction: Faction,
+    pub roster: TroopRoster,
+    pub leader: Option<BattleLeader>,
+    /// The party's entity on the world map, which receives the battle's consequences
+    #[serde(skip)]
+    pub party_entity: Option<Entity>,
+}
+
+/// Everything `setup_combat_scene` needs to build a battle. The world map
+/// fills this in on an encounter; tests can hand-craft one.
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+pub struct BattleSetup {
+    pub attacker: BattleParty,
+    pub defender: BattleParty,
+    /// Width and depth of the square battlefield in metres
+    pub field_size: f32,
+}
+
+impl BattleSetup {
+    pub fn party(&self, side: BattleSide) -> &BattleParty {
+        match side {
+            BattleSide::Attacker => &self.attacker,
+            BattleSide::Defender => &self.defender,
+        }
+    }
+}
+
+/// The ground everyone fights on
+#[derive(Component, Debug, Clone)]
+pub struct BattleTerrain;
+
+/// Where a side's troops enter the field
+#[derive(Component, Debug, Clone)]
+pub struct SpawnPoint {
+    pub side: BattleSide,
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct CombatCamera;
+
+/// Weapon issued to a troop of the given type, better forged at higher tiers
+pub fn troop_weapon(weapon_type: WeaponType, tier: u8) -> Weapon {
+    let (damage, speed, reach) = match weapon_type {
+        WeaponType::OneHandedSword => (20.0, 1.0, 1.5),
+        WeaponType::TwoHandedSword => (30.0, 0.8, 1.9),
+        WeaponType::Spear => (22.0, 0.9, 2.2),
+        WeaponType::Bow => (18.0, 1.0, 1.0),
+        WeaponType::Crossbow => (22.0, 1.0, 1.0),
+        WeaponType::Shield => (8.0, 1.1, 1.2),
+    };
+    Weapon {
+        damage: damage * (1.0 + tier as f32 * 0.1),
+        speed,
+        reach,
+        weapon_type,
+    }
+}
+
+/// Formation a troop deploys with unless reassigned
+pub fn default_formation_group(weapon_type: WeaponType) -> FormationGroup {
+    match weapon_type {
+        WeaponType::Bow | WeaponType::Crossbow => FormationGroup::Archers,
+        _ => FormationGroup::Infantry,
+    }
+}
+
+/// Deployment zones at either end of a field of the given size
+pub fn deployment_zones_for(field_size: f32) -> DeploymentZones {
+    let depth = field_size / 4.0;
+    let zone = |center_z: f32, facing: Vec3| DeploymentZone {
+        center: Vec3::new(0.0, 0.0, center_z),
+        half_width: field_size / 4.0,
+        half_depth: depth / 3.0,
+        facing,
+    };
+    DeploymentZones {
+        attacker: zone(depth, Vec3::NEG_Z),
+        defender: zone(-depth, Vec3::Z),
+    }
+}
+
+pub(super) fn setup_combat_scene(
+    mut commands: Commands,
+    setup: Option<Res<BattleSetup>>,
+    rules: Res<WeaponRules>,
+    game_assets: Option<Res<GameAssets>>,
+) {
+    info!("Setting up combat scene");
+    let Some(setup) = setup else {
+        warn!("Entered combat without a BattleSetup, the field is empty");
+        return;
+    };
+
+    // Battlefield terrain
+    let half_size = setup.field_size / 2.0;
+    commands.spawn((
+        BattleTerrain,
+        StateScoped(GameState::Combat),
+        Transform::from_xyz(0.0, -0.5, 0.0),
+        RigidBody::Fixed,
+        Collider::cuboid(half_size, 0.5, half_size),
+    ));
+
+    let zones = deployment_zones_for(setup.field_size);
+    let model = game_assets.and_then(|assets| assets.character_models.first().cloned());
+    // Numbered in spawn order, which only depends on the setup
+    let mut next_id = 0;
+
+    for side in [BattleSide::Attacker, BattleSide::Defender] {
+        let zone = zones.get(side);
+        commands.spawn((
+            SpawnPoint { side },
+            StateScoped(GameState::Combat),
+            Transform::from_translation(zone.center),
+        ));
+
+        let party = setup.party(side);
+        let mut spawned = 0;
+        let mut spawn = |commands: &mut Commands, weapon_type: WeaponType, tier: u8| {
+            // Lined up behind the spawn point; deployment sorts them into formations
+            let offset = Vec3::new((spawned % 20) as f32 - 10.0, 0.0, (spawned / 20) as f32) * 1.5;
+            spawned += 1;
+            let position = zone.center - zone.facing * offset.z + zone.facing.cross(Vec3::Y) * offset.x;
+            let entity = spawn_soldier(commands, &rules, party, side, weapon_type, tier, position);
+            commands.entity(entity).insert(ReplayId(next_id));
+            next_id += 1;
+            if let Some(model) = &model {
+                commands.entity(entity).insert(SceneRoot(model.clone()));
+            }
+            entity
+        };
+
+        if let Some(leader) = &party.leader {
+            let entity = spawn(&mut commands, leader.weapon_type, LEADER_TIER);
+            commands.entity(entity).insert((
+                Name::new(leader.name.clone()),
+                leader.stats.clone(),
+                CharacterController {
+                    movement_speed: 4.0,
+                    rotation_speed: 3.0,
+                },
+            ));
+            // The player fights by hand and on their own nerve, everyone else is
+            // driven by the AI
+            if leader.is_player {
+                let sidearm = match leader.weapon_type {
+                    WeaponType::OneHandedSword => WeaponType::Spear,
+                    _ => WeaponType::OneHandedSword,
+                };
+                commands
+                    .entity(entity)
+                    .insert((
+                        Player,
+                        player_input_bundle(),
+                        WeaponLoadout {
+                            slots: vec![
+                                troop_weapon(leader.weapon_type, LEADER_TIER),
+                                troop_weapon(sidearm, LEADER_TIER),
+                            ],
+                            equipped: 0,
+                        },
+                    ))
+                    .remove::<(CombatAI, Morale)>();
+            }
+        }
+
+        for stack in &party.roster.stacks {
+            for _ in 0..stack.count.saturating_sub(stack.wounded) {
+                let entity = spawn(&mut commands, stack.weapon_type, stack.tier);
+                if stack.mounted {
+                    commands.entity(entity).insert((troop_mount(stack.tier), FormationGroup::Cavalry));
+                }
+            }
+        }
+    }
+
+    // Combat camera looking down the field from behind the attackers
+    commands.spawn((
+        CombatCamera,
+        StateScoped(GameState::Combat),
+        Camera3d::default(),
+        Camera {
+            order: 1,
+            ..default()
+        },
+        Transform::from_xyz(0.0, setup.field_size / 6.0, setup.field_size / 2.0)
+            .looking_at(Vec3::ZERO, Vec3::Y),
+    ));
+
+    commands.insert_resource(zones);
+}
+
+fn spawn_soldier(
+    commands: &mut Commands,
+    rules: &WeaponRules,
+    party: &BattleParty,
+    side: BattleSide,
+    weapon_type: WeaponType,
+    tier: u8,
+    position: Vec3,
+) -> Entity {
+    let weapon = troop_weapon(weapon_type, tier);
+    let rule = rules.get(weapon_type);
+    let max_health = 80.0 + tier as f32 * 10.0;
+    let preferred_distance = match &rule.ranged {
+        Some(ranged) => ranged.max_range * 0.5,
+        None => (weapon.reach + rule.reach_bonus) * 0.8,
+    };
+
+    let mut soldier = commands.spawn((
+        Health { current: max_health, max: max_health },
+        Stamina { current: 100.0, max: 100.0, recovery_rate: 5.0 },
+        CombatAI {
+            aggression: (0.4 + tier as f32 * 0.1).min(0.9),
+            preferred_distance,
+        },
+        party.faction.clone(),
+        side,
+        default_formation_group(weapon_type),
+        Morale { current: UNIT_MORALE, max: UNIT_MORALE },
+        StateScoped(GameState::Combat),
+        Transform::from_translation(position + Vec3::Y * (UNIT_HALF_HEIGHT + UNIT_RADIUS)),
+        RigidBody::KinematicPositionBased,
+        Collider::capsule_y(UNIT_HALF_HEIGHT, UNIT_RADIUS),
+        weapon,
+        troop_armor(tier),
+        CombatAnimation::default(),
+    ));
+    if rule.ranged.is_some() {
+        soldier.insert(Ammo { current: RANGED_AMMO, max: RANGED_AMMO });
+    }
+    // Sword-and-board troops and dedicated shield bearers carry a shield
+    if matches!(weapon_type, WeaponType::Shield) || (matches!(weapon_type, WeaponType::OneHandedSword) && tier >= 2) {
+        soldier.insert(Shield { durability: SHIELD_DURABILITY, max_durability: SHIELD_DURABILITY });
+    }
+    soldier.id()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::FireProjectile;
+    use crate::test_support::{combat_app, combat_test_app, start_battle, test_party, run_test_app};
+
+    #[test]
+    fn test_battle_scene_built_from_setup() {
+        let mut app = combat_app();
+        let mut player_party = test_party("player", 5, 3);
+        player_party.leader = Some(BattleLeader {
+            name: "Hero".to_string(),
+            stats: CharacterStats {
+                strength: 12,
+                agility: 10,
+                intelligence: 10,
+                charisma: 10,
+                level: 3,
+                experience: 0,
+            },
+            weapon_type: WeaponType::TwoHandedSword,
+            is_player: true,
+        });
+        app.insert_resource(BattleSetup {
+            attacker: player_party,
+            defender: test_party("looters", 10, 0),
+            field_size: 200.0,
+        });
+        app.world_mut().resource_mut::<NextState<GameState>>().set(GameState::Combat);
+        run_test_app(&mut app, 2);
+
+        let world = app.world_mut();
+        let mut sides = world.query::<(&BattleSide, &Health)>();
+        let attackers = sides.iter(world).filter(|(side, _)| **side == BattleSide::Attacker).count();
+        let defenders = sides.iter(world).filter(|(side, _)| **side == BattleSide::Defender).count();
+        // Leader + 5 infantry + 3 archers less 1 wounded
+        assert_eq!(attackers, 8);
+        assert_eq!(defenders, 10);
+
+        assert_eq!(world.query_filtered::<Entity, With<Player>>().iter(world).count(), 1);
+        assert_eq!(world.query_filtered::<Entity, (With<Player>, With<CombatAI>)>().iter(world).count(), 0);
+        assert_eq!(world.query_filtered::<Entity, (With<Player>, With<Morale>)>().iter(world).count(), 0);
+        assert_eq!(world.query::<&BattleTerrain>().iter(world).count(), 1);
+        assert_eq!(world.query::<&CombatCamera>().iter(world).count(), 1);
+        assert_eq!(world.query::<(&Ammo, &BattleSide)>().iter(world).count(), 2);
+    }
+
+    #[test]
+    fn test_no_entities_leak_across_battles() {
+        let mut app = combat_test_app();
+        app.world_mut().resource_mut::<NextState<GameState>>().set(GameState::WorldMap);
+        run_test_app(&mut app, 2);
+        let baseline = app.world().entities().len();
+
+        for _ in 0..3 {
+            app.insert_resource(BattleSetup {
+                attacker: test_party("vlandia", 5, 3),
+                defender: test_party("looters", 8, 2),
+                field_size: 200.0,
+            });
+            app.world_mut().resource_mut::<NextState<GameState>>().set(GameState::Combat);
+            run_test_app(&mut app, 2);
+            start_battle(&mut app);
+
+            // Leave some arrows in flight as well
+            let mut sides = app.world_mut().query::<(Entity, &BattleSide, Has<Ammo>)>();
+            let sides: Vec<_> = sides.iter(app.world()).map(|(entity, side, ranged)| (entity, *side, ranged)).collect();
+            let (target, ..) = sides.iter().find(|(_, side, _)| *side == BattleSide::Defender).copied().unwrap();
+            let shots: Vec<FireProjectile> = sides
+                .iter()
+                .filter(|(_, side, ranged)| *side == BattleSide::Attacker && *ranged)
+                .map(|(shooter, ..)| FireProjectile { shooter: *shooter, target, damage: 1.0, speed: 40.0 })
+                .collect();
+            assert!(!shots.is_empty());
+            for shot in shots {
+                app.world_mut().send_event(shot);
+            }
+            run_test_app(&mut app, 2);
+            assert!(app.world().entities().len() > baseline);
+
+            app.world_mut().resource_mut::<NextState<GameState>>().set(GameState::WorldMap);
+            run_test_app(&mut app, 2);
+            assert_eq!(app.world().entities().len(), baseline);
+        }
+    }
+}
@@ -0,0 +1,482 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::core::components::{
+    Health, BattleSide, FormationGroup, CharacterController, SiegeEngine, Settlement, Besieging,
+    UnderSiege,
+};
+use crate::core::states::{GameState, BattleResult};
+
+use super::AftermathConfirmed;
+use super::ai::DEFAULT_AI_MOVE_SPEED;
+use super::scene::{BattleSetup, UNIT_HALF_HEIGHT, UNIT_RADIUS};
+
+pub const WALL_HEIGHT: f32 = 6.0;
+pub const WALL_THICKNESS: f32 = 2.0;
+pub const GATE_HALF_WIDTH: f32 = 2.5;
+pub const GATE_HEALTH: f32 = 800.0;
+/// Damage each blow of the ram does to the gate
+pub const RAM_DAMAGE: f32 = 80.0;
+/// Seconds between blows of the ram
+pub const RAM_INTERVAL: f32 = 2.5;
+pub const RAM_SPEED: f32 = 1.5;
+pub const TOWER_SPEED: f32 = 1.0;
+/// Damage per second each attacker hacking at the gate does without a ram
+pub const GATE_HACK_DAMAGE: f32 = 2.0;
+/// Half the width of the stretch of wall a ladder lets troops over
+pub const LADDER_HALF_WIDTH: f32 = 1.0;
+/// Half the width of the stretch of wall a docked tower lets troops over
+pub const TOWER_HALF_WIDTH: f32 = 2.0;
+/// Ladders go up at these shares of the field width either side of the gate
+const LADDER_POSITIONS: [f32; 4] = [-0.15, -0.05, 0.05, 0.15];
+
+/// A battle fought over a settlement's walls. The world map inserts this
+/// alongside the `BattleSetup` when a besieger storms a settlement.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct SiegeBattle {
+    pub settlement: Option<Entity>,
+    pub besieger: Option<Entity>,
+    /// Engines the attackers built before the assault
+    pub engines: Vec<SiegeEngine>,
+    /// Filled in once the battle is decided
+    pub winner: Option<BattleSide>,
+    /// Garrison troops who fell
+    pub defenders_fallen: u32,
+}
+
+/// Where the walls stand on the field
+#[derive(Resource, Debug, Clone)]
+pub struct SiegeWalls {
+    /// Z coordinate of the middle of the wall; the defenders are behind it
+    pub line: f32,
+}
+
+impl SiegeWalls {
+    pub fn outer_face(&self) -> f32 {
+        self.line + WALL_THICKNESS / 2.0
+    }
+
+    pub fn inner_face(&self) -> f32 {
+        self.line - WALL_THICKNESS / 2.0
+    }
+}
+
+/// A stretch of curtain wall
+#[derive(Component, Debug, Clone)]
+pub struct Fortification;
+
+#[derive(Component, Debug, Clone)]
+pub struct Gate {
+    pub health: f32,
+    pub max_health: f32,
+}
+
+#[derive(Component, Debug, Clone, Default)]
+pub struct BatteringRam {
+    /// Time until the next blow
+    pub cooldown: f32,
+}
+
+#[derive(Component, Debug, Clone, Default)]
+pub struct SiegeTower {
+    /// Against the wall with its bridge down
+    pub docked: bool,
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct SiegeLadder;
+
+/// Defender posted on the wall walk
+#[derive(Component, Debug, Clone)]
+pub struct OnRampart;
+
+/// The gate gave way
+#[derive(Event, Debug, Clone)]
+pub struct GateBreached {
+    pub gate: Entity,
+}
+
+/// Where the walls stand on a field of the given size: halfway between the
+/// middle and the defenders' deployment zone
+pub fn wall_line(field_size: f32) -> f32 {
+    -field_size / 8.0
+}
+
+/// Stretches of wall troops can cross, as centre and half width along x
+pub fn breaches<'a>(
+    gates: impl IntoIterator<Item = (&'a Gate, &'a Transform)>,
+    ladders: impl IntoIterator<Item = &'a Transform>,
+    towers: impl IntoIterator<Item = (&'a SiegeTower, &'a Transform)>,
+) -> Vec<(f32, f32)> {
+    let mut open = Vec::new();
+    open.extend(
+        gates
+            .into_iter()
+            .filter(|(gate, _)| gate.health <= 0.0)
+            .map(|(_, transform)| (transform.translation.x, GATE_HALF_WIDTH)),
+    );
+    open.extend(ladders.into_iter().map(|transform| (transform.translation.x, LADDER_HALF_WIDTH)));
+    open.extend(
+        towers
+            .into_iter()
+            .filter(|(tower, _)| tower.docked)
+            .map(|(_, transform)| (transform.translation.x, TOWER_HALF_WIDTH)),
+    );
+    open
+}
+
+/// Builds the walls and gate in front of the defenders and brings up the
+/// attackers' engines
+pub(super) fn setup_fortifications(
+    mut commands: Commands,
+    setup: Res<BattleSetup>,
+    siege: Res<SiegeBattle>,
+) {
+    let field_size = setup.field_size;
+    let walls = SiegeWalls { line: wall_line(field_size) };
+    let half_field = field_size / 2.0;
+    let half_height = WALL_HEIGHT / 2.0;
+
+    // Two stretches of wall either side of the gate, running the width of the field
+    let segment_half_width = (half_field - GATE_HALF_WIDTH) / 2.0;
+    for side in [-1.0, 1.0] {
+        commands.spawn((
+            Fortification,
+            StateScoped(GameState::Combat),
+            Transform::from_xyz(side * (GATE_HALF_WIDTH + segment_half_width), half_height, walls.line),
+            RigidBody::Fixed,
+            Collider::cuboid(segment_half_width, half_height, WALL_THICKNESS / 2.0),
+        ));
+    }
+    commands.spawn((
+        Gate { health: GATE_HEALTH, max_health: GATE_HEALTH },
+        StateScoped(GameState::Combat),
+        Transform::from_xyz(0.0, half_height, walls.line),
+        RigidBody::Fixed,
+        Collider::cuboid(GATE_HALF_WIDTH, half_height, WALL_THICKNESS / 2.0),
+    ));
+
+    // Engines start at the front of the attackers' lines
+    let start = field_size / 6.0;
+    if siege.engines.contains(&SiegeEngine::Ladders) {
+        for share in LADDER_POSITIONS {
+            commands.spawn((
+                SiegeLadder,
+                StateScoped(GameState::Combat),
+                Transform::from_xyz(share * field_size, half_height, walls.outer_face()),
+            ));
+        }
+    }
+    if siege.engines.contains(&SiegeEngine::Ram) {
+        commands.spawn((
+            BatteringRam::default(),
+            StateScoped(GameState::Combat),
+            Transform::from_xyz(0.0, 0.0, start),
+        ));
+    }
+    let towers = siege.engines.iter().filter(|engine| **engine == SiegeEngine::SiegeTower).count();
+    for index in 0..towers {
+        // Alternate sides of the gate, each pair further out than the last
+        let side = if index % 2 == 0 { -1.0 } else { 1.0 };
+        let x = side * field_size / 8.0 * (1.0 + (index / 2) as f32 * 0.5);
+        commands.spawn((
+            SiegeTower::default(),
+            StateScoped(GameState::Combat),
+            Transform::from_xyz(x, 0.0, start),
+        ));
+    }
+
+    commands.insert_resource(walls);
+}
+
+/// Sends the defenders' archers up onto the wall walk as the fighting starts
+pub(super) fn man_ramparts(
+    mut commands: Commands,
+    walls: Res<SiegeWalls>,
+    setup: Option<Res<BattleSetup>>,
+    mut archers: Query<(Entity, &mut Transform, &Health, &BattleSide, &FormationGroup)>,
+) {
+    let mut posted: Vec<_> = archers
+        .iter_mut()
+        .filter(|(_, _, health, side, group)| {
+            health.current > 0.0 && **side == BattleSide::Defender && **group == FormationGroup::Archers
+        })
+        .collect();
+    if posted.is_empty() {
+        return;
+    }
+    // Spread them along the middle half of the wall
+    let span = setup.map_or(100.0, |setup| setup.field_size / 2.0);
+    let spacing = span / posted.len() as f32;
+    posted.sort_by_key(|(entity, ..)| *entity);
+    for (index, (entity, transform, ..)) in posted.iter_mut().enumerate() {
+        let x = -span / 2.0 + spacing * (index as f32 + 0.5);
+        transform.translation = Vec3::new(x, WALL_HEIGHT + UNIT_HALF_HEIGHT + UNIT_RADIUS, walls.line);
+        commands.entity(*entity).insert(OnRampart);
+    }
+}
+
+/// Rolls rams and towers up to the wall, swings the ram against the gate and
+/// lets attackers without one hack at it. A gate at zero health is thrown open.
+pub(super) fn advance_siege_engines(
+    mut commands: Commands,
+    time: Res<Time>,
+    walls: Res<SiegeWalls>,
+    mut breached: EventWriter<GateBreached>,
+    mut rams: Query<(&mut Transform, &mut BatteringRam), (Without<SiegeTower>, Without<Gate>)>,
+    mut towers: Query<(&mut Transform, &mut SiegeTower), (Without<BatteringRam>, Without<Gate>)>,
+    mut gates: Query<(Entity, &Transform, &mut Gate)>,
+    attackers: Query<(&Transform, &Health, &BattleSide), (Without<Gate>, Without<BatteringRam>, Without<SiegeTower>)>,
+) {
+    let delta = time.delta_secs();
+    let outer_face = walls.outer_face();
+
+    for (mut transform, mut tower) in towers.iter_mut() {
+        if tower.docked {
+            continue;
+        }
+        let docked_at = outer_face + TOWER_HALF_WIDTH;
+        transform.translation.z = (transform.translation.z - TOWER_SPEED * delta).max(docked_at);
+        tower.docked = transform.translation.z <= docked_at;
+    }
+
+    for (entity, gate_transform, mut gate) in gates.iter_mut() {
+        if gate.health <= 0.0 {
+            continue;
+        }
+        let gate_x = gate_transform.translation.x;
+
+        let mut damage = 0.0;
+        for (mut transform, mut ram) in rams.iter_mut() {
+            let target = Vec3::new(gate_x, 0.0, outer_face + 1.0);
+            let offset = target - transform.translation;
+            if offset.length() > 0.01 {
+                transform.translation += offset.normalize() * (RAM_SPEED * delta).min(offset.length());
+                continue;
+            }
+            ram.cooldown -= delta;
+            if ram.cooldown <= 0.0 {
+                damage += RAM_DAMAGE;
+                ram.cooldown = RAM_INTERVAL;
+            }
+        }
+        let hacking = attackers
+            .iter()
+            .filter(|(transform, health, side)| {
+                health.current > 0.0
+                    && **side == BattleSide::Attacker
+                    && (transform.translation.x - gate_x).abs() <= GATE_HALF_WIDTH
+                    && (transform.translation.z - outer_face).abs() <= 1.0
+            })
+            .count();
+        damage += hacking as f32 * GATE_HACK_DAMAGE * delta;
+
+        gate.health = (gate.health - damage).max(0.0);
+        if gate.health <= 0.0 {
+            commands.entity(entity).remove::<Collider>();
+            breached.send(GateBreached { gate: entity });
+        }
+    }
+}
+
+/// Nobody walks through a wall: anyone who steps into it away from a breach
+/// is put back on their side and edges along it towards the nearest opening.
+/// Men on the ramparts stay on the wall walk, everyone else on the ground.
+pub(super) fn enforce_walls(
+    time: Res<Time>,
+    walls: Res<SiegeWalls>,
+    mut units: Query<(&mut Transform, &Health, Option<&CharacterController>, Has<OnRampart>)>,
+    gates: Query<(&Gate, &Transform), Without<Health>>,
+    ladders: Query<&Transform, (With<SiegeLadder>, Without<Health>)>,
+    towers: Query<(&SiegeTower, &Transform), Without<Health>>,
+) {
+    let open = breaches(gates.iter(), ladders.iter(), towers.iter());
+    let (inner, outer) = (walls.inner_face(), walls.outer_face());
+    let standing = UNIT_HALF_HEIGHT + UNIT_RADIUS;
+
+    for (mut transform, health, controller, on_rampart) in units.iter_mut() {
+        if health.current <= 0.0 {
+            continue;
+        }
+        let position = &mut transform.translation;
+        if on_rampart {
+            position.y = WALL_HEIGHT + standing;
+            position.z = position.z.clamp(inner, outer);
+            continue;
+        }
+        position.y = standing;
+        if position.z <= inner || position.z >= outer {
+            continue;
+        }
+        let nearest = open
+            .iter()
+            .map(|(x, half_width)| (x, half_width, (position.x - x).abs()))
+            .min_by(|a, b| a.2.total_cmp(&b.2));
+        if nearest.is_some_and(|(_, half_width, distance)| distance <= *half_width) {
+            continue;
+        }
+        position.z = if position.z - inner < outer - position.z { inner } else { outer };
+        if let Some((x, ..)) = nearest {
+            let speed = controller.map_or(DEFAULT_AI_MOVE_SPEED, |controller| controller.movement_speed);
+            let step = (x - position.x).clamp(-speed * time.delta_secs(), speed * time.delta_secs());
+            position.x += step;
+        }
+    }
+}
+
+/// Notes who took the walls and how many of the garrison fell
+pub(super) fn record_siege_result(
+    mut results: EventReader<BattleResult>,
+    setup: Option<Res<BattleSetup>>,
+    mut siege: ResMut<SiegeBattle>,
+) {
+    let Some(result) = results.read().last() else {
+        return;
+    };
+    siege.winner = Some(result.winner);
+    if let Some(setup) = setup {
+        siege.defenders_fallen = result.casualties.get(&setup.defender.faction.id).copied().unwrap_or(0);
+    }
+}
+
+/// Writes the assault back to the map: a stormed settlement changes hands
+/// with its garrison gone, a held one buries its dead. Either way the siege
+/// is over.
+pub(super) fn apply_siege_outcome(
+    mut commands: Commands,
+    mut confirmations: EventReader<AftermathConfirmed>,
+    siege: Res<SiegeBattle>,
+    setup: Option<Res<BattleSetup>>,
+    mut settlements: Query<&mut Settlement>,
+) {
+    if confirmations.read().last().is_none() {
+        return;
+    }
+
+    if let Some(mut settlement) = siege.settlement.and_then(|entity| settlements.get_mut(entity).ok()) {
+        match siege.winner {
+            Some(BattleSide::Attacker) => {
+                if let Some(setup) = &setup {
+                    settlement.owner_faction_id = setup.attacker.faction.id.clone();
+                }
+                settlement.garrison_size = 0;
+            }
+            _ => {
+                settlement.garrison_size = settlement.garrison_size.saturating_sub(siege.defenders_fallen);
+            }
+        }
+    }
+    if let Some(entity) = siege.settlement {
+        commands.entity(entity).remove::<UnderSiege>();
+    }
+    if let Some(entity) = siege.besieger {
+        commands.entity(entity).remove::<Besieging>();
+    }
+    commands.remove_resource::<SiegeBattle>();
+}
+
+pub(super) fn clear_siege(mut commands: Commands) {
+    commands.remove_resource::<SiegeWalls>();
+    commands.remove_resource::<SiegeBattle>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Faction;
+    use crate::plugins::{BattleParty, garrison_roster};
+    use crate::test_support::{
+        combat_app, combat_test_app, spawn_swordsman, start_battle, test_party, test_settlement, run_test_app,
+    };
+
+    #[test]
+    fn test_siege_battle_builds_walls_and_mans_ramparts() {
+        let mut app = combat_app();
+        let garrison = garrison_roster(&test_settlement(1000, 20));
+        assert_eq!(garrison.total_count(), 20);
+        app.insert_resource(BattleSetup {
+            attacker: test_party("vlandia", 10, 0),
+            defender: BattleParty {
+                faction: Faction { id: "sturgia".to_string(), name: "Pravend".to_string() },
+                roster: garrison,
+                leader: None,
+                party_entity: None,
+            },
+            field_size: 200.0,
+        });
+        app.insert_resource(SiegeBattle {
+            engines: vec![SiegeEngine::Ladders, SiegeEngine::Ram, SiegeEngine::SiegeTower],
+            ..default()
+        });
+        app.world_mut().resource_mut::<NextState<GameState>>().set(GameState::Combat);
+        run_test_app(&mut app, 2);
+
+        let world = app.world_mut();
+        let mut sides = world.query::<&BattleSide>();
+        assert_eq!(sides.iter(world).filter(|side| **side == BattleSide::Defender).count(), 20);
+        assert_eq!(world.query::<&Fortification>().iter(world).count(), 2);
+        assert_eq!(world.query::<&Gate>().iter(world).count(), 1);
+        assert_eq!(world.query::<&SiegeLadder>().iter(world).count(), 4);
+        assert_eq!(world.query::<&BatteringRam>().iter(world).count(), 1);
+        assert_eq!(world.query::<&SiegeTower>().iter(world).count(), 1);
+
+        start_battle(&mut app);
+        let world = app.world_mut();
+        let mut posted = world.query_filtered::<&Transform, With<OnRampart>>();
+        // Six of the twenty defenders carry crossbows and take to the walls
+        assert_eq!(posted.iter(world).count(), 6);
+        assert!(posted.iter(world).all(|transform| transform.translation.y > WALL_HEIGHT));
+    }
+
+    #[test]
+    fn test_ram_breaks_down_gate() {
+        let mut app = combat_test_app();
+        app.insert_resource(SiegeWalls { line: 0.0 });
+        let gate = app.world_mut().spawn((
+            Gate { health: 160.0, max_health: 160.0 },
+            Transform::from_xyz(0.0, 3.0, 0.0),
+        )).id();
+        app.world_mut().spawn((BatteringRam::default(), Transform::from_xyz(0.0, 0.0, 3.0)));
+        start_battle(&mut app);
+
+        // One blow as the ram reaches the gate, another two and a half seconds later
+        run_test_app(&mut app, 60);
+        assert_eq!(app.world().get::<Gate>(gate).unwrap().health, 80.0);
+        for _ in 0..200 {
+            run_test_app(&mut app, 1);
+            if app.world().get::<Gate>(gate).unwrap().health <= 0.0 {
+                break;
+            }
+        }
+        assert_eq!(app.world().get::<Gate>(gate).unwrap().health, 0.0);
+
+        let events = app.world().resource::<Events<GateBreached>>();
+        let mut cursor = events.get_cursor();
+        assert_eq!(cursor.read(events).filter(|breach| breach.gate == gate).count(), 1);
+    }
+
+    #[test]
+    fn test_walls_hold_except_at_breaches() {
+        let mut app = combat_test_app();
+        app.insert_resource(SiegeWalls { line: 0.0 });
+        app.world_mut().spawn((
+            Gate { health: 100.0, max_health: 100.0 },
+            Transform::from_xyz(0.0, 3.0, 0.0),
+        ));
+        app.world_mut().spawn((SiegeLadder, Transform::from_xyz(10.0, 3.0, 1.0)));
+        let at_gate = spawn_swordsman(&mut app, 10, Vec3::new(0.0, 0.0, 0.5));
+        let at_ladder = spawn_swordsman(&mut app, 10, Vec3::new(10.0, 0.0, 0.5));
+        let beside_ladder = spawn_swordsman(&mut app, 10, Vec3::new(7.0, 0.0, 0.5));
+        start_battle(&mut app);
+        run_test_app(&mut app, 1);
+
+        // The shut gate puts the attacker back outside the wall
+        assert_eq!(app.world().get::<Transform>(at_gate).unwrap().translation.z, 1.0);
+        // The ladder lets its climber over
+        assert_eq!(app.world().get::<Transform>(at_ladder).unwrap().translation.z, 0.5);
+        // Anyone off to the side is held back and edges towards the ladder
+        let beside = app.world().get::<Transform>(beside_ladder).unwrap().translation;
+        assert_eq!(beside.z, 1.0);
+        assert!(beside.x > 7.0);
+    }
+}
@@ -0,0 +1,315 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use serde::{Serialize, Deserialize};
+
+use crate::core::components::{Health, BattleSide, Faction, Weapon, WeaponType};
+use crate::save::SaveGameConfig;
+
+use super::{DamageTaken, UnitDied, AttackBlocked, FireProjectile, ProjectileHit};
+use super::replay::ReplayId;
+
+/// Folder inside the save directory that exported battle reports go to
+pub const REPORT_DIRECTORY: &str = "reports";
+
+/// How one combatant fared
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnitStats {
+    /// The unit's number in the battle's replay
+    pub id: Option<u32>,
+    /// Only leaders have names
+    pub name: Option<String>,
+    pub faction_id: String,
+    pub side: BattleSide,
+    /// Weapon carried as the fighting started
+    pub weapon: Option<WeaponType>,
+    /// Health taken off enemies, after their armor
+    pub damage_dealt: f32,
+    pub damage_taken: f32,
+    /// Combatants taken down, whether killed or knocked out
+    pub kills: u32,
+    pub shots_fired: u32,
+    /// Shots that reached a combatant, including those caught on a shield
+    pub shots_hit: u32,
+    /// Swings and shots stopped by the unit's guard or shield
+    pub blocks: u32,
+    /// Seconds from the start of the fighting until the unit went down or the battle ended
+    pub time_alive: f32,
+    pub survived: bool,
+}
+
+/// A faction's units added together
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FactionStats {
+    pub faction_id: String,
+    pub side: BattleSide,
+    pub units: u32,
+    pub survivors: u32,
+    pub damage_dealt: f32,
+    pub damage_taken: f32,
+    pub kills: u32,
+    pub shots_fired: u32,
+    pub shots_hit: u32,
+    pub blocks: u32,
+    /// Mean seconds its units stayed on their feet
+    pub average_time_alive: f32,
+}
+
+/// Statistics of a fought battle, for the results screens and the balance
+/// spreadsheets
+#[derive(Resource, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BattleReport {
+    /// Seconds the fighting lasted
+    pub duration: f32,
+    pub units: Vec<UnitStats>,
+    pub factions: Vec<FactionStats>,
+}
+
+impl BattleReport {
+    pub fn from_units(duration: f32, units: Vec<UnitStats>) -> Self {
+        let mut factions: Vec<FactionStats> = Vec::new();
+        for unit in &units {
+            let index = match factions.iter().position(|faction| faction.faction_id == unit.faction_id) {
+                Some(index) => index,
+                None => {
+                    factions.push(FactionStats {
+                        faction_id: unit.faction_id.clone(),
+                        side: unit.side,
+                        units: 0,
+                        survivors: 0,
+                        damage_dealt: 0.0,
+                        damage_taken: 0.0,
+                        kills: 0,
+                        shots_fired: 0,
+                        shots_hit: 0,
+                        blocks: 0,
+                        average_time_alive: 0.0,
+                    });
+                    factions.len() - 1
+                }
+            };
+            let faction = &mut factions[index];
+            faction.units += 1;
+            faction.survivors += unit.survived as u32;
+            faction.damage_dealt += unit.damage_dealt;
+            faction.damage_taken += unit.damage_taken;
+            faction.kills += unit.kills;
+            faction.shots_fired += unit.shots_fired;
+            faction.shots_hit += unit.shots_hit;
+            faction.blocks += unit.blocks;
+            // Summed here, divided once everyone is counted
+            faction.average_time_alive += unit.time_alive;
+        }
+        for faction in &mut factions {
+            faction.average_time_alive /= faction.units.max(1) as f32;
+        }
+        Self { duration, units, factions }
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn export(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_json()?)
+    }
+}
+
+/// Writes `report` to a new file in the save directory's report folder
+pub fn export_battle_report(report: &BattleReport, config: &SaveGameConfig) -> io::Result<PathBuf> {
+    let directory = config.save_directory.join(REPORT_DIRECTORY);
+    fs::create_dir_all(&directory)?;
+    let stamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+    let path = directory.join(format!("battle_{stamp}.json"));
+    report.export(&path)?;
+    Ok(path)
+}
+
+/// Tallies kept while the battle is fought
+#[derive(Resource, Debug, Clone, Default)]
+pub struct BattleStats {
+    pub units: HashMap<Entity, UnitStats>,
+    /// Seconds since the fighting started
+    pub elapsed: f32,
+}
+
+impl BattleStats {
+    fn unit(&mut self, entity: Entity) -> Option<&mut UnitStats> {
+        self.units.get_mut(&entity)
+    }
+}
+
+/// Opens a tally for everyone on the field as the fighting starts
+pub(super) fn start_battle_stats(
+    mut commands: Commands,
+    combatants: Query<(
+        Entity,
+        &BattleSide,
+        Option<&Faction>,
+        Option<&Name>,
+        Option<&Weapon>,
+        Option<&ReplayId>,
+    ), With<Health>>,
+) {
+    let units = combatants
+        .iter()
+        .map(|(entity, side, faction, name, weapon, id)| {
+            (entity, UnitStats {
+                id: id.map(|id| id.0),
+                name: name.map(|name| name.to_string()),
+                faction_id: faction.map_or_else(String::new, |faction| faction.id.clone()),
+                side: *side,
+                weapon: weapon.map(|weapon| weapon.weapon_type),
+                damage_dealt: 0.0,
+                damage_taken: 0.0,
+                kills: 0,
+                shots_fired: 0,
+                shots_hit: 0,
+                blocks: 0,
+                time_alive: 0.0,
+                survived: true,
+            })
+        })
+        .collect();
+    commands.insert_resource(BattleStats { units, elapsed: 0.0 });
+}
+
+/// Counts this frame's hits, shots, blocks and deaths into the tally
+pub(super) fn collect_battle_stats(
+    time: Res<Time>,
+    mut stats: ResMut<BattleStats>,
+    mut damage_events: EventReader<DamageTaken>,
+    mut death_events: EventReader<UnitDied>,
+    mut blocked_events: EventReader<AttackBlocked>,
+    mut fire_events: EventReader<FireProjectile>,
+    mut hit_events: EventReader<ProjectileHit>,
+) {
+    stats.elapsed += time.delta_secs();
+    let elapsed = stats.elapsed;
+
+    for event in damage_events.read() {
+        if let Some(attacker) = stats.unit(event.attacker) {
+            attacker.damage_dealt += event.amount;
+        }
+        if let Some(target) = stats.unit(event.target) {
+            target.damage_taken += event.amount;
+        }
+    }
+    for event in fire_events.read() {
+        if let Some(shooter) = stats.unit(event.shooter) {
+            shooter.shots_fired += 1;
+        }
+    }
+    for event in hit_events.read() {
+        if let Some(shooter) = stats.unit(event.shooter) {
+            shooter.shots_hit += 1;
+        }
+    }
+    for event in blocked_events.read() {
+        if let Some(target) = stats.unit(event.target) {
+            target.blocks += 1;
+        }
+    }
+    for event in death_events.read() {
+        if let Some(killer) = stats.unit(event.killer).filter(|_| event.killer != event.entity) {
+            killer.kills += 1;
+        }
+        if let Some(fallen) = stats.unit(event.entity) {
+            fallen.survived = false;
+            fallen.time_alive = elapsed;
+        }
+    }
+}
+
+/// Turns the tally into the battle's report once it is decided
+pub(super) fn compile_battle_report(mut commands: Commands, stats: Res<BattleStats>) {
+    let mut units: Vec<UnitStats> = stats
+        .units
+        .values()
+        .cloned()
+        .map(|mut unit| {
+            if unit.survived {
+                unit.time_alive = stats.elapsed;
+            }
+            unit
+        })
+        .collect();
+    units.sort_by_key(|unit| (unit.side == BattleSide::Defender, unit.id));
+    commands.insert_resource(BattleReport::from_units(stats.elapsed, units));
+}
+
+pub(super) fn clear_battle_stats(mut commands: Commands) {
+    commands.remove_resource::<BattleStats>();
+    commands.remove_resource::<BattleReport>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{AttackDirection, CombatState};
+    use crate::plugins::BlockKind;
+    use crate::test_support::{
+        combat_test_app, spawn_soldier, spawn_swordsman, enlist, kill, start_battle, run_test_app,
+    };
+
+    #[test]
+    fn test_battle_report_tallies_combat_events() {
+        let mut app = combat_test_app();
+        let hero = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+        enlist(&mut app, hero, BattleSide::Attacker, "vlandia");
+        let archer = spawn_soldier(&mut app, WeaponType::Bow, 10, Vec3::new(3.0, 0.0, 0.0));
+        enlist(&mut app, archer, BattleSide::Attacker, "vlandia");
+        let enemy = spawn_swordsman(&mut app, 10, Vec3::new(0.0, 0.0, -20.0));
+        enlist(&mut app, enemy, BattleSide::Defender, "looters");
+        start_battle(&mut app);
+
+        app.world_mut().send_event(FireProjectile { shooter: archer, target: enemy, damage: 1.0, speed: 40.0 });
+        app.world_mut().send_event(AttackBlocked {
+            attacker: enemy,
+            target: hero,
+            direction: AttackDirection::Overhead,
+            kind: BlockKind::Weapon,
+            damage: 10.0,
+            shield_damage: 0.0,
+        });
+        run_test_app(&mut app, 1);
+        kill(&mut app, hero, enemy);
+        run_test_app(&mut app, 2);
+        assert_eq!(app.world().resource::<State<CombatState>>().get(), &CombatState::Victory);
+
+        let report = app.world().resource::<BattleReport>().clone();
+        let unit = |side: BattleSide, weapon_type: WeaponType| {
+            report
+                .units
+                .iter()
+                .find(|unit| unit.side == side && unit.weapon == Some(weapon_type))
+                .unwrap()
+                .clone()
+        };
+        let hero_stats = unit(BattleSide::Attacker, WeaponType::OneHandedSword);
+        let archer_stats = unit(BattleSide::Attacker, WeaponType::Bow);
+        let enemy_stats = unit(BattleSide::Defender, WeaponType::OneHandedSword);
+
+        // Only the health the blow actually took counts
+        assert_eq!(hero_stats.damage_dealt, 100.0);
+        assert_eq!(hero_stats.kills, 1);
+        assert_eq!(hero_stats.blocks, 1);
+        assert_eq!(archer_stats.shots_fired, 1);
+        assert_eq!(enemy_stats.damage_taken, 100.0);
+        assert!(!enemy_stats.survived);
+        assert!(enemy_stats.time_alive > 0.0 && enemy_stats.time_alive <= report.duration);
+
+        let vlandia = report.factions.iter().find(|faction| faction.faction_id == "vlandia").unwrap();
+        assert_eq!((vlandia.units, vlandia.survivors, vlandia.kills), (2, 2, 1));
+        assert_eq!(vlandia.average_time_alive, report.duration);
+
+        // The export carries the per-unit breakdown as well as the faction totals
+        let exported: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(exported["units"].as_array().unwrap().len(), 3);
+        assert_eq!(exported["factions"].as_array().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,167 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use serde::{Serialize, Deserialize};
+
+use crate::core::components::WeaponType;
+
+/// Designer-facing weapon table, edited in `assets/data/weapon_rules.json`
+const WEAPON_RULES_JSON: &str = include_str!("../../../assets/data/weapon_rules.json");
+
+/// Per-weapon-type combat behaviour. Fields left out of the table fall back
+/// to neutral values, so a row only needs what makes that weapon special.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WeaponRule {
+    pub weapon_type: WeaponType,
+    pub damage_multiplier: f32,
+    pub stamina_multiplier: f32,
+    /// Added to `Weapon::reach` when checking if a swing connects
+    pub reach_bonus: f32,
+    /// Damage multiplier against a charging target while the wielder is braced
+    pub brace_multiplier: f32,
+    /// Extra enemies a single swing can carry through to
+    pub cleave_targets: u32,
+    /// Damage multiplier applied to each successive cleaved target
+    pub cleave_falloff: f32,
+    /// Width in degrees of the swing arc that cleaved targets must stand in
+    pub cleave_arc: f32,
+    /// Width in degrees of the frontal arc a shield covers, 0.0 means it can't block
+    pub block_arc: f32,
+    /// Shield durability lost per point of blocked damage
+    pub durability_damage_multiplier: f32,
+    /// Set for weapons that fire projectiles instead of swinging
+    pub ranged: Option<RangedRule>,
+}
+
+impl Default for WeaponRule {
+    fn default() -> Self {
+        Self {
+            weapon_type: WeaponType::OneHandedSword,
+            damage_multiplier: 1.0,
+            stamina_multiplier: 1.0,
+            reach_bonus: 0.0,
+            brace_multiplier: 1.0,
+            cleave_targets: 0,
+            cleave_falloff: 0.0,
+            cleave_arc: 0.0,
+            block_arc: 0.0,
+            durability_damage_multiplier: 1.0,
+            ranged: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RangedRule {
+    /// Seconds between shots
+    pub reload_time: f32,
+    /// Launch speed in metres per second
+    pub projectile_speed: f32,
+    pub max_range: f32,
+    /// How much of the shooter's strength bonus carries into the shot (draw weight)
+    pub strength_scaling: f32,
+}
+
+/// Lookup table of `WeaponRule`s keyed by weapon type
+#[derive(Resource, Debug, Clone)]
+pub struct WeaponRules {
+    rules: HashMap<WeaponType, WeaponRule>,
+    fallback: WeaponRule,
+}
+
+impl WeaponRules {
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        let rows: Vec<WeaponRule> = serde_json::from_str(json)?;
+        Ok(Self {
+            rules: rows.into_iter().map(|rule| (rule.weapon_type, rule)).collect(),
+            fallback: WeaponRule::default(),
+        })
+    }
+
+    pub fn get(&self, weapon_type: WeaponType) -> &WeaponRule {
+        self.rules.get(&weapon_type).unwrap_or(&self.fallback)
+    }
+
+    pub fn insert(&mut self, rule: WeaponRule) {
+        self.rules.insert(rule.weapon_type, rule);
+    }
+}
+
+impl Default for WeaponRules {
+    fn default() -> Self {
+        Self::from_json(WEAPON_RULES_JSON).expect("Invalid weapon rules table")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Health, Shield, Reload, AttackDirection};
+    use crate::plugins::{AttackEvent, FireProjectile};
+    use crate::test_support::{combat_test_app, spawn_soldier, spawn_swordsman, run_test_app};
+
+    #[test]
+    fn test_two_handed_cleave_hits_second_target() {
+        let mut app = combat_test_app();
+        let attacker = spawn_soldier(&mut app, WeaponType::TwoHandedSword, 0, Vec3::ZERO);
+        let first = spawn_swordsman(&mut app, 0, Vec3::new(0.0, 0.0, -1.0));
+        let second = spawn_swordsman(&mut app, 0, Vec3::new(0.3, 0.0, -1.2));
+        let behind = spawn_swordsman(&mut app, 0, Vec3::new(0.0, 0.0, 1.0));
+
+        app.world_mut().send_event(AttackEvent {
+            attacker,
+            target: first,
+            direction: AttackDirection::Right,
+            timing: 1.0,
+        });
+        run_test_app(&mut app, 1);
+
+        let first_health = app.world().get::<Health>(first).unwrap().current;
+        let second_health = app.world().get::<Health>(second).unwrap().current;
+        assert!(first_health < second_health);
+        assert!(second_health < 100.0);
+        assert_eq!(app.world().get::<Health>(behind).unwrap().current, 100.0);
+    }
+
+    #[test]
+    fn test_shield_blocks_frontal_hit() {
+        let mut app = combat_test_app();
+        let attacker = spawn_swordsman(&mut app, 10, Vec3::new(0.0, 0.0, -1.0));
+        // Transform::default faces -Z, towards the attacker
+        let defender = spawn_swordsman(&mut app, 10, Vec3::ZERO);
+        app.world_mut().entity_mut(defender).insert(Shield { durability: 50.0, max_durability: 50.0 });
+
+        app.world_mut().send_event(AttackEvent {
+            attacker,
+            target: defender,
+            direction: AttackDirection::Overhead,
+            timing: 1.0,
+        });
+        run_test_app(&mut app, 1);
+
+        assert_eq!(app.world().get::<Health>(defender).unwrap().current, 100.0);
+        assert!(app.world().get::<Shield>(defender).unwrap().durability < 50.0);
+    }
+
+    #[test]
+    fn test_crossbow_fires_then_reloads() {
+        let mut app = combat_test_app();
+        let shooter = spawn_soldier(&mut app, WeaponType::Crossbow, 10, Vec3::ZERO);
+        let target = spawn_swordsman(&mut app, 10, Vec3::new(0.0, 0.0, -30.0));
+
+        for _ in 0..2 {
+            app.world_mut().send_event(AttackEvent {
+                attacker: shooter,
+                target,
+                direction: AttackDirection::Thrust,
+                timing: 1.0,
+            });
+            run_test_app(&mut app, 1);
+        }
+
+        // Only the first bolt is loosed, the second request lands mid-reload
+        let fired = app.world().resource::<Events<FireProjectile>>().len();
+        assert_eq!(fired, 1);
+        assert!(app.world().get::<Reload>(shooter).unwrap().remaining > 0.0);
+    }
+}
@@ -43,7 +43,8 @@ fn setup_main_menu(mut commands: Commands) {
 
 fn cleanup_main_menu() {
     info!("Cleaning up main menu");
-    // Clean up menu entities
+    // The menu spawns nothing yet; whatever it comes to spawn should carry
+    // `StateScoped(GameState::MainMenu)` so it despawns on its own
 }
 
 fn handle_menu_input() {
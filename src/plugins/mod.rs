@@ -1,7 +1,15 @@
 mod combat;
 mod world_map;
 mod menu;
+mod stamina;
 
-pub use combat::CombatPlugin;
-pub use world_map::WorldMapPlugin;
-pub use menu::MenuPlugin;
\ No newline at end of file
+pub use combat::{
+    CombatPlugin, AttackEvent, DamageApplied, FireProjectile, CombatantSnapshot, FormationCommand,
+    BattleSetup, BattleParty, BattleLeader, BattleAftermath, AftermathConfirmed, BlockKind,
+};
+pub use world_map::{
+    WorldMapPlugin, PendingBattle, garrison_roster, CampaignMap, MapCell, Terrain, WorldSeed,
+    generate_campaign_map, MoveParty, CampaignClock, ClockSpeed, NewDay, CAPTIVITY_DAYS,
+};
+pub use menu::MenuPlugin;
+pub use stamina::StaminaPlugin;
@@ -0,0 +1,205 @@
+use bevy::prelude::*;
+use crate::core::states::GameState;
+use crate::core::components::{
+    Health, Stamina, Sprinting, Blocking, Exhausted, ForcedMarch, WorldPosition,
+};
+
+/// Seconds without moving or spending stamina before a unit counts as resting
+pub const IDLE_RECOVERY_DELAY: f32 = 1.5;
+/// Resting units recover this many times faster than their `recovery_rate`
+pub const IDLE_RECOVERY_MULTIPLIER: f32 = 2.5;
+/// Stamina per second spent running flat out; sprinting units don't recover
+pub const SPRINT_STAMINA_DRAIN: f32 = 12.0;
+/// Stamina per second spent holding a guard up; guarding units don't recover
+pub const GUARD_STAMINA_DRAIN: f32 = 2.0;
+/// Stamina per second a party spends on a forced march across the map
+pub const FORCED_MARCH_STAMINA_DRAIN: f32 = 4.0;
+/// Exhausted units shake it off once back above this share of their stamina
+pub const EXHAUSTION_RECOVERY_RATIO: f32 = 0.3;
+/// Swing speed of an exhausted unit, as a share of its normal speed
+pub const EXHAUSTED_SWING_SPEED_MULTIPLIER: f32 = 0.7;
+/// Exhausted defenders pay this many times the usual stamina for every block
+pub const EXHAUSTED_BLOCK_COST_MULTIPLIER: f32 = 2.0;
+/// Movement below this many metres per frame doesn't break a rest
+const REST_MOVEMENT_TOLERANCE: f32 = 0.001;
+
+/// Tracks how long a unit has gone without moving or spending stamina
+#[derive(Component, Debug, Clone, Default)]
+pub struct Exertion {
+    /// Seconds since the unit last moved or spent stamina
+    pub idle: f32,
+    last_stamina: f32,
+    last_position: Option<Vec3>,
+}
+
+pub struct StaminaPlugin;
+
+impl Plugin for StaminaPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (update_stamina, update_exhaustion)
+                .chain()
+                .run_if(in_state(GameState::Combat).or(in_state(GameState::WorldMap))),
+        );
+    }
+}
+
+/// Regenerates stamina at `recovery_rate`, faster for resting units, and drains
+/// it for sprinting, guarding and forced marches
+fn update_stamina(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut units: Query<(
+        Entity,
+        &mut Stamina,
+        Option<&mut Exertion>,
+        Option<&Health>,
+        Option<&Transform>,
+        Option<&WorldPosition>,
+        Has<Sprinting>,
+        Has<Blocking>,
+        Has<ForcedMarch>,
+    )>,
+) {
+    let delta = time.delta_secs();
+    for (entity, mut stamina, exertion, health, transform, world_position, sprinting, guarding, marching) in units.iter_mut() {
+        let position = transform
+            .map(|transform| transform.translation)
+            .or_else(|| world_position.map(|position| Vec3::new(position.x, 0.0, position.y)));
+        let Some(mut exertion) = exertion else {
+            commands.entity(entity).insert(Exertion {
+                idle: 0.0,
+                last_stamina: stamina.current,
+                last_position: position,
+            });
+            continue;
+        };
+        if health.is_some_and(|health| health.current <= 0.0) {
+            continue;
+        }
+
+        let mut drain = 0.0;
+        if sprinting {
+            drain += SPRINT_STAMINA_DRAIN;
+        }
+        if guarding {
+            drain += GUARD_STAMINA_DRAIN;
+        }
+        if marching {
+            drain += FORCED_MARCH_STAMINA_DRAIN;
+        }
+
+        // Moving, straining or having spent stamina since last frame breaks a rest
+        let moved = match (exertion.last_position, position) {
+            (Some(last), Some(position)) => last.distance(position) > REST_MOVEMENT_TOLERANCE,
+            _ => false,
+        };
+        if moved || drain > 0.0 || stamina.current < exertion.last_stamina {
+            exertion.idle = 0.0;
+        } else {
+            exertion.idle += delta;
+        }
+        let change = if drain > 0.0 {
+            -drain
+        } else if exertion.idle >= IDLE_RECOVERY_DELAY {
+            stamina.recovery_rate * IDLE_RECOVERY_MULTIPLIER
+        } else {
+            stamina.recovery_rate
+        };
+        stamina.current = (stamina.current + change * delta).clamp(0.0, stamina.max);
+
+        exertion.last_stamina = stamina.current;
+        exertion.last_position = position;
+    }
+}
+
+/// Marks units who have run out of stamina as `Exhausted` until they have
+/// caught their breath, and stops them sprinting or force-marching meanwhile
+fn update_exhaustion(
+    mut commands: Commands,
+    units: Query<(Entity, &Stamina, Has<Exhausted>)>,
+) {
+    for (entity, stamina, exhausted) in units.iter() {
+        if !exhausted && stamina.current <= 0.0 {
+            commands.entity(entity).insert(Exhausted).remove::<(Sprinting, ForcedMarch)>();
+        } else if exhausted && stamina.current >= stamina.max * EXHAUSTION_RECOVERY_RATIO {
+            commands.entity(entity).remove::<Exhausted>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::utils::Duration;
+    use crate::core::Player;
+    use crate::test_support::{headless_app, enter_state, run_test_app};
+
+    // Stamina bookkeeping alone, stepping 60Hz in the given game state
+    fn stamina_test_app(state: GameState) -> App {
+        let mut app = headless_app(Duration::from_secs_f32(1.0 / 60.0));
+        app.add_plugins(StaminaPlugin);
+        enter_state(&mut app, state);
+        app
+    }
+
+    fn spawn_winded(app: &mut App, position: Vec3) -> Entity {
+        app.world_mut().spawn((
+            Health { current: 100.0, max: 100.0 },
+            Stamina { current: 10.0, max: 100.0, recovery_rate: 5.0 },
+            Transform::from_translation(position),
+        )).id()
+    }
+
+    #[test]
+    fn test_stamina_recovers_faster_at_rest() {
+        let mut app = stamina_test_app(GameState::Combat);
+        let resting = spawn_winded(&mut app, Vec3::ZERO);
+        let walking = spawn_winded(&mut app, Vec3::new(10.0, 0.0, 0.0));
+
+        for _ in 0..180 {
+            app.world_mut().get_mut::<Transform>(walking).unwrap().translation.z += 0.05;
+            run_test_app(&mut app, 1);
+        }
+
+        // Three seconds at 5 per second for the walker
+        let walked = app.world().get::<Stamina>(walking).unwrap().current;
+        assert!((walked - 25.0).abs() < 0.5, "walker at {walked}");
+        assert!(app.world().get::<Stamina>(resting).unwrap().current > walked + 5.0);
+        assert!(app.world().get::<Exertion>(resting).unwrap().idle > 2.0);
+        assert_eq!(app.world().get::<Exertion>(walking).unwrap().idle, 0.0);
+    }
+
+    #[test]
+    fn test_sprinting_drains_to_exhaustion() {
+        let mut app = stamina_test_app(GameState::Combat);
+        let runner = spawn_winded(&mut app, Vec3::ZERO);
+        app.world_mut().entity_mut(runner).insert(Sprinting);
+
+        // Ten stamina lasts under a second at a sprint
+        run_test_app(&mut app, 60);
+        assert_eq!(app.world().get::<Stamina>(runner).unwrap().current, 0.0);
+        assert!(app.world().get::<Exhausted>(runner).is_some());
+        assert!(app.world().get::<Sprinting>(runner).is_none());
+
+        // Catching breath back to 30 lifts the exhaustion
+        run_test_app(&mut app, 60 * 4);
+        assert!(app.world().get::<Exhausted>(runner).is_none());
+    }
+
+    #[test]
+    fn test_forced_march_drains_party_stamina() {
+        let mut app = stamina_test_app(GameState::WorldMap);
+        let party = app.world_mut().spawn((
+            Player,
+            Stamina { current: 100.0, max: 100.0, recovery_rate: 5.0 },
+            WorldPosition { x: 0.0, y: 0.0 },
+            ForcedMarch,
+        )).id();
+
+        run_test_app(&mut app, 60 * 5);
+        let stamina = app.world().get::<Stamina>(party).unwrap().current;
+        assert!(stamina < 85.0, "party at {stamina}");
+    }
+}
@@ -0,0 +1,237 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_egui::{egui, EguiContexts};
+use bevy_rand::prelude::{GlobalEntropy, WyRand};
+
+use crate::core::states::{GameState, BattleResult};
+use crate::core::components::{
+    TroopRoster, Faction, CharacterStats, Player, Prisoners, WeaponType, BattleSide,
+};
+use crate::plugins::combat::{
+    BattleSetup, BattleParty, BattleLeader, AutoResolvedBattle, auto_resolve, DEFAULT_FIELD_SIZE,
+};
+
+/// Parties on the map, as battles read and update them
+type MapParties<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static mut TroopRoster,
+        Option<&'static Faction>,
+        Option<&'static CharacterStats>,
+        Option<&'static Name>,
+        Has<Player>,
+        Option<&'static mut Prisoners>,
+    ),
+>;
+
+/// Two parties on the map come to blows
+#[derive(Event, Debug, Clone)]
+pub struct EngageBattle {
+    pub attacker: Entity,
+    pub defender: Entity,
+    /// Whether the player leads their troops onto the field rather than
+    /// sending them in and waiting on the outcome
+    pub in_person: bool,
+}
+
+/// A battle waiting on the player to choose between fighting it and sending
+/// the troops in alone
+#[derive(Resource, Debug, Clone)]
+pub struct PendingBattle {
+    pub attacker: Entity,
+    pub defender: Entity,
+}
+
+/// A party on the map as it takes the field; one with stats of its own is
+/// led by its captain, unless they stay behind
+pub fn battle_party(
+    entity: Entity,
+    roster: &TroopRoster,
+    faction: Option<&Faction>,
+    stats: Option<&CharacterStats>,
+    name: Option<&Name>,
+    is_player: bool,
+    leads: bool,
+) -> BattleParty {
+    let leader = stats.filter(|_| leads).map(|stats| BattleLeader {
+        name: name.map_or_else(|| "Player".to_string(), |name| name.to_string()),
+        stats: stats.clone(),
+        weapon_type: WeaponType::OneHandedSword,
+        is_player,
+    });
+    BattleParty {
+        faction: faction.cloned().unwrap_or_else(|| Faction {
+            id: "player".to_string(),
+            name: "Player".to_string(),
+        }),
+        roster: roster.clone(),
+        leader,
+        party_entity: Some(entity),
+    }
+}
+
+/// The party `entity` as it takes the field. The player leads their own
+/// troops only when fighting in person; other captains always do.
+fn field_party(parties: &MapParties, entity: Entity, player_in_person: bool) -> Option<BattleParty> {
+    let (roster, faction, stats, name, is_player, _) = parties.get(entity).ok()?;
+    Some(battle_party(entity, roster, faction, stats, name, is_player, player_in_person || !is_player))
+}
+
+/// Starts each battle the way it is meant to be fought: the player's in
+/// person or by the results screen, everyone else's settled on the spot
+pub(super) fn engage_battles(
+    mut commands: Commands,
+    mut engagements: EventReader<EngageBattle>,
+    mut rng: ResMut<GlobalEntropy<WyRand>>,
+    mut next_game_state: ResMut<NextState<GameState>>,
+    mut parties: MapParties,
+) {
+    for engagement in engagements.read() {
+        let is_player = |entity| parties.get(entity).is_ok_and(|party| party.4);
+        let player_side = if is_player(engagement.attacker) {
+            Some(BattleSide::Attacker)
+        } else if is_player(engagement.defender) {
+            Some(BattleSide::Defender)
+        } else {
+            None
+        };
+        let in_person = engagement.in_person && player_side.is_some();
+        let (Some(attacker), Some(defender)) = (
+            field_party(&parties, engagement.attacker, in_person),
+            field_party(&parties, engagement.defender, in_person),
+        ) else {
+            continue;
+        };
+        let setup = BattleSetup { attacker, defender, field_size: DEFAULT_FIELD_SIZE };
+        commands.remove_resource::<PendingBattle>();
+
+        match player_side {
+            Some(_) if in_person => {
+                commands.insert_resource(setup);
+                next_game_state.set(GameState::Combat);
+            }
+            Some(player_side) => {
+                let result = auto_resolve(&setup, &mut *rng);
+                commands.insert_resource(AutoResolvedBattle { result, player_side });
+                commands.insert_resource(setup);
+                next_game_state.set(GameState::Combat);
+            }
+            None => {
+                let result = auto_resolve(&setup, &mut *rng);
+                apply_battle_result(&mut commands, &result, &setup, &mut parties);
+            }
+        }
+    }
+}
+
+/// Writes a battle between two AI parties back to their rosters: both bury
+/// their dead and the winners lead away the losers' wounded
+fn apply_battle_result(
+    commands: &mut Commands,
+    result: &BattleResult,
+    setup: &BattleSetup,
+    parties: &mut MapParties,
+) {
+    let winner = setup.party(result.winner);
+    let loser = setup.party(result.winner.opponent());
+    let (Some(winner_entity), Some(loser_entity)) = (winner.party_entity, loser.party_entity) else {
+        return;
+    };
+    let Ok([mut won, mut lost]) = parties.get_many_mut([winner_entity, loser_entity]) else {
+        return;
+    };
+    let count = |tally: &HashMap<String, u32>, id: &str| tally.get(id).copied().unwrap_or(0);
+
+    won.0.remove_healthy(count(&result.casualties, &winner.faction.id));
+    won.0.wound(count(&result.wounded, &winner.faction.id));
+
+    lost.0.remove_healthy(count(&result.casualties, &loser.faction.id));
+    let captured = count(&result.prisoners, &loser.faction.id);
+    let taken = lost.0.remove_healthy(captured);
+    lost.0.wound(count(&result.wounded, &loser.faction.id).saturating_sub(captured));
+
+    match won.5.as_mut() {
+        Some(prisoners) => {
+            for stack in taken {
+                prisoners.roster.add(stack);
+            }
+        }
+        None if !taken.is_empty() => {
+            commands.entity(winner_entity).insert(Prisoners {
+                roster: TroopRoster { stacks: taken },
+            });
+        }
+        None => {}
+    }
+}
+
+/// Asks the player whether to lead the coming battle or send the troops in alone
+pub(super) fn battle_choice_menu(
+    mut contexts: EguiContexts,
+    pending: Res<PendingBattle>,
+    mut engagements: EventWriter<EngageBattle>,
+) {
+    let Some(ctx) = contexts.try_ctx_mut() else {
+        return;
+    };
+    egui::Window::new("Battle")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                for (label, in_person) in [("Fight", true), ("Send troops", false)] {
+                    if ui.button(label).clicked() {
+                        engagements.send(EngageBattle {
+                            attacker: pending.attacker,
+                            defender: pending.defender,
+                            in_person,
+                        });
+                    }
+                }
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::TroopStack;
+    use crate::test_support::{world_map_test_app, run_test_app};
+
+    #[test]
+    fn test_ai_battle_on_map_auto_resolves() {
+        let mut app = world_map_test_app();
+        let party = |app: &mut App, faction_id: &str, count: u32| {
+            app.world_mut().spawn((
+                Faction { id: faction_id.to_string(), name: faction_id.to_string() },
+                TroopRoster {
+                    stacks: vec![TroopStack {
+                        troop_id: "recruit".to_string(),
+                        tier: 2,
+                        weapon_type: WeaponType::Spear,
+                        count,
+                        wounded: 0,
+                        mounted: false,
+                    }],
+                },
+            )).id()
+        };
+        let lords = party(&mut app, "vlandia", 60);
+        let bandits = party(&mut app, "looters", 15);
+
+        // Neither party is the player's, so even a request to fight in person is settled on the spot
+        app.world_mut().send_event(EngageBattle { attacker: lords, defender: bandits, in_person: true });
+        run_test_app(&mut app, 2);
+        assert_eq!(*app.world().resource::<State<GameState>>().get(), GameState::WorldMap);
+
+        let bandit_roster = app.world().get::<TroopRoster>(bandits).unwrap();
+        assert!(bandit_roster.healthy_count() < 15);
+        let lord_roster = app.world().get::<TroopRoster>(lords).unwrap();
+        assert!(lord_roster.total_count() <= 60);
+        // Looters led away by the lords are gone from the looters' roster
+        let taken = app.world().get::<Prisoners>(lords).map_or(0, |prisoners| prisoners.roster.total_count());
+        assert!(taken + bandit_roster.total_count() <= 15);
+    }
+}
@@ -0,0 +1,206 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use bevy::color::Mix;
+use bevy_egui::{egui, EguiContexts};
+use serde::{Serialize, Deserialize};
+
+use super::WorldMapCamera;
+
+/// Real seconds in a day on the campaign map at normal speed
+pub const SECONDS_PER_DAY: f32 = 10.0;
+pub const HOURS_PER_DAY: u64 = 24;
+pub const DAYS_PER_SEASON: u64 = 21;
+/// How many times faster than normal the clock runs when fast-forwarding
+pub const FAST_FORWARD_MULTIPLIER: f32 = 4.0;
+/// Colour behind the map at midnight and at noon
+const NIGHT_COLOR: Color = Color::srgb(0.02, 0.03, 0.08);
+const DAY_COLOR: Color = Color::srgb(0.35, 0.55, 0.75);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Season {
+    Spring,
+    Summer,
+    Autumn,
+    Winter,
+}
+
+impl Season {
+    pub const ALL: [Season; 4] = [Season::Spring, Season::Summer, Season::Autumn, Season::Winter];
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ClockSpeed {
+    Paused,
+    #[default]
+    Normal,
+    FastForward,
+}
+
+impl ClockSpeed {
+    pub fn multiplier(self) -> f32 {
+        match self {
+            ClockSpeed::Paused => 0.0,
+            ClockSpeed::Normal => 1.0,
+            ClockSpeed::FastForward => FAST_FORWARD_MULTIPLIER,
+        }
+    }
+}
+
+/// Time on the campaign map. It only moves while the map is open, and holds
+/// still whenever the map waits on the player.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct CampaignClock {
+    /// Seconds of campaign time, at normal speed, since the campaign began
+    pub elapsed: f64,
+    pub speed: ClockSpeed,
+    /// Stopped for an encounter or a choice, whatever the speed
+    pub held: bool,
+}
+
+impl CampaignClock {
+    pub fn from_elapsed(elapsed: f64) -> Self {
+        Self { elapsed, ..default() }
+    }
+
+    /// How fast campaign time runs against real time right now
+    pub fn rate(&self) -> f32 {
+        if self.held {
+            0.0
+        } else {
+            self.speed.multiplier()
+        }
+    }
+
+    /// Whole hours since the campaign began
+    pub fn total_hours(&self) -> u64 {
+        // Multiplied before dividing, so whole days land exactly on their hour
+        (self.elapsed * HOURS_PER_DAY as f64 / SECONDS_PER_DAY as f64).floor() as u64
+    }
+
+    /// Hour of the day, from 0 at midnight
+    pub fn hour(&self) -> u64 {
+        self.total_hours() % HOURS_PER_DAY
+    }
+
+    /// Day of the campaign, counting from 1
+    pub fn day(&self) -> u32 {
+        (self.total_hours() / HOURS_PER_DAY) as u32 + 1
+    }
+
+    /// Day of the current season, counting from 1
+    pub fn day_of_season(&self) -> u32 {
+        ((self.total_hours() / HOURS_PER_DAY) % DAYS_PER_SEASON) as u32 + 1
+    }
+
+    pub fn season(&self) -> Season {
+        let seasons = self.total_hours() / HOURS_PER_DAY / DAYS_PER_SEASON;
+        Season::ALL[(seasons % Season::ALL.len() as u64) as usize]
+    }
+
+    /// Year of the campaign, counting from 1
+    pub fn year(&self) -> u32 {
+        (self.total_hours() / HOURS_PER_DAY / DAYS_PER_SEASON / Season::ALL.len() as u64) as u32 + 1
+    }
+
+    /// How light it is, from 0 at midnight to 1 at noon
+    pub fn daylight(&self) -> f32 {
+        let day_fraction = (self.elapsed / SECONDS_PER_DAY as f64).fract() as f32;
+        0.5 - 0.5 * (day_fraction * TAU).cos()
+    }
+
+    pub fn is_night(&self) -> bool {
+        self.daylight() < 0.25
+    }
+}
+
+/// An hour has passed on the campaign map
+#[derive(Event, Debug, Clone)]
+pub struct NewHour {
+    /// Hours since the campaign began
+    pub hour: u64,
+}
+
+/// A day has passed on the campaign map
+#[derive(Event, Debug, Clone)]
+pub struct NewDay {
+    /// The day that has just begun
+    pub day: u32,
+}
+
+/// A season has turned on the campaign map
+#[derive(Event, Debug, Clone)]
+pub struct NewSeason {
+    pub season: Season,
+    pub year: u32,
+}
+
+/// Sets the colour behind the map by the time of day
+pub(super) fn shade_day_and_night(clock: Res<CampaignClock>, mut cameras: Query<&mut Camera, With<WorldMapCamera>>) {
+    let color = NIGHT_COLOR.mix(&DAY_COLOR, clock.daylight());
+    for mut camera in cameras.iter_mut() {
+        camera.clear_color = ClearColorConfig::Custom(color);
+    }
+}
+
+/// Date, time and speed in the corner of the map
+pub(super) fn clock_display(mut contexts: EguiContexts, clock: Res<CampaignClock>) {
+    let Some(ctx) = contexts.try_ctx_mut() else {
+        return;
+    };
+    egui::Area::new(egui::Id::new("campaign_clock"))
+        .anchor(egui::Align2::RIGHT_TOP, [-10.0, 10.0])
+        .show(ctx, |ui| {
+            ui.label(format!(
+                "{:?}, day {} of year {}, {:02}:00",
+                clock.season(),
+                clock.day_of_season(),
+                clock.year(),
+                clock.hour(),
+            ));
+            let speed = match (clock.held, clock.speed) {
+                (true, _) | (_, ClockSpeed::Paused) => "Paused",
+                (_, ClockSpeed::Normal) => "Normal speed",
+                (_, ClockSpeed::FastForward) => "Fast forward",
+            };
+            ui.label(speed);
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::PendingBattle;
+    use crate::test_support::{world_map_test_app, run_test_app};
+
+    #[test]
+    fn test_campaign_clock_turns_days_and_seasons() {
+        let mut app = world_map_test_app();
+        // Half a second before the last day of spring runs out
+        app.world_mut().resource_mut::<CampaignClock>().elapsed = 21.0 * 10.0 - 0.5;
+        run_test_app(&mut app, 1);
+
+        let clock = app.world().resource::<CampaignClock>().clone();
+        assert_eq!((clock.day(), clock.day_of_season(), clock.season(), clock.year()), (22, 1, Season::Summer, 1));
+        let days = app.world().resource::<Events<NewDay>>();
+        assert_eq!(days.get_cursor().read(days).map(|event| event.day).collect::<Vec<_>>(), vec![22]);
+        let seasons = app.world().resource::<Events<NewSeason>>();
+        let turned: Vec<(Season, u32)> = seasons.get_cursor().read(seasons).map(|event| (event.season, event.year)).collect();
+        assert_eq!(turned, vec![(Season::Summer, 1)]);
+
+        let elapsed = clock.elapsed;
+        app.world_mut().resource_mut::<CampaignClock>().speed = ClockSpeed::Paused;
+        run_test_app(&mut app, 5);
+        assert_eq!(app.world().resource::<CampaignClock>().elapsed, elapsed);
+
+        app.world_mut().resource_mut::<CampaignClock>().speed = ClockSpeed::FastForward;
+        run_test_app(&mut app, 1);
+        assert_eq!(app.world().resource::<CampaignClock>().elapsed, elapsed + 4.0);
+
+        // A battle waiting on the player holds the clock whatever its speed
+        app.world_mut().insert_resource(PendingBattle { attacker: Entity::PLACEHOLDER, defender: Entity::PLACEHOLDER });
+        run_test_app(&mut app, 3);
+        assert_eq!(app.world().resource::<CampaignClock>().elapsed, elapsed + 4.0);
+        assert!(app.world().resource::<CampaignClock>().held);
+    }
+}
@@ -0,0 +1,440 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use bevy_rand::prelude::{GlobalEntropy, WyRand};
+
+use crate::core::states::WorldMapState;
+use crate::core::random::roll;
+use crate::core::components::{
+    AiParty, PartyKind, WorldPosition, TroopRoster, Player, Inventory, CharacterStats, CharacterController,
+    ForcedMarch, Prisoners, Captive, Faction, MapPath, EncounterCooldown,
+};
+
+use super::battles::PendingBattle;
+use super::calendar::{CampaignClock, NewDay, SECONDS_PER_DAY};
+use super::movement::party_speed;
+use super::parties::{party_strength, ARRIVAL_DISTANCE};
+
+/// How close two parties come before they meet
+pub const ENCOUNTER_DISTANCE: f32 = ARRIVAL_DISTANCE;
+/// Campaign seconds a party is left alone after meeting the player
+pub const ENCOUNTER_COOLDOWN: f64 = SECONDS_PER_DAY as f64 / 2.0;
+/// Gold it takes to buy off each point of a party's strength
+pub const BRIBE_GOLD_PER_STRENGTH: f32 = 5.0;
+/// Chance of talking a hostile party round for each point of charisma
+pub const TALK_CHANCE_PER_CHARISMA: f32 = 0.02;
+pub const MAX_TALK_CHANCE: f32 = 0.9;
+/// Chance of getting away from a party just as fast
+pub const BASE_FLEE_CHANCE: f32 = 0.5;
+pub const MIN_FLEE_CHANCE: f32 = 0.05;
+pub const MAX_FLEE_CHANCE: f32 = 0.95;
+/// Days captors hold a captive before letting them go for what they can get
+pub const CAPTIVITY_DAYS: u32 = 3;
+
+/// The player's party has met another on the map
+#[derive(Resource, Debug, Clone)]
+pub struct Encounter {
+    pub player: Entity,
+    pub other: Entity,
+    /// Whether the other party means the player harm
+    pub hostile: bool,
+    /// What came of the last thing the player tried
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncounterAction {
+    Attack,
+    Surrender,
+    Bribe,
+    Talk,
+    Flee,
+}
+
+/// The player's choice in the encounter menu
+#[derive(Event, Debug, Clone)]
+pub struct EncounterChoice {
+    pub action: EncounterAction,
+}
+
+/// Gold a party wants to let the player go
+pub fn bribe_cost(roster: &TroopRoster) -> u32 {
+    (party_strength(roster) * BRIBE_GOLD_PER_STRENGTH).ceil() as u32
+}
+
+/// Chance of talking a hostile party out of a fight
+pub fn talk_chance(stats: Option<&CharacterStats>) -> f32 {
+    (stats.map_or(0, |stats| stats.charisma) as f32 * TALK_CHANCE_PER_CHARISMA).min(MAX_TALK_CHANCE)
+}
+
+/// Chance of outrunning a pursuer, better the faster the player's party is
+/// than the one on its heels
+pub fn flee_chance(speed: f32, pursuer_speed: f32) -> f32 {
+    (BASE_FLEE_CHANCE + (speed - pursuer_speed) / pursuer_speed.max(f32::EPSILON))
+        .clamp(MIN_FLEE_CHANCE, MAX_FLEE_CHANCE)
+}
+
+/// Stops the map when a party the player can meet comes close. Bandits seek
+/// the player out; anyone else is only met when the player stops beside them.
+/// A captive player meets no one.
+pub(super) fn detect_encounters(
+    mut commands: Commands,
+    clock: Res<CampaignClock>,
+    mut next_map_state: ResMut<NextState<WorldMapState>>,
+    players: Query<(Entity, &WorldPosition, Has<MapPath>), (With<Player>, Without<AiParty>, Without<Captive>)>,
+    parties: Query<(Entity, &WorldPosition, &AiParty, Option<&EncounterCooldown>)>,
+) {
+    let Ok((player, position, travelling)) = players.get_single() else {
+        return;
+    };
+    let here = Vec2::new(position.x, position.y);
+    let met = parties
+        .iter()
+        .filter(|(.., cooldown)| cooldown.is_none_or(|cooldown| cooldown.until <= clock.elapsed))
+        .filter(|(_, other, ..)| here.distance(Vec2::new(other.x, other.y)) <= ENCOUNTER_DISTANCE)
+        .map(|(entity, _, ai, _)| (entity, ai.kind == PartyKind::Bandit))
+        .filter(|(_, hostile)| *hostile || !travelling)
+        // A hostile party is met before a friendly one
+        .max_by_key(|(_, hostile)| *hostile);
+    if let Some((other, hostile)) = met {
+        commands.insert_resource(Encounter { player, other, hostile, message: None });
+        next_map_state.set(WorldMapState::Encounter);
+    }
+}
+
+/// Carries out the player's choice in an encounter. Fights go to the battle
+/// menu, to be led in person or auto-resolved; everything else is settled here.
+pub(super) fn handle_encounter(
+    mut commands: Commands,
+    clock: Res<CampaignClock>,
+    mut rng: ResMut<GlobalEntropy<WyRand>>,
+    mut choices: EventReader<EncounterChoice>,
+    mut encounter: ResMut<Encounter>,
+    mut next_map_state: ResMut<NextState<WorldMapState>>,
+    mut players: Query<(
+        &mut TroopRoster,
+        Option<&mut Inventory>,
+        Option<&CharacterStats>,
+        Option<&CharacterController>,
+        Has<ForcedMarch>,
+    ), With<Player>>,
+    mut others: Query<(&TroopRoster, Option<&mut Prisoners>, Option<&Faction>, Option<&CharacterController>), Without<Player>>,
+) {
+    let Some(choice) = choices.read().last() else {
+        return;
+    };
+    let (player, other) = (encounter.player, encounter.other);
+    let (Ok((mut roster, inventory, stats, controller, forced_march)), Ok((other_roster, prisoners, faction, other_controller))) =
+        (players.get_mut(player), others.get_mut(other))
+    else {
+        // Whoever the player met is gone
+        commands.remove_resource::<Encounter>();
+        next_map_state.set(WorldMapState::Free);
+        return;
+    };
+
+    // Parted on whatever terms; the other party keeps its distance for a while
+    let mut part = |commands: &mut Commands| {
+        commands.entity(other).insert(EncounterCooldown { until: clock.elapsed + ENCOUNTER_COOLDOWN });
+        commands.remove_resource::<Encounter>();
+        next_map_state.set(WorldMapState::Free);
+    };
+    // Battle is joined; the encounter is wound up when the map comes back
+    let fight = |commands: &mut Commands, attacker: Entity, defender: Entity| {
+        commands.insert_resource(PendingBattle { attacker, defender });
+    };
+
+    match choice.action {
+        EncounterAction::Attack => {
+            fight(&mut commands, player, other);
+            next_map_state.set(WorldMapState::Free);
+        }
+        EncounterAction::Surrender if encounter.hostile => {
+            // The captors lead away the player's troops and empty their purse
+            let healthy = roster.healthy_count();
+            let taken = roster.remove_healthy(healthy);
+            match prisoners {
+                Some(mut prisoners) => {
+                    for stack in taken {
+                        prisoners.roster.add(stack);
+                    }
+                }
+                None if !taken.is_empty() => {
+                    commands.entity(other).insert(Prisoners { roster: TroopRoster { stacks: taken } });
+                }
+                None => {}
+            }
+            if let Some(mut inventory) = inventory {
+                inventory.gold = 0;
+            }
+            commands.entity(player).insert(Captive {
+                captor_faction_id: faction.map_or_else(String::new, |faction| faction.id.clone()),
+                captor: Some(other),
+                days: 0,
+            });
+            part(&mut commands);
+        }
+        EncounterAction::Bribe if encounter.hostile => {
+            let cost = bribe_cost(other_roster);
+            match inventory {
+                Some(mut inventory) if inventory.gold >= cost => {
+                    inventory.gold -= cost;
+                    part(&mut commands);
+                }
+                _ => encounter.message = Some(format!("They want {cost} gold, more than you carry.")),
+            }
+        }
+        EncounterAction::Talk => {
+            if !encounter.hostile || roll(&mut *rng) < talk_chance(stats) {
+                part(&mut commands);
+            } else {
+                // Talk only riles them; they attack
+                fight(&mut commands, other, player);
+                next_map_state.set(WorldMapState::Free);
+            }
+        }
+        EncounterAction::Flee => {
+            let speed = party_speed(Some(&*roster), controller, forced_march);
+            let pursuer_speed = party_speed(Some(other_roster), other_controller, false);
+            if !encounter.hostile || roll(&mut *rng) < flee_chance(speed, pursuer_speed) {
+                part(&mut commands);
+            } else {
+                // Run down, the player has to stand and fight
+                fight(&mut commands, other, player);
+                next_map_state.set(WorldMapState::Free);
+            }
+        }
+        // Nothing to surrender to or buy off
+        EncounterAction::Surrender | EncounterAction::Bribe => {}
+    }
+}
+
+/// Winds up an encounter that ended in battle once the map comes back: a
+/// party with nobody left breaks up, and one that survived leaves the player be
+pub(super) fn conclude_encounter(
+    mut commands: Commands,
+    clock: Res<CampaignClock>,
+    encounter: Option<Res<Encounter>>,
+    parties: Query<&TroopRoster, With<AiParty>>,
+) {
+    let Some(encounter) = encounter else {
+        return;
+    };
+    match parties.get(encounter.other) {
+        Ok(roster) if roster.total_count() == 0 => {
+            commands.entity(encounter.other).despawn();
+        }
+        Ok(_) => {
+            commands.entity(encounter.other).insert(EncounterCooldown { until: clock.elapsed + ENCOUNTER_COOLDOWN });
+        }
+        Err(_) => {}
+    }
+    commands.remove_resource::<Encounter>();
+}
+
+/// Keeps captives with the party holding them. They are let go once they have
+/// been held `CAPTIVITY_DAYS`, or as soon as their captors are gone, and set
+/// off from wherever they were freed.
+pub(super) fn hold_captives(
+    mut commands: Commands,
+    clock: Res<CampaignClock>,
+    mut days: EventReader<NewDay>,
+    mut captives: Query<(Entity, &mut Captive, &mut WorldPosition)>,
+    captors: Query<&WorldPosition, Without<Captive>>,
+) {
+    let days_passed = days.read().count() as u32;
+    for (entity, mut captive, mut position) in captives.iter_mut() {
+        captive.days += days_passed;
+        let captor = captive.captor.and_then(|captor| captors.get(captor).ok().map(|at| (captor, at)));
+        match captor {
+            Some((_, at)) if captive.days < CAPTIVITY_DAYS => {
+                *position = at.clone();
+            }
+            _ => {
+                commands.entity(entity).remove::<(Captive, MapPath)>();
+                // The captors leave the freed player be for a while
+                if let Some((captor, _)) = captor {
+                    commands.entity(captor).insert(EncounterCooldown { until: clock.elapsed + ENCOUNTER_COOLDOWN });
+                }
+            }
+        }
+    }
+}
+
+/// What the player can do about the party they have met
+pub(super) fn encounter_menu(
+    mut contexts: EguiContexts,
+    encounter: Res<Encounter>,
+    mut choices: EventWriter<EncounterChoice>,
+    players: Query<(Option<&Inventory>, Option<&CharacterStats>), With<Player>>,
+    others: Query<(&TroopRoster, Option<&Name>), Without<Player>>,
+) {
+    let Some(ctx) = contexts.try_ctx_mut() else {
+        return;
+    };
+    let Ok((roster, name)) = others.get(encounter.other) else {
+        return;
+    };
+    let (gold, stats) = players
+        .get(encounter.player)
+        .map_or((0, None), |(inventory, stats)| (inventory.map_or(0, |inventory| inventory.gold), stats));
+    let cost = bribe_cost(roster);
+
+    let title = name.map_or_else(|| "A party".to_string(), |name| name.to_string());
+    egui::Window::new(title)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ctx, |ui| {
+            ui.label(format!("{} troops", roster.total_count()));
+            ui.label(if encounter.hostile { "They mean you harm." } else { "They are peaceful." });
+            if let Some(message) = &encounter.message {
+                ui.label(message.as_str());
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                let talk = if encounter.hostile {
+                    format!("Talk ({:.0}%)", talk_chance(stats) * 100.0)
+                } else {
+                    "Talk".to_string()
+                };
+                for (label, action, enabled) in [
+                    ("Attack".to_string(), EncounterAction::Attack, true),
+                    (talk, EncounterAction::Talk, true),
+                    (format!("Bribe ({cost} gold)"), EncounterAction::Bribe, encounter.hostile && gold >= cost),
+                    ("Surrender".to_string(), EncounterAction::Surrender, encounter.hostile),
+                    (if encounter.hostile { "Flee" } else { "Leave" }.to_string(), EncounterAction::Flee, true),
+                ] {
+                    if ui.add_enabled(enabled, egui::Button::new(label)).clicked() {
+                        choices.send(EncounterChoice { action });
+                    }
+                }
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{GameState, PartyGoal, TroopStack, WeaponType};
+    use crate::plugins::MoveParty;
+    use crate::test_support::{world_map_test_app_with, plains_map, enter_state, run_test_app};
+
+    #[test]
+    fn test_flee_chance_favours_the_faster_party() {
+        assert_eq!(flee_chance(5.0, 5.0), 0.5);
+        assert!(flee_chance(6.0, 5.0) > flee_chance(5.0, 5.0));
+        assert!(flee_chance(4.0, 5.0) < flee_chance(5.0, 5.0));
+        assert_eq!(flee_chance(50.0, 5.0), 0.95);
+        assert_eq!(flee_chance(0.5, 5.0), 0.05);
+    }
+
+    // The player's party with a band of looters closing on it, already met
+    fn meet_looters() -> (App, Entity, Entity) {
+        let mut app = world_map_test_app_with(plains_map(8, 1));
+        let recruits = |troop_id: &str, count: u32| TroopRoster {
+            stacks: vec![TroopStack {
+                troop_id: troop_id.to_string(),
+                tier: 1,
+                weapon_type: WeaponType::Spear,
+                count,
+                wounded: 0,
+                mounted: false,
+            }],
+        };
+        let player = app.world_mut().spawn((
+            Player,
+            WorldPosition { x: 15.0, y: 5.0 },
+            recruits("recruit", 12),
+            Inventory { gold: 500, items: Vec::new() },
+        )).id();
+        let bandits = app.world_mut().spawn((
+            AiParty { kind: PartyKind::Bandit, home: None, goal: PartyGoal::Idle },
+            Faction { id: "looters".to_string(), name: "Looters".to_string() },
+            WorldPosition { x: 20.0, y: 5.0 },
+            recruits("looter", 10),
+        )).id();
+        run_test_app(&mut app, 2);
+        (app, player, bandits)
+    }
+
+    fn choose(app: &mut App, action: EncounterAction) {
+        app.world_mut().send_event(EncounterChoice { action });
+        run_test_app(app, 2);
+    }
+
+    #[test]
+    fn test_bandits_nearby_stop_the_map_and_can_be_bought_off() {
+        let (mut app, player, bandits) = meet_looters();
+        assert_eq!(*app.world().resource::<State<WorldMapState>>().get(), WorldMapState::Encounter);
+        let encounter = app.world().resource::<Encounter>().clone();
+        assert_eq!((encounter.player, encounter.other, encounter.hostile), (player, bandits, true));
+        // The map waits on the player
+        let elapsed = app.world().resource::<CampaignClock>().elapsed;
+        run_test_app(&mut app, 3);
+        assert_eq!(app.world().resource::<CampaignClock>().elapsed, elapsed);
+
+        let cost = bribe_cost(app.world().get::<TroopRoster>(bandits).unwrap());
+        choose(&mut app, EncounterAction::Bribe);
+        assert_eq!(app.world().get::<Inventory>(player).unwrap().gold, 500 - cost);
+        assert_eq!(*app.world().resource::<State<WorldMapState>>().get(), WorldMapState::Free);
+        assert!(app.world().get_resource::<Encounter>().is_none());
+        // Paid off, they leave the player be for a while
+        assert!(app.world().get::<EncounterCooldown>(bandits).is_some());
+        run_test_app(&mut app, 1);
+        assert_eq!(*app.world().resource::<State<WorldMapState>>().get(), WorldMapState::Free);
+    }
+
+    #[test]
+    fn test_surrender_hands_the_party_to_its_captors() {
+        let (mut app, player, bandits) = meet_looters();
+        choose(&mut app, EncounterAction::Surrender);
+
+        assert_eq!(app.world().get::<TroopRoster>(player).unwrap().total_count(), 0);
+        assert_eq!(app.world().get::<Inventory>(player).unwrap().gold, 0);
+        assert_eq!(app.world().get::<Captive>(player).unwrap().captor_faction_id, "looters");
+        assert_eq!(app.world().get::<Prisoners>(bandits).unwrap().roster.total_count(), 12);
+        assert_eq!(*app.world().resource::<State<WorldMapState>>().get(), WorldMapState::Free);
+
+        // A captive is led about by their captors, not met, and can't march off on their own
+        app.world_mut().entity_mut(bandits).remove::<EncounterCooldown>();
+        app.world_mut().send_event(MoveParty { party: player, destination: WorldPosition { x: 75.0, y: 5.0 } });
+        run_test_app(&mut app, 3);
+        assert_eq!(*app.world().resource::<State<WorldMapState>>().get(), WorldMapState::Free);
+        assert!(app.world().get::<MapPath>(player).is_none());
+        assert_eq!(app.world().get::<WorldPosition>(player).unwrap().x, 20.0);
+
+        // Wherever the captors go, the captive goes too
+        app.world_mut().get_mut::<WorldPosition>(bandits).unwrap().x = 40.0;
+        run_test_app(&mut app, 1);
+        assert_eq!(app.world().get::<WorldPosition>(player).unwrap().x, 40.0);
+
+        // Until they have been held long enough and are let go
+        for day in 0..CAPTIVITY_DAYS {
+            app.world_mut().send_event(NewDay { day: day + 2 });
+        }
+        run_test_app(&mut app, 1);
+        assert!(app.world().get::<Captive>(player).is_none());
+        assert!(app.world().get::<EncounterCooldown>(bandits).is_some());
+        app.world_mut().send_event(MoveParty { party: player, destination: WorldPosition { x: 75.0, y: 5.0 } });
+        run_test_app(&mut app, 1);
+        assert!(app.world().get::<MapPath>(player).is_some());
+    }
+
+    #[test]
+    fn test_encounter_battle_is_wound_up_back_on_the_map() {
+        let (mut app, player, bandits) = meet_looters();
+        choose(&mut app, EncounterAction::Attack);
+        let pending = app.world().resource::<PendingBattle>().clone();
+        assert_eq!((pending.attacker, pending.defender), (player, bandits));
+
+        // The battle is fought and the looters wiped out
+        app.world_mut().remove_resource::<PendingBattle>();
+        enter_state(&mut app, GameState::Combat);
+        app.world_mut().get_mut::<TroopRoster>(bandits).unwrap().stacks.clear();
+        enter_state(&mut app, GameState::WorldMap);
+
+        assert!(app.world().get_entity(bandits).is_err());
+        assert!(app.world().get_resource::<Encounter>().is_none());
+        assert_eq!(*app.world().resource::<State<WorldMapState>>().get(), WorldMapState::Free);
+    }
+}
@@ -0,0 +1,490 @@
+use bevy::prelude::*;
+use bevy_rand::prelude::{GlobalEntropy, WyRand};
+use rand_core::{RngCore, SeedableRng};
+use serde::{Serialize, Deserialize};
+
+use crate::core::components::{Settlement, WorldPosition};
+use crate::core::random::roll;
+
+use super::parties::spawn_ai_parties;
+
+/// Cells across and down the campaign map
+pub const MAP_WIDTH: u32 = 128;
+pub const MAP_HEIGHT: u32 = 128;
+/// World units covered by one cell
+pub const MAP_CELL_SIZE: f32 = 10.0;
+/// Elevation below which the land is under the sea
+pub const SEA_LEVEL: f32 = 0.3;
+pub const HILLS_LEVEL: f32 = 0.6;
+pub const MOUNTAINS_LEVEL: f32 = 0.75;
+/// Moisture above which lowland grows into forest
+pub const FOREST_MOISTURE: f32 = 0.55;
+/// Rivers springing from the high ground
+pub const RIVER_COUNT: u32 = 8;
+pub const SETTLEMENT_COUNT: usize = 24;
+/// Fewest cells between two settlements
+pub const MIN_SETTLEMENT_SPACING: f32 = 10.0;
+/// Each settlement builds a road to this many of its nearest neighbours
+pub const ROADS_PER_SETTLEMENT: usize = 2;
+/// Realms the settlements are shared out between, as `(id, name)`
+pub const MAP_FACTIONS: [(&str, &str); 4] = [
+    ("vlandia", "Vlandia"),
+    ("sturgia", "Sturgia"),
+    ("battania", "Battania"),
+    ("aserai", "Aserai"),
+];
+
+const NAME_STARTS: [&str; 16] = [
+    "Pra", "Ven", "Os", "Ta", "Ly", "Mar", "Dun", "Ebe", "Rho", "Sar", "Qua", "Hel", "Gal", "Var", "Zer", "Ami",
+];
+const NAME_ENDS: [&str; 12] = ["vend", "gard", "holm", "ric", "ton", "mir", "lan", "dor", "sk", "ath", "ova", "ek"];
+
+/// What covers a cell of the campaign map
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Reflect)]
+pub enum Terrain {
+    Sea,
+    /// Land on the shore
+    Coast,
+    Plains,
+    Forest,
+    Hills,
+    Mountains,
+    River,
+}
+
+impl Terrain {
+    pub fn is_land(self) -> bool {
+        self != Terrain::Sea
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MapCell {
+    pub terrain: Terrain,
+    pub road: bool,
+}
+
+/// Where a settlement was founded, before it became an entity
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SettlementSite {
+    pub name: String,
+    pub cell: UVec2,
+    pub faction_id: String,
+    pub prosperity: u32,
+    pub garrison_size: u32,
+}
+
+/// A road between two settlements, given by their index in `CampaignMap::settlements`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Road {
+    pub from: usize,
+    pub to: usize,
+    pub cells: Vec<UVec2>,
+}
+
+/// Seed the next campaign map is generated from; without one a fresh seed is drawn
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct WorldSeed(pub u64);
+
+/// The generated campaign map. Everything here follows from `seed`, so a save
+/// only has to keep the seed and whatever has changed since.
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CampaignMap {
+    pub seed: u64,
+    pub width: u32,
+    pub height: u32,
+    /// Row by row, `y * width + x`
+    pub cells: Vec<MapCell>,
+    pub settlements: Vec<SettlementSite>,
+    pub roads: Vec<Road>,
+}
+
+impl CampaignMap {
+    pub fn cell(&self, cell: UVec2) -> Option<&MapCell> {
+        (cell.x < self.width && cell.y < self.height).then(|| &self.cells[(cell.y * self.width + cell.x) as usize])
+    }
+
+    pub fn terrain(&self, cell: UVec2) -> Option<Terrain> {
+        self.cell(cell).map(|cell| cell.terrain)
+    }
+
+    /// The cell a point in the world lies in
+    pub fn cell_at(&self, position: &WorldPosition) -> Option<UVec2> {
+        let (x, y) = (position.x / MAP_CELL_SIZE, position.y / MAP_CELL_SIZE);
+        if x < 0.0 || y < 0.0 || x >= self.width as f32 || y >= self.height as f32 {
+            return None;
+        }
+        Some(UVec2::new(x as u32, y as u32))
+    }
+
+    /// The middle of `cell` in world units
+    pub fn world_position(&self, cell: UVec2) -> WorldPosition {
+        WorldPosition {
+            x: (cell.x as f32 + 0.5) * MAP_CELL_SIZE,
+            y: (cell.y as f32 + 0.5) * MAP_CELL_SIZE,
+        }
+    }
+
+    /// Settlements a road runs to from the settlement at `index`
+    pub fn connections(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        self.roads.iter().filter_map(move |road| match (road.from, road.to) {
+            (from, to) if from == index => Some(to),
+            (from, to) if to == index => Some(from),
+            _ => None,
+        })
+    }
+
+    fn neighbours(&self, cell: UVec2) -> impl Iterator<Item = UVec2> + '_ {
+        [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y]
+            .into_iter()
+            .map(move |step| cell.as_ivec2() + step)
+            .filter(|next| next.x >= 0 && next.y >= 0 && next.x < self.width as i32 && next.y < self.height as i32)
+            .map(|next| next.as_uvec2())
+    }
+
+    fn set_terrain(&mut self, cell: UVec2, terrain: Terrain) {
+        let index = (cell.y * self.width + cell.x) as usize;
+        self.cells[index].terrain = terrain;
+    }
+}
+
+/// Smooth noise in `[0, 1]`: random values on lattices of several spacings,
+/// blended between lattice points and added up with finer lattices counting less
+fn fractal_noise(rng: &mut impl RngCore, width: u32, height: u32, spacings: &[u32]) -> Vec<f32> {
+    let mut noise = vec![0.0; (width * height) as usize];
+    let mut total_weight = 0.0;
+    let mut weight = 1.0;
+    for &spacing in spacings {
+        let lattice_width = width / spacing + 2;
+        let lattice_height = height / spacing + 2;
+        let lattice: Vec<f32> = (0..lattice_width * lattice_height).map(|_| roll(rng)).collect();
+        let at = |x: u32, y: u32| lattice[(y * lattice_width + x) as usize];
+        for y in 0..height {
+            for x in 0..width {
+                let (cx, cy) = (x / spacing, y / spacing);
+                let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+                let tx = smooth((x % spacing) as f32 / spacing as f32);
+                let ty = smooth((y % spacing) as f32 / spacing as f32);
+                let top = at(cx, cy) + (at(cx + 1, cy) - at(cx, cy)) * tx;
+                let bottom = at(cx, cy + 1) + (at(cx + 1, cy + 1) - at(cx, cy + 1)) * tx;
+                noise[(y * width + x) as usize] += (top + (bottom - top) * ty) * weight;
+            }
+        }
+        total_weight += weight;
+        weight *= 0.5;
+    }
+    for value in noise.iter_mut() {
+        *value /= total_weight;
+    }
+    noise
+}
+
+/// Generates the campaign map for `seed`. Only integer steps and the seeded
+/// rng decide anything, so the same seed always gives the same map.
+pub fn generate_campaign_map(seed: u64) -> CampaignMap {
+    let mut rng = WyRand::seed_from_u64(seed);
+    let (width, height) = (MAP_WIDTH, MAP_HEIGHT);
+
+    let elevation = fractal_noise(&mut rng, width, height, &[32, 16, 8]);
+    let moisture = fractal_noise(&mut rng, width, height, &[16, 8]);
+    let mut map = CampaignMap {
+        seed,
+        width,
+        height,
+        cells: Vec::with_capacity((width * height) as usize),
+        settlements: Vec::new(),
+        roads: Vec::new(),
+    };
+
+    // The land falls away into the sea towards the edges
+    let mut heights = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let index = (y * width + x) as usize;
+            let dx = (x as f32 + 0.5) / width as f32 * 2.0 - 1.0;
+            let dy = (y as f32 + 0.5) / height as f32 * 2.0 - 1.0;
+            let falloff = (dx * dx + dy * dy).min(1.0);
+            let elevation = (elevation[index] * (1.2 - falloff)).clamp(0.0, 1.0);
+            heights.push(elevation);
+            let terrain = if elevation < SEA_LEVEL {
+                Terrain::Sea
+            } else if elevation >= MOUNTAINS_LEVEL {
+                Terrain::Mountains
+            } else if elevation >= HILLS_LEVEL {
+                Terrain::Hills
+            } else if moisture[index] >= FOREST_MOISTURE {
+                Terrain::Forest
+            } else {
+                Terrain::Plains
+            };
+            map.cells.push(MapCell { terrain, road: false });
+        }
+    }
+    let height_at = |cell: UVec2| heights[(cell.y * width + cell.x) as usize];
+
+    // Lowland on the shore is coast
+    for y in 0..height {
+        for x in 0..width {
+            let cell = UVec2::new(x, y);
+            let terrain = map.terrain(cell).unwrap();
+            let shore = map.neighbours(cell).any(|next| map.terrain(next) == Some(Terrain::Sea));
+            if shore && matches!(terrain, Terrain::Plains | Terrain::Forest) {
+                map.set_terrain(cell, Terrain::Coast);
+            }
+        }
+    }
+
+    // Rivers rise in the high ground and run downhill until they reach the sea
+    let springs: Vec<UVec2> = (0..width * height)
+        .map(|index| UVec2::new(index % width, index / width))
+        .filter(|cell| matches!(map.terrain(*cell), Some(Terrain::Hills | Terrain::Mountains)))
+        .collect();
+    for _ in 0..RIVER_COUNT {
+        if springs.is_empty() {
+            break;
+        }
+        let mut cell = springs[rng.next_u32() as usize % springs.len()];
+        for _ in 0..width + height {
+            if map.terrain(cell) == Some(Terrain::Sea) {
+                break;
+            }
+            map.set_terrain(cell, Terrain::River);
+            // A river that finds no lower ground cuts through the lowest rise around it
+            let Some(next) = map
+                .neighbours(cell)
+                .filter(|next| map.terrain(*next) != Some(Terrain::River))
+                .min_by(|a, b| height_at(*a).total_cmp(&height_at(*b)))
+            else {
+                break;
+            };
+            cell = next;
+        }
+    }
+
+    place_settlements(&mut map, &mut rng);
+    build_roads(&mut map);
+    map
+}
+
+/// Founds settlements on open ground at least `MIN_SETTLEMENT_SPACING` apart,
+/// then shares them out among the realms around capitals spread across the map
+fn place_settlements(map: &mut CampaignMap, rng: &mut impl RngCore) {
+    let sites: Vec<UVec2> = (0..map.width * map.height)
+        .map(|index| UVec2::new(index % map.width, index / map.width))
+        .filter(|cell| matches!(map.terrain(*cell), Some(Terrain::Plains | Terrain::Forest | Terrain::Hills | Terrain::Coast)))
+        .collect();
+    if sites.is_empty() {
+        return;
+    }
+
+    let mut founded: Vec<UVec2> = Vec::new();
+    for _ in 0..SETTLEMENT_COUNT * 50 {
+        if founded.len() == SETTLEMENT_COUNT {
+            break;
+        }
+        let cell = sites[rng.next_u32() as usize % sites.len()];
+        if founded.iter().all(|other| other.as_vec2().distance(cell.as_vec2()) >= MIN_SETTLEMENT_SPACING) {
+            founded.push(cell);
+        }
+    }
+
+    // Capitals as far from each other as they can be; every settlement joins the nearest
+    let mut capitals = vec![founded[0]];
+    while capitals.len() < MAP_FACTIONS.len().min(founded.len()) {
+        let distance_to_capitals = |cell: &UVec2| {
+            capitals.iter().map(|capital| capital.as_vec2().distance(cell.as_vec2())).fold(f32::MAX, f32::min)
+        };
+        let furthest = founded
+            .iter()
+            .max_by(|a, b| distance_to_capitals(a).total_cmp(&distance_to_capitals(b)))
+            .copied()
+            .unwrap();
+        capitals.push(furthest);
+    }
+
+    for cell in founded {
+        let realm = capitals
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.as_vec2().distance(cell.as_vec2()).total_cmp(&b.as_vec2().distance(cell.as_vec2())))
+            .map_or(0, |(index, _)| index);
+        // Trade along the water makes a place richer
+        let watered = map
+            .neighbours(cell)
+            .any(|next| matches!(map.terrain(next), Some(Terrain::Sea | Terrain::River)));
+        let prosperity = 300 + rng.next_u32() % 900 + if watered { 200 } else { 0 };
+
+        let mut name = String::new();
+        for attempt in 0..10 {
+            name = format!(
+                "{}{}",
+                NAME_STARTS[rng.next_u32() as usize % NAME_STARTS.len()],
+                NAME_ENDS[rng.next_u32() as usize % NAME_ENDS.len()],
+            );
+            if map.settlements.iter().all(|site| site.name != name) {
+                break;
+            }
+            if attempt == 9 {
+                name = format!("{} {}", name, map.settlements.len() + 1);
+            }
+        }
+
+        map.settlements.push(SettlementSite {
+            name,
+            cell,
+            faction_id: MAP_FACTIONS[realm].0.to_string(),
+            prosperity,
+            garrison_size: 20 + prosperity / 25,
+        });
+    }
+}
+
+/// Links every settlement to its nearest neighbours by road. Roads run
+/// straight over land and ford rivers; a pair with the sea between them stays apart.
+fn build_roads(map: &mut CampaignMap) {
+    let mut pairs: Vec<(usize, usize)> = Vec::new();
+    for (index, site) in map.settlements.iter().enumerate() {
+        let mut others: Vec<usize> = (0..map.settlements.len()).filter(|other| *other != index).collect();
+        let distance = |other: &usize| map.settlements[*other].cell.as_vec2().distance(site.cell.as_vec2());
+        others.sort_by(|a, b| distance(a).total_cmp(&distance(b)).then(a.cmp(b)));
+        for other in others.into_iter().take(ROADS_PER_SETTLEMENT) {
+            let pair = (index.min(other), index.max(other));
+            if !pairs.contains(&pair) {
+                pairs.push(pair);
+            }
+        }
+    }
+
+    for (from, to) in pairs {
+        let cells = line_cells(map.settlements[from].cell, map.settlements[to].cell);
+        // No road is built across the sea or over the mountains
+        if cells.iter().any(|cell| matches!(map.terrain(*cell), Some(Terrain::Sea | Terrain::Mountains))) {
+            continue;
+        }
+        for cell in &cells {
+            let index = (cell.y * map.width + cell.x) as usize;
+            map.cells[index].road = true;
+        }
+        map.roads.push(Road { from, to, cells });
+    }
+}
+
+/// Cells on the straight line from `from` to `to`, both ends included
+fn line_cells(from: UVec2, to: UVec2) -> Vec<UVec2> {
+    let (mut x, mut y) = (from.x as i32, from.y as i32);
+    let (dx, dy) = ((to.x as i32 - x).abs(), -(to.y as i32 - y).abs());
+    let (sx, sy) = (if x < to.x as i32 { 1 } else { -1 }, if y < to.y as i32 { 1 } else { -1 });
+    let mut error = dx + dy;
+    let mut cells = vec![from];
+    while (x, y) != (to.x as i32, to.y as i32) {
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += sx;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += sy;
+        }
+        cells.push(UVec2::new(x as u32, y as u32));
+    }
+    cells
+}
+
+/// Generates the campaign map the first time the map is shown, founds its
+/// settlements and sends out the first parties. A loaded game brings its own map and settlements.
+pub(super) fn generate_world(
+    mut commands: Commands,
+    seed: Option<Res<WorldSeed>>,
+    rng: Option<ResMut<GlobalEntropy<WyRand>>>,
+) {
+    let seed = match (seed, rng) {
+        (Some(seed), _) => seed.0,
+        (None, Some(mut rng)) => rng.next_u64(),
+        (None, None) => 0,
+    };
+    info!("Generating campaign map from seed {}", seed);
+    let map = generate_campaign_map(seed);
+    let mut settlements = Vec::with_capacity(map.settlements.len());
+    for site in &map.settlements {
+        let settlement = commands.spawn((
+            Name::new(site.name.clone()),
+            Settlement {
+                name: site.name.clone(),
+                prosperity: site.prosperity,
+                garrison_size: site.garrison_size,
+                owner_faction_id: site.faction_id.clone(),
+            },
+            map.world_position(site.cell),
+        ));
+        settlements.push(settlement.id());
+    }
+    // The parties draw from a stream of their own, so the map never depends on them
+    let mut party_rng = WyRand::seed_from_u64(seed.wrapping_add(1));
+    spawn_ai_parties(&mut commands, &map, &settlements, &mut party_rng);
+    commands.insert_resource(map);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::GameState;
+    use crate::test_support::{world_map_app, enter_state};
+
+    #[test]
+    fn test_campaign_map_is_reproducible_from_its_seed() {
+        let map = generate_campaign_map(2024);
+        let bytes = serde_json::to_vec(&map).unwrap();
+        assert_eq!(serde_json::to_vec(&generate_campaign_map(2024)).unwrap(), bytes);
+        assert_ne!(serde_json::to_vec(&generate_campaign_map(2025)).unwrap(), bytes);
+
+        // Sea round the edges, shore and open land inside
+        assert_eq!(map.terrain(UVec2::ZERO), Some(Terrain::Sea));
+        for terrain in [Terrain::Sea, Terrain::Coast, Terrain::Plains] {
+            assert!(map.cells.iter().any(|cell| cell.terrain == terrain), "no {terrain:?}");
+        }
+
+        assert!(!map.settlements.is_empty());
+        for (index, site) in map.settlements.iter().enumerate() {
+            assert!(map.terrain(site.cell).is_some_and(|terrain| terrain.is_land() && terrain != Terrain::River));
+            for other in &map.settlements[index + 1..] {
+                assert!(site.cell.as_vec2().distance(other.cell.as_vec2()) >= 10.0);
+            }
+        }
+        assert!(!map.roads.is_empty());
+        for road in &map.roads {
+            assert_eq!(road.cells.first(), Some(&map.settlements[road.from].cell));
+            assert_eq!(road.cells.last(), Some(&map.settlements[road.to].cell));
+            assert!(road.cells.iter().all(|cell| {
+                map.cell(*cell).is_some_and(|cell| cell.road && cell.terrain.is_land() && cell.terrain != Terrain::Mountains)
+            }));
+        }
+    }
+
+    #[test]
+    fn test_world_map_founds_settlements_once() {
+        let mut app = world_map_app();
+        app.insert_resource(WorldSeed(99));
+        enter_state(&mut app, GameState::WorldMap);
+
+        let map = app.world().resource::<CampaignMap>().clone();
+        assert_eq!(map, generate_campaign_map(99));
+        let settlements: Vec<(Settlement, WorldPosition)> = app
+            .world_mut()
+            .query::<(&Settlement, &WorldPosition)>()
+            .iter(app.world())
+            .map(|(settlement, position)| (settlement.clone(), position.clone()))
+            .collect();
+        assert_eq!(settlements.len(), map.settlements.len());
+        for site in &map.settlements {
+            let (settlement, position) = settlements.iter().find(|(settlement, _)| settlement.name == site.name).unwrap();
+            assert_eq!(settlement.owner_faction_id, site.faction_id);
+            assert_eq!(map.cell_at(position), Some(site.cell));
+        }
+
+        // Coming back to the map keeps the world it already has
+        enter_state(&mut app, GameState::MainMenu);
+        enter_state(&mut app, GameState::WorldMap);
+        assert_eq!(app.world_mut().query::<&Settlement>().iter(app.world()).count(), map.settlements.len());
+    }
+}
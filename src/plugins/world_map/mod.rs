@@ -0,0 +1,288 @@
+use bevy::prelude::*;
+use bevy_egui::EguiUserTextures;
+use crate::core::states::{GameState, WorldMapState};
+use bevy::window::PrimaryWindow;
+use crate::core::components::{WorldPosition, Player, Stamina, ForcedMarch, Exhausted, BattleSide};
+
+mod siege;
+mod battles;
+mod generation;
+mod movement;
+mod calendar;
+mod parties;
+mod encounters;
+
+pub use battles::PendingBattle;
+pub use siege::garrison_roster;
+pub use generation::{
+    CampaignMap, MapCell, Terrain, WorldSeed, generate_campaign_map, MAP_WIDTH, MAP_HEIGHT,
+    MAP_CELL_SIZE,
+};
+pub use movement::MoveParty;
+pub use encounters::CAPTIVITY_DAYS;
+pub use calendar::{
+    CampaignClock, ClockSpeed, Season, NewHour, NewDay, NewSeason, HOURS_PER_DAY,
+    DAYS_PER_SEASON,
+};
+
+/// World units across each pixel of the screen when looking at the campaign map
+pub const WORLD_MAP_CAMERA_SCALE: f32 = 2.0;
+
+#[derive(Component, Debug, Clone)]
+pub struct WorldMapCamera;
+
+pub struct WorldMapPlugin;
+
+impl Plugin for WorldMapPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            // Register the world map substate
+            .add_sub_state::<WorldMapState>()
+            
+            // Siege events
+            .add_event::<siege::BeginSiege>()
+            .add_event::<siege::BuildSiegeEngine>()
+            .add_event::<siege::AssaultSettlement>()
+            .add_event::<siege::LiftSiege>()
+            .add_event::<battles::EngageBattle>()
+            .add_event::<movement::MoveParty>()
+            .add_event::<encounters::EncounterChoice>()
+
+            // Campaign time
+            .init_resource::<calendar::CampaignClock>()
+            .add_event::<calendar::NewHour>()
+            .add_event::<calendar::NewDay>()
+            .add_event::<calendar::NewSeason>()
+            
+            // Add systems that run only in WorldMap state
+            .add_systems(
+                Update, 
+                (
+                    handle_world_map_input,
+                    update_world_map,
+                    (calendar::shade_day_and_night, parties::spot_parties),
+                )
+                .chain()
+                .run_if(in_state(GameState::WorldMap))
+            )
+            .add_systems(
+                Update,
+                calendar::clock_display
+                    .run_if(in_state(GameState::WorldMap).and(resource_exists::<EguiUserTextures>))
+            )
+            
+            // AI parties take stock each hour, and only travel while nothing on the map is
+            // waiting on the player
+            .add_systems(
+                Update,
+                (parties::plan_party_goals, parties::pursue_party_goals)
+                    .chain()
+                    .after(update_world_map)
+                    .before(movement::plan_party_routes)
+                    .run_if(in_state(WorldMapState::Free).and(on_event::<calendar::NewHour>))
+            )
+            .add_systems(
+                Update,
+                (movement::plan_party_routes, movement::move_parties)
+                    .chain()
+                    .after(handle_world_map_input)
+                    .run_if(in_state(WorldMapState::Free).and(resource_exists::<generation::CampaignMap>))
+            )
+
+            // Captives go where their captors go until they are let go
+            .add_systems(
+                Update,
+                encounters::hold_captives
+                    .after(movement::move_parties)
+                    .before(encounters::detect_encounters)
+                    .run_if(in_state(GameState::WorldMap))
+            )
+
+            // Parties that come together stop the map until the player deals with them
+            .add_systems(
+                Update,
+                encounters::detect_encounters
+                    .after(movement::move_parties)
+                    .run_if(
+                        in_state(WorldMapState::Free)
+                            .and(not(resource_exists::<encounters::Encounter>))
+                            .and(not(resource_exists::<battles::PendingBattle>))
+                    )
+            )
+
+            // Systems for different world map substates
+            .add_systems(
+                Update,
+                encounters::handle_encounter
+                    .run_if(in_state(WorldMapState::Encounter).and(resource_exists::<encounters::Encounter>))
+            )
+            .add_systems(
+                Update,
+                encounters::encounter_menu.run_if(
+                    in_state(WorldMapState::Encounter)
+                        .and(resource_exists::<encounters::Encounter>)
+                        .and(resource_exists::<EguiUserTextures>)
+                )
+            )
+            // Battles the player fights in person or by auto-resolve; AI parties always auto-resolve
+            .add_systems(
+                Update,
+                battles::engage_battles.run_if(in_state(GameState::WorldMap))
+            )
+            .add_systems(
+                Update,
+                battles::battle_choice_menu.run_if(
+                    in_state(GameState::WorldMap)
+                        .and(resource_exists::<battles::PendingBattle>)
+                        .and(resource_exists::<EguiUserTextures>)
+                )
+            )
+            // Sieges carry on across the map, whoever is besieging whom
+            .add_systems(
+                Update,
+                (
+                    siege::handle_besiege_input.run_if(in_state(WorldMapState::Free)),
+                    siege::handle_siege_orders,
+                    (siege::progress_sieges, siege::starve_besieged),
+                    siege::launch_assault,
+                )
+                    .chain()
+                    .after(update_world_map)
+                    .run_if(in_state(GameState::WorldMap))
+            )
+            .add_systems(
+                Update,
+                siege::siege_menu
+                    .run_if(in_state(WorldMapState::Siege).and(resource_exists::<EguiUserTextures>))
+            )
+            
+            // Systems for entering/exiting world map
+            .add_systems(
+                OnEnter(GameState::WorldMap),
+                (
+                    setup_world_map,
+                    generation::generate_world.run_if(not(resource_exists::<generation::CampaignMap>)),
+                    // A battle fought from an encounter has just come back to the map
+                    encounters::conclude_encounter,
+                )
+            )
+            .add_systems(OnExit(GameState::WorldMap), cleanup_world_map);
+    }
+}
+
+// World map systems
+fn setup_world_map(mut commands: Commands) {
+    info!("Setting up world map");
+    // Create map visuals
+    // Top-down camera over the middle of the map
+    let centre = Vec2::new(MAP_WIDTH as f32, MAP_HEIGHT as f32) * MAP_CELL_SIZE / 2.0;
+    commands.spawn((
+        WorldMapCamera,
+        StateScoped(GameState::WorldMap),
+        Camera2d,
+        OrthographicProjection {
+            scale: WORLD_MAP_CAMERA_SCALE,
+            ..OrthographicProjection::default_2d()
+        },
+        Transform::from_xyz(centre.x, centre.y, 0.0),
+    ));
+}
+
+fn cleanup_world_map() {
+    info!("Cleaning up world map");
+    // Map visuals are spawned with `StateScoped(GameState::WorldMap)` and despawn on their own;
+    // parties and settlements outlive the map view and are never scoped
+}
+
+/// Moves campaign time on, unless an encounter or a pending battle is waiting
+/// on the player, and announces each hour, day and season as it turns
+fn update_world_map(
+    time: Res<Time>,
+    map_state: Option<Res<State<WorldMapState>>>,
+    pending: Option<Res<PendingBattle>>,
+    mut clock: ResMut<CampaignClock>,
+    mut hours: EventWriter<NewHour>,
+    mut days: EventWriter<NewDay>,
+    mut seasons: EventWriter<NewSeason>,
+) {
+    clock.held = pending.is_some()
+        || map_state.is_some_and(|state| *state.get() == WorldMapState::Encounter);
+
+    let before = clock.total_hours();
+    clock.elapsed += (time.delta_secs() * clock.rate()) as f64;
+    for hour in before + 1..=clock.total_hours() {
+        hours.send(NewHour { hour });
+        if hour % HOURS_PER_DAY != 0 {
+            continue;
+        }
+        let day = (hour / HOURS_PER_DAY) as u32 + 1;
+        days.send(NewDay { day });
+        if (day - 1) as u64 % DAYS_PER_SEASON == 0 {
+            let seasons_passed = (day - 1) as u64 / DAYS_PER_SEASON;
+            seasons.send(NewSeason {
+                season: Season::ALL[(seasons_passed % Season::ALL.len() as u64) as usize],
+                year: (seasons_passed / Season::ALL.len() as u64) as u32 + 1,
+            });
+        }
+    }
+
+    // Weather, etc.
+}
+
+fn handle_world_map_input(
+    mut commands: Commands,
+    keys: Option<Res<ButtonInput<KeyCode>>>,
+    mouse: Option<Res<ButtonInput<MouseButton>>>,
+    map_state: Option<Res<State<WorldMapState>>>,
+    mut clock: ResMut<CampaignClock>,
+    mut moves: EventWriter<movement::MoveParty>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<WorldMapCamera>>,
+    travellers: Query<Entity, (With<Player>, With<WorldPosition>, Without<BattleSide>)>,
+    party: Query<(Entity, Has<ForcedMarch>, Has<Exhausted>), (With<Player>, With<Stamina>, Without<BattleSide>)>,
+) {
+    // Left click sends the party to the clicked spot, unless a menu is open
+    let free = map_state.is_some_and(|state| *state.get() == WorldMapState::Free);
+    if free && mouse.is_some_and(|mouse| mouse.just_pressed(MouseButton::Left)) {
+        let clicked = windows
+            .get_single()
+            .ok()
+            .and_then(Window::cursor_position)
+            .zip(cameras.get_single().ok())
+            .and_then(|(cursor, (camera, transform))| camera.viewport_to_world_2d(transform, cursor).ok());
+        if let (Some(point), Ok(player)) = (clicked, travellers.get_single()) {
+            moves.send(movement::MoveParty {
+                party: player,
+                destination: WorldPosition { x: point.x, y: point.y },
+            });
+        }
+    }
+
+    let Some(keys) = keys else {
+        return;
+    };
+    // Space pauses and resumes campaign time, 1 and 2 set it running at normal and fast speed
+    if keys.just_pressed(KeyCode::Space) {
+        clock.speed = match clock.speed {
+            ClockSpeed::Paused => ClockSpeed::Normal,
+            _ => ClockSpeed::Paused,
+        };
+    }
+    if keys.just_pressed(KeyCode::Digit1) {
+        clock.speed = ClockSpeed::Normal;
+    }
+    if keys.just_pressed(KeyCode::Digit2) {
+        clock.speed = ClockSpeed::FastForward;
+    }
+
+    // M toggles a forced march, which an exhausted party can't start
+    if keys.just_pressed(KeyCode::KeyM) {
+        if let Ok((entity, marching, exhausted)) = party.get_single() {
+            if marching {
+                commands.entity(entity).remove::<ForcedMarch>();
+            } else if !exhausted {
+                commands.entity(entity).insert(ForcedMarch);
+            }
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,344 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+use crate::core::components::{WorldPosition, MapPath, TroopRoster, CharacterController, ForcedMarch, Besieging, Captive};
+
+use super::generation::{CampaignMap, Terrain};
+use super::calendar::CampaignClock;
+
+/// Cost of crossing a cell of open plains; every other cost is measured against it
+pub const PLAINS_COST: u32 = 10;
+pub const ROAD_COST: u32 = 5;
+pub const HILLS_COST: u32 = 15;
+pub const FOREST_COST: u32 = 20;
+/// Fording a river off the roads
+pub const RIVER_COST: u32 = 30;
+/// World units a party covers each second on plains when it has no captain to set the pace
+pub const DEFAULT_PARTY_SPEED: f32 = 5.0;
+/// Speed lost for each troop in the party
+pub const PARTY_SIZE_SLOWDOWN: f32 = 0.002;
+/// Slowest a large party gets, as a share of its captain's pace
+pub const MIN_PARTY_SIZE_FACTOR: f32 = 0.5;
+/// Speed gained by a party whose troops all ride
+pub const MOUNTED_SPEED_BONUS: f32 = 0.3;
+pub const FORCED_MARCH_SPEED_MULTIPLIER: f32 = 1.3;
+
+/// Send a party across the campaign map. The player's clicks and the AI's
+/// plans both come through here.
+#[derive(Event, Debug, Clone)]
+pub struct MoveParty {
+    pub party: Entity,
+    pub destination: WorldPosition,
+}
+
+/// Cost of entering a cell, or `None` where no party can go. Mountains and sea
+/// are impassable road or no road; elsewhere a road is the quickest going.
+pub fn terrain_cost(terrain: Terrain, road: bool) -> Option<u32> {
+    match terrain {
+        Terrain::Mountains | Terrain::Sea => None,
+        _ if road => Some(ROAD_COST),
+        Terrain::Plains | Terrain::Coast => Some(PLAINS_COST),
+        Terrain::Hills => Some(HILLS_COST),
+        Terrain::Forest => Some(FOREST_COST),
+        Terrain::River => Some(RIVER_COST),
+    }
+}
+
+/// Cost of entering `cell`, or `None` off the map or where it can't be crossed
+pub fn cell_cost(map: &CampaignMap, cell: UVec2) -> Option<u32> {
+    map.cell(cell).and_then(|cell| terrain_cost(cell.terrain, cell.road))
+}
+
+/// World units a party covers each second of campaign time on plains: its captain's pace,
+/// slowed by its numbers and quickened by its horses and a forced march
+pub fn party_speed(roster: Option<&TroopRoster>, controller: Option<&CharacterController>, forced_march: bool) -> f32 {
+    let mut speed = controller.map_or(DEFAULT_PARTY_SPEED, |controller| controller.movement_speed);
+    if let Some(roster) = roster {
+        let total = roster.total_count();
+        speed *= (1.0 - total as f32 * PARTY_SIZE_SLOWDOWN).max(MIN_PARTY_SIZE_FACTOR);
+        if total > 0 {
+            let mounted: u32 = roster.stacks.iter().filter(|stack| stack.mounted).map(|stack| stack.count).sum();
+            speed *= 1.0 + MOUNTED_SPEED_BONUS * mounted as f32 / total as f32;
+        }
+    }
+    if forced_march {
+        speed *= FORCED_MARCH_SPEED_MULTIPLIER;
+    }
+    speed
+}
+
+/// A cell waiting to be explored, cheapest estimate first
+#[derive(PartialEq, Eq)]
+struct OpenCell {
+    estimate: u32,
+    cost: u32,
+    cell: UVec2,
+}
+
+impl Ord for OpenCell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed, so the heap hands out the cheapest estimate; ties go to the
+        // cell furthest along, then by position so equal routes always come out the same
+        other
+            .estimate
+            .cmp(&self.estimate)
+            .then(self.cost.cmp(&other.cost))
+            .then((other.cell.y, other.cell.x).cmp(&(self.cell.y, self.cell.x)))
+    }
+}
+
+impl PartialOrd for OpenCell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Cheapest route from `from` to `to` by A*, moving between touching cells.
+/// Diagonal steps cost half as much again and may not cut the corner of a
+/// cell that can't be crossed. The route leaves out `from` and ends on `to`.
+pub fn find_path(map: &CampaignMap, from: UVec2, to: UVec2) -> Option<Vec<UVec2>> {
+    cell_cost(map, to)?;
+    map.cell(from)?;
+    if from == to {
+        return Some(Vec::new());
+    }
+
+    // Octile distance at road cost never overestimates what's left
+    let heuristic = |cell: UVec2| {
+        let (dx, dy) = (cell.x.abs_diff(to.x), cell.y.abs_diff(to.y));
+        (dx.max(dy) * 10 + dx.min(dy) * 4) * ROAD_COST / 10
+    };
+
+    let mut open = BinaryHeap::new();
+    let mut best: HashMap<UVec2, u32> = HashMap::default();
+    let mut came_from: HashMap<UVec2, UVec2> = HashMap::default();
+    best.insert(from, 0);
+    open.push(OpenCell { estimate: heuristic(from), cost: 0, cell: from });
+
+    while let Some(OpenCell { cost, cell, .. }) = open.pop() {
+        if cell == to {
+            let mut path = vec![to];
+            let mut step = to;
+            while let Some(&previous) = came_from.get(&step) {
+                if previous == from {
+                    break;
+                }
+                path.push(previous);
+                step = previous;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        if best.get(&cell).is_some_and(|&known| known < cost) {
+            continue;
+        }
+
+        for dy in -1i32..=1 {
+            for dx in -1i32..=1 {
+                if (dx, dy) == (0, 0) {
+                    continue;
+                }
+                let next = cell.as_ivec2() + IVec2::new(dx, dy);
+                if next.x < 0 || next.y < 0 {
+                    continue;
+                }
+                let next = next.as_uvec2();
+                let Some(step_cost) = cell_cost(map, next) else {
+                    continue;
+                };
+                let diagonal = dx != 0 && dy != 0;
+                if diagonal
+                    && (cell_cost(map, UVec2::new(next.x, cell.y)).is_none()
+                        || cell_cost(map, UVec2::new(cell.x, next.y)).is_none())
+                {
+                    continue;
+                }
+                let next_cost = cost + if diagonal { step_cost * 3 / 2 } else { step_cost };
+                if best.get(&next).is_some_and(|&known| known <= next_cost) {
+                    continue;
+                }
+                best.insert(next, next_cost);
+                came_from.insert(next, cell);
+                open.push(OpenCell { estimate: next_cost + heuristic(next), cost: next_cost, cell: next });
+            }
+        }
+    }
+    None
+}
+
+/// Plots a route for every party sent somewhere; one that can't get there stops.
+/// Captives are going nowhere.
+pub(super) fn plan_party_routes(
+    mut commands: Commands,
+    map: Res<CampaignMap>,
+    mut moves: EventReader<MoveParty>,
+    parties: Query<&WorldPosition, Without<Captive>>,
+) {
+    for order in moves.read() {
+        let Ok(position) = parties.get(order.party) else {
+            continue;
+        };
+        let route = map
+            .cell_at(position)
+            .zip(map.cell_at(&order.destination))
+            .and_then(|(from, to)| find_path(&map, from, to));
+        match route {
+            Some(cells) if !cells.is_empty() => {
+                commands.entity(order.party).insert(MapPath { cells });
+            }
+            _ => {
+                commands.entity(order.party).remove::<MapPath>();
+            }
+        }
+    }
+}
+
+/// Walks parties along their routes, slower over rough ground and faster on roads
+pub(super) fn move_parties(
+    mut commands: Commands,
+    time: Res<Time>,
+    clock: Res<CampaignClock>,
+    map: Res<CampaignMap>,
+    mut parties: Query<(
+        Entity,
+        &mut WorldPosition,
+        &mut MapPath,
+        Option<&TroopRoster>,
+        Option<&CharacterController>,
+        Has<ForcedMarch>,
+    ), (Without<Besieging>, Without<Captive>)>,
+) {
+    for (entity, mut position, mut path, roster, controller, forced_march) in parties.iter_mut() {
+        // Distance the party could cover on plains this frame
+        let mut budget = party_speed(roster, controller, forced_march) * time.delta_secs() * clock.rate();
+        while budget > 0.0 {
+            let Some(&next) = path.cells.first() else {
+                break;
+            };
+            // The ground may have changed under a stale route
+            let Some(cost) = cell_cost(&map, next) else {
+                path.cells.clear();
+                break;
+            };
+            let factor = cost as f32 / PLAINS_COST as f32;
+            let target = map.world_position(next);
+            let offset = Vec2::new(target.x - position.x, target.y - position.y);
+            let needed = offset.length() * factor;
+            if needed <= budget {
+                position.x = target.x;
+                position.y = target.y;
+                path.cells.remove(0);
+                budget -= needed;
+            } else {
+                let step = offset.normalize_or_zero() * budget / factor;
+                position.x += step.x;
+                position.y += step.y;
+                budget = 0.0;
+            }
+        }
+        if path.cells.is_empty() {
+            commands.entity(entity).remove::<MapPath>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Player, TroopStack, WeaponType};
+    use crate::test_support::{world_map_test_app_with, plains_map, set_cell, run_test_app};
+
+    #[test]
+    fn test_pathfinding_goes_round_mountains_and_along_roads() {
+        let mut map = plains_map(6, 3);
+        set_cell(&mut map, 2, 0, Terrain::Mountains, false);
+        set_cell(&mut map, 2, 1, Terrain::Mountains, false);
+
+        // The only way through is the gap at the bottom
+        let path = find_path(&map, UVec2::new(0, 0), UVec2::new(4, 0)).unwrap();
+        assert_eq!(path.last(), Some(&UVec2::new(4, 0)));
+        assert!(path.contains(&UVec2::new(2, 2)));
+        assert!(path.iter().all(|cell| map.terrain(*cell) == Some(Terrain::Plains)));
+
+        set_cell(&mut map, 2, 2, Terrain::Mountains, false);
+        assert_eq!(find_path(&map, UVec2::new(0, 0), UVec2::new(4, 0)), None);
+
+        // A road laid over the mountains doesn't open a way through them
+        for x in 0..5 {
+            let terrain = map.terrain(UVec2::new(x, 0)).unwrap();
+            set_cell(&mut map, x, 0, terrain, true);
+        }
+        assert_eq!(terrain_cost(Terrain::Mountains, true), None);
+        assert_eq!(find_path(&map, UVec2::new(0, 0), UVec2::new(4, 0)), None);
+
+        // Over open country a road is worth the detour
+        let mut map = plains_map(7, 2);
+        for x in 0..7 {
+            set_cell(&mut map, x, 1, Terrain::Plains, true);
+        }
+        let path = find_path(&map, UVec2::new(0, 0), UVec2::new(6, 0)).unwrap();
+        assert_eq!(path.last(), Some(&UVec2::new(6, 0)));
+        assert!(path.contains(&UVec2::new(3, 1)));
+    }
+
+    #[test]
+    fn test_party_speed_from_size_mounts_and_captain() {
+        let stack = |count: u32, mounted: bool| TroopStack {
+            troop_id: "recruit".to_string(),
+            tier: 1,
+            weapon_type: WeaponType::Spear,
+            count,
+            wounded: 0,
+            mounted,
+        };
+        let mut small = TroopRoster::default();
+        small.add(stack(10, false));
+        let mut large = TroopRoster::default();
+        large.add(stack(200, false));
+        let mut riders = TroopRoster::default();
+        riders.add(stack(10, true));
+        let captain = CharacterController { movement_speed: 8.0, rotation_speed: 1.0 };
+
+        assert!(party_speed(Some(&large), None, false) < party_speed(Some(&small), None, false));
+        assert!(party_speed(Some(&riders), None, false) > party_speed(Some(&small), None, false));
+        assert!(party_speed(Some(&small), Some(&captain), false) > party_speed(Some(&small), None, false));
+        assert!(party_speed(Some(&small), None, true) > party_speed(Some(&small), None, false));
+    }
+
+    fn send_party(app: &mut App, destination: WorldPosition) -> Entity {
+        let party = app.world_mut().spawn((Player, WorldPosition { x: 5.0, y: 5.0 })).id();
+        app.world_mut().send_event(MoveParty { party, destination });
+        party
+    }
+
+    #[test]
+    fn test_party_travels_slower_through_forest() {
+        let destination = WorldPosition { x: 55.0, y: 5.0 };
+        let mut open = world_map_test_app_with(plains_map(8, 1));
+        let mut forest_map = plains_map(8, 1);
+        for x in 1..8 {
+            set_cell(&mut forest_map, x, 0, Terrain::Forest, false);
+        }
+        let mut forest = world_map_test_app_with(forest_map);
+        let open_party = send_party(&mut open, destination.clone());
+        let forest_party = send_party(&mut forest, destination.clone());
+
+        run_test_app(&mut open, 3);
+        run_test_app(&mut forest, 3);
+        let open_x = open.world().get::<WorldPosition>(open_party).unwrap().x;
+        let forest_x = forest.world().get::<WorldPosition>(forest_party).unwrap().x;
+        assert!(forest_x > 5.0 && forest_x < open_x);
+
+        // Both get there in the end and stop
+        run_test_app(&mut open, 30);
+        run_test_app(&mut forest, 30);
+        for (app, party) in [(&open, open_party), (&forest, forest_party)] {
+            let position = app.world().get::<WorldPosition>(party).unwrap();
+            assert_eq!((position.x, position.y), (destination.x, destination.y));
+            assert!(app.world().get::<MapPath>(party).is_none());
+        }
+    }
+}
@@ -0,0 +1,638 @@
+use bevy::prelude::*;
+use bevy_rand::prelude::{GlobalEntropy, WyRand};
+use rand_core::RngCore;
+
+use crate::core::components::{
+    AiParty, PartyKind, PartyGoal, Spotting, Spotted, WorldPosition, Faction, TroopRoster, TroopStack,
+    WeaponType, Settlement, Player, Besieging, UnderSiege, MapPath, CharacterStats, SiegeEngine, Captive,
+};
+
+use super::battles::EngageBattle;
+use super::siege::{BeginSiege, BuildSiegeEngine, LiftSiege, garrison_roster};
+use super::movement::{MoveParty, cell_cost};
+use super::calendar::CampaignClock;
+use super::generation::{CampaignMap, MAP_CELL_SIZE, MAP_FACTIONS};
+
+/// World units a party with no scouting to speak of can see
+pub const BASE_SPOTTING_RANGE: f32 = 60.0;
+/// Extra sight for each point of spotting skill
+pub const SPOTTING_RANGE_PER_SKILL: f32 = 0.5;
+/// Share of its sight a party keeps after dark
+pub const NIGHT_SPOTTING_MULTIPLIER: f32 = 0.6;
+/// A party runs from one this many times its strength
+pub const FLEE_STRENGTH_RATIO: f32 = 1.5;
+/// A party only falls on one it outweighs this many times over
+pub const ATTACK_STRENGTH_RATIO: f32 = 1.2;
+/// A lord only lays siege with this many times the garrison's strength
+pub const BESIEGE_STRENGTH_RATIO: f32 = 2.0;
+/// Below this share of healthy troops a party heads home
+pub const RETURN_HEALTHY_RATIO: f32 = 0.5;
+/// How close a party has to come to reach a settlement or catch another party
+pub const ARRIVAL_DISTANCE: f32 = MAP_CELL_SIZE;
+/// Furthest a patrol strays from the point it watches
+pub const PATROL_RADIUS: f32 = 50.0;
+/// How far a fleeing party tries to open the gap each time it looks back
+pub const FLEE_DISTANCE: f32 = 80.0;
+/// Prosperity a settlement gains each time a caravan comes to trade
+pub const CARAVAN_TRADE_PROSPERITY: u32 = 10;
+/// Prosperity a settlement gains each time villagers bring their produce to market
+pub const VILLAGER_TRADE_PROSPERITY: u32 = 3;
+pub const LORDS_PER_FACTION: usize = 2;
+pub const CARAVANS_PER_FACTION: usize = 1;
+pub const BANDIT_PARTY_COUNT: usize = 6;
+/// Faction every bandit party belongs to, as `(id, name)`
+pub const BANDIT_FACTION: (&str, &str) = ("looters", "Looters");
+/// How much more a rider counts for in a party's strength
+const MOUNTED_STRENGTH_MULTIPLIER: f32 = 1.5;
+
+/// Rough fighting weight of a party's healthy troops, the better trained and
+/// the mounted counting for more
+pub fn party_strength(roster: &TroopRoster) -> f32 {
+    roster
+        .stacks
+        .iter()
+        .map(|stack| {
+            let weight = stack.tier.max(1) as f32 * if stack.mounted { MOUNTED_STRENGTH_MULTIPLIER } else { 1.0 };
+            stack.count.saturating_sub(stack.wounded) as f32 * weight
+        })
+        .sum()
+}
+
+/// How far a party can see others on the map
+pub fn spotting_range(spotting: Option<&Spotting>, night: bool) -> f32 {
+    let range = BASE_SPOTTING_RANGE + spotting.map_or(0, |spotting| spotting.skill) as f32 * SPOTTING_RANGE_PER_SKILL;
+    if night {
+        range * NIGHT_SPOTTING_MULTIPLIER
+    } else {
+        range
+    }
+}
+
+/// A party on the map as the planner sees it
+#[derive(Debug, Clone)]
+pub struct PartyView {
+    pub entity: Entity,
+    /// `None` for the player's party
+    pub kind: Option<PartyKind>,
+    pub faction_id: String,
+    pub position: Vec2,
+    pub strength: f32,
+}
+
+/// A settlement as the planner sees it
+#[derive(Debug, Clone)]
+pub struct SettlementView {
+    pub entity: Entity,
+    pub faction_id: String,
+    pub position: Vec2,
+    /// Strength of its garrison
+    pub defence: f32,
+    pub besieged: bool,
+}
+
+/// Everything on the map an AI party weighs up
+pub struct PlannerView<'a> {
+    pub parties: &'a [PartyView],
+    pub settlements: &'a [SettlementView],
+    /// How far the planning party can see
+    pub range: f32,
+}
+
+/// Whether two parties come to blows when they meet. Lords and bandits fall
+/// on anyone of another faction; the player, for now, only has bandits to fear.
+pub fn hostile(a: &PartyView, b: &PartyView) -> bool {
+    match (a.kind, b.kind) {
+        (None, None) => false,
+        (None, Some(kind)) | (Some(kind), None) => kind == PartyKind::Bandit,
+        (Some(a_kind), Some(b_kind)) => {
+            a.faction_id != b.faction_id
+                && [a_kind, b_kind].iter().any(|kind| matches!(kind, PartyKind::Lord | PartyKind::Bandit))
+        }
+    }
+}
+
+/// What an AI party does next. Getting away from a stronger enemy in sight
+/// comes first, then going home to heal; errands under way are seen through;
+/// otherwise each kind of party goes about its own business.
+pub fn choose_goal(
+    party: &PartyView,
+    ai: &AiParty,
+    healthy_ratio: f32,
+    view: &PlannerView,
+    rng: &mut impl RngCore,
+) -> PartyGoal {
+    let distance = |position: Vec2| party.position.distance(position);
+    let settlement = |entity: Entity| view.settlements.iter().find(|settlement| settlement.entity == entity);
+    let in_sight: Vec<&PartyView> = view
+        .parties
+        .iter()
+        .filter(|other| other.entity != party.entity && distance(other.position) <= view.range && hostile(party, other))
+        .collect();
+
+    let threat = in_sight
+        .iter()
+        .filter(|other| other.strength > party.strength * FLEE_STRENGTH_RATIO)
+        .max_by(|a, b| a.strength.total_cmp(&b.strength));
+    if let Some(threat) = threat {
+        return PartyGoal::Flee { from: threat.entity };
+    }
+    if let Some(home) = ai.home.filter(|_| healthy_ratio < RETURN_HEALTHY_RATIO) {
+        return PartyGoal::Return { settlement: home };
+    }
+
+    let still_on = match &ai.goal {
+        PartyGoal::Trade { settlement: target } | PartyGoal::Return { settlement: target } => settlement(*target).is_some(),
+        PartyGoal::Besiege { settlement: target } => {
+            settlement(*target).is_some_and(|target| target.faction_id != party.faction_id && !target.besieged)
+        }
+        _ => false,
+    };
+    if still_on {
+        return ai.goal.clone();
+    }
+
+    let prey = in_sight
+        .iter()
+        .filter(|other| other.strength * ATTACK_STRENGTH_RATIO <= party.strength)
+        .min_by(|a, b| distance(a.position).total_cmp(&distance(b.position)));
+    let home_position = ai.home.and_then(settlement).map(|home| home.position);
+
+    match ai.kind {
+        PartyKind::Lord => {
+            if let Some(prey) = prey {
+                return PartyGoal::Raid { target: prey.entity };
+            }
+            let siege_target = view
+                .settlements
+                .iter()
+                .filter(|target| {
+                    target.faction_id != party.faction_id
+                        && !target.besieged
+                        && distance(target.position) <= view.range
+                        && target.defence * BESIEGE_STRENGTH_RATIO <= party.strength
+                })
+                .min_by(|a, b| distance(a.position).total_cmp(&distance(b.position)));
+            match siege_target {
+                Some(target) => PartyGoal::Besiege { settlement: target.entity },
+                None => PartyGoal::Patrol { around: home_position.unwrap_or(party.position) },
+            }
+        }
+        PartyKind::Bandit => match (prey, &ai.goal) {
+            (Some(prey), _) => PartyGoal::Raid { target: prey.entity },
+            // Bandits keep to the stretch of country they have been haunting
+            (None, PartyGoal::Patrol { around }) => PartyGoal::Patrol { around: *around },
+            (None, _) => PartyGoal::Patrol { around: party.position },
+        },
+        PartyKind::Caravan => {
+            // Anywhere but the market it is standing in
+            let markets: Vec<&SettlementView> = view
+                .settlements
+                .iter()
+                .filter(|market| distance(market.position) > ARRIVAL_DISTANCE)
+                .collect();
+            match markets.len() {
+                0 => PartyGoal::Patrol { around: party.position },
+                count => PartyGoal::Trade { settlement: markets[rng.next_u32() as usize % count].entity },
+            }
+        }
+        PartyKind::Villager => match ai.home.zip(home_position) {
+            Some((home, position)) if distance(position) > ARRIVAL_DISTANCE => PartyGoal::Return { settlement: home },
+            _ => {
+                // The nearest market of their own realm
+                let market = view
+                    .settlements
+                    .iter()
+                    .filter(|market| market.faction_id == party.faction_id && Some(market.entity) != ai.home)
+                    .min_by(|a, b| distance(a.position).total_cmp(&distance(b.position)));
+                match market {
+                    Some(market) => PartyGoal::Trade { settlement: market.entity },
+                    None => PartyGoal::Patrol { around: party.position },
+                }
+            }
+        },
+    }
+}
+
+fn stack(troop_id: &str, tier: u8, weapon_type: WeaponType, count: u32, mounted: bool) -> TroopStack {
+    TroopStack {
+        troop_id: troop_id.to_string(),
+        tier,
+        weapon_type,
+        count,
+        wounded: 0,
+        mounted,
+    }
+}
+
+/// The troops a new party of `kind` sets out with
+pub fn starting_roster(kind: PartyKind, rng: &mut impl RngCore) -> TroopRoster {
+    let mut between = |low: u32, high: u32| low + rng.next_u32() % (high - low + 1);
+    let mut roster = TroopRoster::default();
+    match kind {
+        PartyKind::Lord => {
+            roster.add(stack("levy_spearman", 2, WeaponType::Spear, between(30, 60), false));
+            roster.add(stack("levy_archer", 2, WeaponType::Bow, between(10, 20), false));
+            roster.add(stack("household_cavalry", 3, WeaponType::OneHandedSword, between(5, 15), true));
+        }
+        PartyKind::Bandit => {
+            roster.add(stack("looter", 1, WeaponType::OneHandedSword, between(8, 25), false));
+        }
+        PartyKind::Caravan => {
+            roster.add(stack("caravan_guard", 2, WeaponType::Spear, between(10, 20), false));
+            roster.add(stack("caravan_rider", 2, WeaponType::OneHandedSword, between(2, 6), true));
+        }
+        PartyKind::Villager => {
+            roster.add(stack("villager", 1, WeaponType::Spear, between(5, 12), false));
+        }
+    }
+    roster
+}
+
+/// Sends the first parties out onto a freshly generated map: the lords,
+/// caravans and villagers of each realm from its settlements, and bandits in
+/// the wilds between. The map's seed decides them all.
+pub(super) fn spawn_ai_parties(commands: &mut Commands, map: &CampaignMap, settlements: &[Entity], rng: &mut impl RngCore) {
+    // Who sets out from where, as (kind, name, faction, home, cell)
+    let mut parties: Vec<(PartyKind, String, (&str, &str), Option<Entity>, UVec2)> = Vec::new();
+    for faction in MAP_FACTIONS {
+        let holdings: Vec<usize> = (0..map.settlements.len())
+            .filter(|&index| map.settlements[index].faction_id == faction.0)
+            .collect();
+        for (count, &index) in holdings.iter().cycle().take(LORDS_PER_FACTION).enumerate() {
+            let name = format!("{} host {}", faction.1, count + 1);
+            parties.push((PartyKind::Lord, name, faction, Some(settlements[index]), map.settlements[index].cell));
+        }
+        for &index in holdings.iter().take(CARAVANS_PER_FACTION) {
+            let site = &map.settlements[index];
+            parties.push((PartyKind::Caravan, format!("Caravan of {}", site.name), faction, Some(settlements[index]), site.cell));
+        }
+    }
+    for (index, site) in map.settlements.iter().enumerate() {
+        let faction = MAP_FACTIONS
+            .into_iter()
+            .find(|faction| faction.0 == site.faction_id)
+            .unwrap_or(MAP_FACTIONS[0]);
+        parties.push((PartyKind::Villager, format!("Villagers of {}", site.name), faction, Some(settlements[index]), site.cell));
+    }
+
+    // Bandits lurk on open ground out of sight of the settlements
+    let lairs: Vec<UVec2> = (0..map.width * map.height)
+        .map(|index| UVec2::new(index % map.width, index / map.width))
+        .filter(|cell| cell_cost(map, *cell).is_some())
+        .filter(|cell| {
+            map.settlements
+                .iter()
+                .all(|site| site.cell.as_vec2().distance(cell.as_vec2()) * MAP_CELL_SIZE > BASE_SPOTTING_RANGE)
+        })
+        .collect();
+    if !lairs.is_empty() {
+        for _ in 0..BANDIT_PARTY_COUNT {
+            let cell = lairs[rng.next_u32() as usize % lairs.len()];
+            parties.push((PartyKind::Bandit, BANDIT_FACTION.1.to_string(), BANDIT_FACTION, None, cell));
+        }
+    }
+
+    for (kind, name, faction, home, cell) in parties {
+        let mut party = commands.spawn((
+            Name::new(name),
+            AiParty { kind, home, goal: PartyGoal::Idle },
+            Faction { id: faction.0.to_string(), name: faction.1.to_string() },
+            starting_roster(kind, rng),
+            map.world_position(cell),
+            Spotting { skill: if kind == PartyKind::Lord { 40 } else { 10 } },
+        ));
+        // Lords lead their own men into battle
+        if kind == PartyKind::Lord {
+            party.insert(CharacterStats {
+                strength: 12,
+                agility: 10,
+                intelligence: 10,
+                charisma: 12,
+                level: 5,
+                experience: 0,
+            });
+        }
+    }
+}
+
+/// Every hour, each AI party that isn't camped at a siege looks about and
+/// settles on what to do next
+pub(super) fn plan_party_goals(
+    clock: Res<CampaignClock>,
+    mut rng: ResMut<GlobalEntropy<WyRand>>,
+    mut parties: ParamSet<(
+        Query<(Entity, &mut AiParty, &TroopRoster, Option<&Spotting>), Without<Besieging>>,
+        Query<(Entity, &WorldPosition, &TroopRoster, Option<&Faction>, Option<&AiParty>, Has<Player>), Without<Captive>>,
+    )>,
+    settlements: Query<(Entity, &Settlement, &WorldPosition, Has<UnderSiege>)>,
+) {
+    // Everyone at large is looked over before any planner changes its mind
+    let views: Vec<PartyView> = parties
+        .p1()
+        .iter()
+        .filter(|(.., ai, is_player)| ai.is_some() || *is_player)
+        .map(|(entity, position, roster, faction, ai, _)| PartyView {
+            entity,
+            kind: ai.map(|ai| ai.kind),
+            faction_id: faction.map_or_else(|| "player".to_string(), |faction| faction.id.clone()),
+            position: Vec2::new(position.x, position.y),
+            strength: party_strength(roster),
+        })
+        .collect();
+    let settlement_views: Vec<SettlementView> = settlements
+        .iter()
+        .map(|(entity, settlement, position, besieged)| SettlementView {
+            entity,
+            faction_id: settlement.owner_faction_id.clone(),
+            position: Vec2::new(position.x, position.y),
+            defence: party_strength(&garrison_roster(settlement)),
+            besieged,
+        })
+        .collect();
+
+    for (entity, mut ai, roster, spotting) in parties.p0().iter_mut() {
+        let Some(party) = views.iter().find(|view| view.entity == entity) else {
+            continue;
+        };
+        let view = PlannerView {
+            parties: &views,
+            settlements: &settlement_views,
+            range: spotting_range(spotting, clock.is_night()),
+        };
+        let healthy_ratio = roster.healthy_count() as f32 / roster.total_count().max(1) as f32;
+        let goal = choose_goal(party, &ai, healthy_ratio, &view, &mut *rng);
+        if ai.goal != goal {
+            ai.goal = goal;
+        }
+    }
+}
+
+/// Every hour, sets AI parties moving after their goals and carries out
+/// whatever they have come to do once they get there
+pub(super) fn pursue_party_goals(
+    mut commands: Commands,
+    mut rng: ResMut<GlobalEntropy<WyRand>>,
+    mut orders: (EventWriter<MoveParty>, EventWriter<EngageBattle>),
+    mut siege_orders: (EventWriter<BeginSiege>, EventWriter<BuildSiegeEngine>, EventWriter<LiftSiege>),
+    mut parties: Query<(Entity, &mut AiParty, &WorldPosition, Option<&Faction>, &mut TroopRoster, Option<&Besieging>, Has<MapPath>)>,
+    others: Query<(&WorldPosition, Has<Player>), Without<AiParty>>,
+    mut settlements: Query<(&mut Settlement, &WorldPosition), Without<AiParty>>,
+) {
+    let (moves, engagements) = &mut orders;
+    let (begins, builds, lifts) = &mut siege_orders;
+    let mut party_positions: Vec<(Entity, Vec2)> = Vec::new();
+    for (entity, _, position, ..) in parties.iter() {
+        party_positions.push((entity, Vec2::new(position.x, position.y)));
+    }
+
+    for (entity, mut ai, position, faction, mut roster, besieging, travelling) in parties.iter_mut() {
+        // A party beaten down to nobody breaks up, and its siege with it
+        if roster.total_count() == 0 {
+            if let Some(besieging) = besieging {
+                commands.entity(besieging.settlement).remove::<UnderSiege>();
+            }
+            commands.entity(entity).despawn();
+            continue;
+        }
+        let here = Vec2::new(position.x, position.y);
+        let position_of = |target: Entity| {
+            party_positions
+                .iter()
+                .find(|(other, _)| *other == target)
+                .map(|(_, position)| *position)
+                .or_else(|| others.get(target).ok().map(|(position, _)| Vec2::new(position.x, position.y)))
+        };
+        let mut go = |to: Vec2| {
+            moves.send(MoveParty { party: entity, destination: WorldPosition { x: to.x, y: to.y } });
+        };
+
+        match ai.goal.clone() {
+            PartyGoal::Idle => {}
+            PartyGoal::Patrol { around } => {
+                if !travelling {
+                    let angle = (rng.next_u32() % 360) as f32 * std::f32::consts::PI / 180.0;
+                    let reach = (rng.next_u32() % 1000) as f32 / 1000.0 * PATROL_RADIUS;
+                    go(around + Vec2::from_angle(angle) * reach);
+                }
+            }
+            PartyGoal::Raid { target } => {
+                let Some(prey) = position_of(target) else {
+                    ai.goal = PartyGoal::Idle;
+                    continue;
+                };
+                // The player's party is met through the encounter menu, not fallen on here
+                let is_player = others.get(target).is_ok_and(|(_, is_player)| is_player);
+                if here.distance(prey) <= ARRIVAL_DISTANCE && !is_player {
+                    engagements.send(EngageBattle { attacker: entity, defender: target, in_person: false });
+                    ai.goal = PartyGoal::Idle;
+                } else {
+                    go(prey);
+                }
+            }
+            PartyGoal::Flee { from } => {
+                if let Some(threat) = position_of(from) {
+                    let away = (here - threat).normalize_or(Vec2::X);
+                    go(here + away * FLEE_DISTANCE);
+                }
+            }
+            PartyGoal::Besiege { settlement } => {
+                let Ok((mut target, target_position)) = settlements.get_mut(settlement) else {
+                    ai.goal = PartyGoal::Idle;
+                    continue;
+                };
+                match besieging {
+                    // Starved out, the settlement opens its gates
+                    Some(_) if target.garrison_size == 0 => {
+                        if let Some(faction) = faction {
+                            target.owner_faction_id = faction.id.clone();
+                        }
+                        lifts.send(LiftSiege { besieger: entity });
+                        ai.goal = PartyGoal::Idle;
+                    }
+                    Some(_) => {}
+                    None => {
+                        let walls = Vec2::new(target_position.x, target_position.y);
+                        if here.distance(walls) <= ARRIVAL_DISTANCE {
+                            begins.send(BeginSiege { besieger: entity, settlement });
+                            builds.send(BuildSiegeEngine { besieger: entity, engine: SiegeEngine::Ladders });
+                        } else if !travelling {
+                            go(walls);
+                        }
+                    }
+                }
+            }
+            PartyGoal::Trade { settlement: target } | PartyGoal::Return { settlement: target } => {
+                let Ok((mut market, market_position)) = settlements.get_mut(target) else {
+                    ai.goal = PartyGoal::Idle;
+                    continue;
+                };
+                let destination = Vec2::new(market_position.x, market_position.y);
+                if here.distance(destination) > ARRIVAL_DISTANCE {
+                    if !travelling {
+                        go(destination);
+                    }
+                    continue;
+                }
+                if matches!(ai.goal, PartyGoal::Trade { .. }) {
+                    market.prosperity += match ai.kind {
+                        PartyKind::Caravan => CARAVAN_TRADE_PROSPERITY,
+                        _ => VILLAGER_TRADE_PROSPERITY,
+                    };
+                } else {
+                    // Home at last, the wounded recover
+                    for stack in roster.stacks.iter_mut() {
+                        stack.wounded = 0;
+                    }
+                }
+                ai.goal = PartyGoal::Idle;
+            }
+        }
+    }
+}
+
+/// Shows the player the parties their scouts can see, and hides the rest
+pub(super) fn spot_parties(
+    mut commands: Commands,
+    clock: Res<CampaignClock>,
+    players: Query<(&WorldPosition, Option<&Spotting>), With<Player>>,
+    mut parties: Query<(Entity, &WorldPosition, Has<Spotted>, Option<&mut Visibility>), With<AiParty>>,
+) {
+    let Ok((player, spotting)) = players.get_single() else {
+        return;
+    };
+    let range = spotting_range(spotting, clock.is_night());
+    let eye = Vec2::new(player.x, player.y);
+    for (entity, position, spotted, visibility) in parties.iter_mut() {
+        let seen = eye.distance(Vec2::new(position.x, position.y)) <= range;
+        if let Some(mut visibility) = visibility {
+            visibility.set_if_neq(if seen { Visibility::Inherited } else { Visibility::Hidden });
+        }
+        match (seen, spotted) {
+            (true, false) => {
+                commands.entity(entity).insert(Spotted);
+            }
+            (false, true) => {
+                commands.entity(entity).remove::<Spotted>();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_core::SeedableRng;
+    use crate::test_support::{world_map_test_app, run_test_app};
+
+    fn party_view(id: u32, kind: Option<PartyKind>, faction_id: &str, x: f32, strength: f32) -> PartyView {
+        PartyView {
+            entity: Entity::from_raw(id),
+            kind,
+            faction_id: faction_id.to_string(),
+            position: Vec2::new(x, 0.0),
+            strength,
+        }
+    }
+
+    #[test]
+    fn test_party_planner_flees_raids_and_besieges() {
+        let mut rng = WyRand::seed_from_u64(3);
+        let bandit = party_view(1, Some(PartyKind::Bandit), "looters", 0.0, 20.0);
+        let caravan = party_view(2, Some(PartyKind::Caravan), "vlandia", 30.0, 10.0);
+        let lord = party_view(3, Some(PartyKind::Lord), "vlandia", 50.0, 200.0);
+        let player = party_view(4, None, "player", 40.0, 15.0);
+        assert!(hostile(&bandit, &caravan) && hostile(&bandit, &player) && hostile(&lord, &bandit));
+        assert!(!hostile(&lord, &caravan) && !hostile(&lord, &player));
+
+        let ai = |kind: PartyKind, home: Option<u32>| AiParty {
+            kind,
+            home: home.map(Entity::from_raw),
+            goal: PartyGoal::Idle,
+        };
+        let town = SettlementView {
+            entity: Entity::from_raw(10),
+            faction_id: "sturgia".to_string(),
+            position: Vec2::new(80.0, 0.0),
+            defence: 40.0,
+            besieged: false,
+        };
+        let home = SettlementView {
+            entity: Entity::from_raw(11),
+            faction_id: "vlandia".to_string(),
+            position: Vec2::new(30.0, 0.0),
+            defence: 40.0,
+            besieged: false,
+        };
+        let settlements = [town.clone(), home.clone()];
+
+        // With only the caravan in sight, the bandits go for it
+        let parties = [bandit.clone(), caravan.clone()];
+        let view = PlannerView { parties: &parties, settlements: &settlements, range: 60.0 };
+        let goal = choose_goal(&bandit, &ai(PartyKind::Bandit, None), 1.0, &view, &mut rng);
+        assert_eq!(goal, PartyGoal::Raid { target: caravan.entity });
+
+        // Once the lord's host comes into view they run
+        let parties = [bandit.clone(), caravan.clone(), lord.clone()];
+        let view = PlannerView { parties: &parties, settlements: &settlements, range: 60.0 };
+        let goal = choose_goal(&bandit, &ai(PartyKind::Bandit, None), 1.0, &view, &mut rng);
+        assert_eq!(goal, PartyGoal::Flee { from: lord.entity });
+
+        // The lord hunts the bandits before turning on the enemy town
+        let goal = choose_goal(&lord, &ai(PartyKind::Lord, Some(11)), 1.0, &view, &mut rng);
+        assert_eq!(goal, PartyGoal::Raid { target: bandit.entity });
+        let parties = [lord.clone()];
+        let view = PlannerView { parties: &parties, settlements: &settlements, range: 60.0 };
+        let goal = choose_goal(&lord, &ai(PartyKind::Lord, Some(11)), 1.0, &view, &mut rng);
+        assert_eq!(goal, PartyGoal::Besiege { settlement: town.entity });
+        // A battered host goes home instead
+        let goal = choose_goal(&lord, &ai(PartyKind::Lord, Some(11)), 0.3, &view, &mut rng);
+        assert_eq!(goal, PartyGoal::Return { settlement: home.entity });
+
+        // Villagers take their goods to market, then go home
+        let mut village = home.clone();
+        village.entity = Entity::from_raw(12);
+        village.position = Vec2::new(-40.0, 0.0);
+        let settlements = [town, home.clone(), village.clone()];
+        let villagers = party_view(5, Some(PartyKind::Villager), "vlandia", -40.0, 5.0);
+        let parties = [villagers.clone()];
+        let view = PlannerView { parties: &parties, settlements: &settlements, range: 60.0 };
+        let goal = choose_goal(&villagers, &ai(PartyKind::Villager, Some(12)), 1.0, &view, &mut rng);
+        assert_eq!(goal, PartyGoal::Trade { settlement: home.entity });
+        let at_market = PartyView { position: home.position, ..villagers };
+        let goal = choose_goal(&at_market, &ai(PartyKind::Villager, Some(12)), 1.0, &view, &mut rng);
+        assert_eq!(goal, PartyGoal::Return { settlement: village.entity });
+    }
+
+    #[test]
+    fn test_generated_world_sends_out_parties_the_player_spots_nearby() {
+        assert!(spotting_range(Some(&Spotting { skill: 100 }), false) > spotting_range(None, false));
+        assert!(spotting_range(None, true) < spotting_range(None, false));
+
+        let mut app = world_map_test_app();
+        let world = app.world_mut();
+        let mut parties = world.query::<(Entity, &AiParty, &WorldPosition, &Faction, &TroopRoster)>();
+        let kinds: Vec<PartyKind> = parties.iter(world).map(|(_, party, ..)| party.kind).collect();
+        for kind in [PartyKind::Lord, PartyKind::Bandit, PartyKind::Caravan, PartyKind::Villager] {
+            assert!(kinds.contains(&kind), "no {kind:?} parties");
+        }
+        assert!(parties.iter(world).all(|(.., roster)| roster.healthy_count() > 0));
+
+        // Standing on a party, the player sees it; the far side of the map stays hidden
+        let (near, position) = parties
+            .iter(world)
+            .map(|(entity, _, position, ..)| (entity, position.clone()))
+            .next()
+            .unwrap();
+        world.spawn((Player, position.clone(), Spotting { skill: 0 }));
+        run_test_app(&mut app, 1);
+        let world = app.world_mut();
+        assert!(world.get::<Spotted>(near).is_some());
+        let mut parties = world.query::<(&WorldPosition, Has<Spotted>)>();
+        let far_seen = parties
+            .iter(world)
+            .filter(|(other, _)| Vec2::new(other.x - position.x, other.y - position.y).length() > 100.0)
+            .any(|(_, spotted)| spotted);
+        assert!(!far_seen);
+    }
+}
@@ -0,0 +1,424 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::core::states::{GameState, WorldMapState};
+use crate::core::components::{
+    Settlement, FoodStores, SiegeEngine, Besieging, UnderSiege, TroopRoster, TroopStack,
+    WeaponType, Faction, CharacterStats, Player, WorldPosition, BattleSide, Captive,
+};
+use crate::plugins::combat::{BattleSetup, BattleParty, SiegeBattle};
+
+use super::battles::battle_party;
+use super::calendar::NewDay;
+use super::parties::ARRIVAL_DISTANCE;
+
+/// Healthy troops it takes to build an engine in its listed number of days
+pub const FULL_BUILD_CREW: u32 = 50;
+/// Food a defender eats each day of a siege
+pub const FOOD_PER_DEFENDER_PER_DAY: f32 = 0.1;
+/// Food a settlement has laid by for each point of prosperity
+pub const FOOD_PER_PROSPERITY: f32 = 0.1;
+/// Share of a starving garrison that dies or deserts each day
+pub const STARVATION_LOSS_RATIO: f32 = 0.05;
+/// Share of a garrison that mans the walls with crossbows; the rest carry spears
+pub const GARRISON_CROSSBOW_RATIO: f32 = 0.3;
+/// Width and depth of the field a siege assault is fought on
+pub const SIEGE_FIELD_SIZE: f32 = 200.0;
+
+/// Camp outside a settlement and start a siege
+#[derive(Event, Debug, Clone)]
+pub struct BeginSiege {
+    pub besieger: Entity,
+    pub settlement: Entity,
+}
+
+/// Put another engine on the besieger's build queue
+#[derive(Event, Debug, Clone)]
+pub struct BuildSiegeEngine {
+    pub besieger: Entity,
+    pub engine: SiegeEngine,
+}
+
+/// Storm the walls with whatever engines are ready
+#[derive(Event, Debug, Clone)]
+pub struct AssaultSettlement {
+    pub besieger: Entity,
+}
+
+/// Break camp and leave the settlement be
+#[derive(Event, Debug, Clone)]
+pub struct LiftSiege {
+    pub besieger: Entity,
+}
+
+/// Days a full crew needs to build `engine`
+pub fn engine_build_days(engine: SiegeEngine) -> f32 {
+    match engine {
+        SiegeEngine::Ladders => 1.0,
+        SiegeEngine::Ram => 2.0,
+        SiegeEngine::SiegeTower => 4.0,
+    }
+}
+
+/// Days of work a party with `builders` healthy troops gets through in a day
+pub fn build_rate(builders: u32) -> f32 {
+    (builders as f32 / FULL_BUILD_CREW as f32).clamp(0.25, 2.0)
+}
+
+/// Food a settlement holds when a siege begins
+pub fn food_stores_for(settlement: &Settlement) -> FoodStores {
+    let food = settlement.prosperity as f32 * FOOD_PER_PROSPERITY;
+    FoodStores { current: food, max: food }
+}
+
+/// The troops defending a settlement, one for every man of its garrison and
+/// better equipped the more prosperous it is
+pub fn garrison_roster(settlement: &Settlement) -> TroopRoster {
+    let tier = (1 + settlement.prosperity / 500).min(4) as u8;
+    let crossbows = (settlement.garrison_size as f32 * GARRISON_CROSSBOW_RATIO).round() as u32;
+    let stack = |troop_id: &str, weapon_type: WeaponType, count: u32| TroopStack {
+        troop_id: troop_id.to_string(),
+        tier,
+        weapon_type,
+        count,
+        wounded: 0,
+        mounted: false,
+    };
+
+    let mut roster = TroopRoster::default();
+    roster.add(stack("garrison_spearman", WeaponType::Spear, settlement.garrison_size - crossbows));
+    roster.add(stack("garrison_crossbowman", WeaponType::Crossbow, crossbows));
+    roster
+}
+
+/// B lays siege to the nearest settlement of another faction that the player's
+/// party has reached
+pub(super) fn handle_besiege_input(
+    keys: Option<Res<ButtonInput<KeyCode>>>,
+    mut begins: EventWriter<BeginSiege>,
+    players: Query<
+        (Entity, &WorldPosition, Option<&Faction>),
+        (With<Player>, Without<BattleSide>, Without<Captive>, Without<Besieging>),
+    >,
+    settlements: Query<(Entity, &Settlement, &WorldPosition), Without<UnderSiege>>,
+) {
+    if !keys.is_some_and(|keys| keys.just_pressed(KeyCode::KeyB)) {
+        return;
+    }
+    let Ok((besieger, at, faction)) = players.get_single() else {
+        return;
+    };
+    let here = Vec2::new(at.x, at.y);
+    let nearest = settlements
+        .iter()
+        .filter(|(_, settlement, _)| faction.map_or(true, |faction| faction.id != settlement.owner_faction_id))
+        .map(|(entity, _, walls)| (entity, here.distance(Vec2::new(walls.x, walls.y))))
+        .filter(|(_, distance)| *distance <= ARRIVAL_DISTANCE)
+        .min_by(|a, b| a.1.total_cmp(&b.1));
+    if let Some((settlement, _)) = nearest {
+        begins.send(BeginSiege { besieger, settlement });
+    }
+}
+
+/// Starts, extends and lifts sieges. The player's own siege brings up the
+/// siege menu.
+pub(super) fn handle_siege_orders(
+    mut commands: Commands,
+    mut begins: EventReader<BeginSiege>,
+    mut builds: EventReader<BuildSiegeEngine>,
+    mut lifts: EventReader<LiftSiege>,
+    mut next_map_state: ResMut<NextState<WorldMapState>>,
+    mut besiegers: Query<(&mut Besieging, Has<Player>)>,
+    settlements: Query<(&Settlement, Has<FoodStores>, Has<UnderSiege>)>,
+    players: Query<(), With<Player>>,
+) {
+    for begin in begins.read() {
+        let Ok((settlement, stocked, besieged)) = settlements.get(begin.settlement) else {
+            continue;
+        };
+        if besieged || besiegers.contains(begin.besieger) {
+            continue;
+        }
+        commands.entity(begin.besieger).insert(Besieging {
+            settlement: begin.settlement,
+            days: 0.0,
+            queue: Vec::new(),
+            progress: 0.0,
+            built: Vec::new(),
+        });
+        let mut target = commands.entity(begin.settlement);
+        target.insert(UnderSiege { besieger: begin.besieger });
+        if !stocked {
+            target.insert(food_stores_for(settlement));
+        }
+        if players.contains(begin.besieger) {
+            next_map_state.set(WorldMapState::Siege);
+        }
+    }
+
+    for build in builds.read() {
+        if let Ok((mut besieging, _)) = besiegers.get_mut(build.besieger) {
+            besieging.queue.push(build.engine);
+        }
+    }
+
+    for lift in lifts.read() {
+        let Ok((besieging, is_player)) = besiegers.get(lift.besieger) else {
+            continue;
+        };
+        commands.entity(besieging.settlement).remove::<UnderSiege>();
+        commands.entity(lift.besieger).remove::<Besieging>();
+        if is_player {
+            next_map_state.set(WorldMapState::Free);
+        }
+    }
+}
+
+/// Each day, besiegers put their healthy troops to work on the engine at the
+/// front of their queue
+pub(super) fn progress_sieges(
+    mut days: EventReader<NewDay>,
+    mut besiegers: Query<(&mut Besieging, Option<&TroopRoster>)>,
+) {
+    for _ in days.read() {
+        for (mut besieging, roster) in besiegers.iter_mut() {
+            besieging.days += 1.0;
+            if besieging.queue.is_empty() {
+                continue;
+            }
+            besieging.progress += build_rate(roster.map_or(0, TroopRoster::healthy_count));
+            while let Some(&engine) = besieging.queue.first() {
+                let needed = engine_build_days(engine);
+                if besieging.progress < needed {
+                    break;
+                }
+                besieging.progress -= needed;
+                besieging.queue.remove(0);
+                besieging.built.push(engine);
+            }
+            if besieging.queue.is_empty() {
+                besieging.progress = 0.0;
+            }
+        }
+    }
+}
+
+/// Each day, a besieged garrison eats into its stores; once they run out it
+/// starts to waste away
+pub(super) fn starve_besieged(
+    mut days: EventReader<NewDay>,
+    mut settlements: Query<(&mut Settlement, &mut FoodStores), With<UnderSiege>>,
+) {
+    for _ in days.read() {
+        for (mut settlement, mut food) in settlements.iter_mut() {
+            let eaten = settlement.garrison_size as f32 * FOOD_PER_DEFENDER_PER_DAY;
+            if food.current >= eaten {
+                food.current -= eaten;
+                continue;
+            }
+            food.current = 0.0;
+            let lost = (settlement.garrison_size as f32 * STARVATION_LOSS_RATIO).ceil() as u32;
+            settlement.garrison_size = settlement.garrison_size.saturating_sub(lost);
+        }
+    }
+}
+
+/// Sends the besieger against the walls with the engines it has ready; the
+/// garrison defends
+pub(super) fn launch_assault(
+    mut commands: Commands,
+    mut assaults: EventReader<AssaultSettlement>,
+    mut next_game_state: ResMut<NextState<GameState>>,
+    besiegers: Query<(
+        &Besieging,
+        &TroopRoster,
+        Option<&Faction>,
+        Option<&CharacterStats>,
+        Option<&Name>,
+        Has<Player>,
+    )>,
+    settlements: Query<&Settlement>,
+) {
+    let Some(assault) = assaults.read().last() else {
+        return;
+    };
+    let Ok((besieging, roster, faction, stats, name, is_player)) = besiegers.get(assault.besieger) else {
+        return;
+    };
+    let Ok(settlement) = settlements.get(besieging.settlement) else {
+        return;
+    };
+
+    commands.insert_resource(BattleSetup {
+        attacker: battle_party(assault.besieger, roster, faction, stats, name, is_player, true),
+        defender: BattleParty {
+            faction: Faction {
+                id: settlement.owner_faction_id.clone(),
+                name: settlement.name.clone(),
+            },
+            roster: garrison_roster(settlement),
+            leader: None,
+            // The garrison isn't a party; the siege outcome updates the settlement
+            party_entity: None,
+        },
+        field_size: SIEGE_FIELD_SIZE,
+    });
+    commands.insert_resource(SiegeBattle {
+        settlement: Some(besieging.settlement),
+        besieger: Some(assault.besieger),
+        engines: besieging.built.clone(),
+        ..default()
+    });
+    next_game_state.set(GameState::Combat);
+}
+
+/// The player's siege camp: what's built, what's left to eat and when to attack
+pub(super) fn siege_menu(
+    mut contexts: EguiContexts,
+    mut builds: EventWriter<BuildSiegeEngine>,
+    mut assaults: EventWriter<AssaultSettlement>,
+    mut lifts: EventWriter<LiftSiege>,
+    besiegers: Query<(Entity, &Besieging), With<Player>>,
+    settlements: Query<(&Settlement, Option<&FoodStores>)>,
+) {
+    let Some(ctx) = contexts.try_ctx_mut() else {
+        return;
+    };
+    let Ok((besieger, besieging)) = besiegers.get_single() else {
+        return;
+    };
+    let Ok((settlement, food)) = settlements.get(besieging.settlement) else {
+        return;
+    };
+
+    egui::Window::new(format!("Siege of {}", settlement.name))
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ctx, |ui| {
+            ui.label(format!("Day {}", besieging.days as u32 + 1));
+            ui.label(format!("Garrison: {}", settlement.garrison_size));
+            if let Some(food) = food {
+                ui.label(format!("Food: {:.0} / {:.0}", food.current, food.max));
+            }
+            ui.separator();
+            if !besieging.built.is_empty() {
+                let built: Vec<String> = besieging.built.iter().map(|engine| format!("{engine:?}")).collect();
+                ui.label(format!("Ready: {}", built.join(", ")));
+            }
+            if let Some(engine) = besieging.queue.first() {
+                ui.label(format!(
+                    "Building {engine:?}: {:.1} / {:.0} days",
+                    besieging.progress,
+                    engine_build_days(*engine),
+                ));
+            }
+            ui.horizontal(|ui| {
+                for (label, engine) in [
+                    ("Build ladders", SiegeEngine::Ladders),
+                    ("Build ram", SiegeEngine::Ram),
+                    ("Build siege tower", SiegeEngine::SiegeTower),
+                ] {
+                    if ui.button(label).clicked() {
+                        builds.send(BuildSiegeEngine { besieger, engine });
+                    }
+                }
+            });
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Assault").clicked() {
+                    assaults.send(AssaultSettlement { besieger });
+                }
+                if ui.button("Lift siege").clicked() {
+                    lifts.send(LiftSiege { besieger });
+                }
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{world_map_test_app, world_map_test_app_with, plains_map, test_settlement, run_test_app};
+
+    #[test]
+    fn test_siege_engines_take_days_to_build() {
+        let mut app = world_map_test_app();
+        let settlement = app.world_mut().spawn(test_settlement(1000, 100)).id();
+        let besieger = app.world_mut().spawn((
+            Player,
+            TroopRoster {
+                stacks: vec![TroopStack {
+                    troop_id: "recruit".to_string(),
+                    tier: 1,
+                    weapon_type: WeaponType::OneHandedSword,
+                    count: 50,
+                    wounded: 0,
+                    mounted: false,
+                }],
+            },
+        )).id();
+
+        app.world_mut().send_event(BeginSiege { besieger, settlement });
+        run_test_app(&mut app, 2);
+        assert_eq!(*app.world().resource::<State<WorldMapState>>().get(), WorldMapState::Siege);
+        assert!(app.world().get::<FoodStores>(settlement).is_some());
+
+        // A full crew builds a ram in two days
+        app.world_mut().send_event(BuildSiegeEngine { besieger, engine: SiegeEngine::Ram });
+        run_test_app(&mut app, 15);
+        let besieging = app.world().get::<Besieging>(besieger).unwrap();
+        assert!(besieging.built.is_empty());
+        assert_eq!(besieging.queue, vec![SiegeEngine::Ram]);
+
+        run_test_app(&mut app, 10);
+        let besieging = app.world().get::<Besieging>(besieger).unwrap();
+        assert_eq!(besieging.built, vec![SiegeEngine::Ram]);
+        assert!(besieging.queue.is_empty());
+    }
+
+    #[test]
+    fn test_besieged_garrison_eats_through_food_then_starves() {
+        let mut app = world_map_test_app();
+        // 10 food for 50 men eating 5 a day: two days of food
+        let settlement = app.world_mut().spawn(test_settlement(100, 50)).id();
+        let besieger = app.world_mut().spawn(TroopRoster::default()).id();
+        app.world_mut().send_event(BeginSiege { besieger, settlement });
+        run_test_app(&mut app, 1);
+
+        run_test_app(&mut app, 10);
+        assert!((app.world().get::<FoodStores>(settlement).unwrap().current - 5.0).abs() < 1e-3);
+        // An AI siege leaves the player's map alone
+        assert_eq!(*app.world().resource::<State<WorldMapState>>().get(), WorldMapState::Free);
+
+        run_test_app(&mut app, 20);
+        assert_eq!(app.world().get::<FoodStores>(settlement).unwrap().current, 0.0);
+        // The third day finds the stores empty and a twentieth of the garrison is lost
+        assert_eq!(app.world().get::<Settlement>(settlement).unwrap().garrison_size, 47);
+    }
+
+    #[test]
+    fn test_player_lays_siege_to_an_enemy_settlement() {
+        let mut app = world_map_test_app_with(plains_map(20, 20));
+        let walls = WorldPosition { x: 100.0, y: 100.0 };
+        let settlement = app.world_mut().spawn((test_settlement(1000, 100), walls.clone())).id();
+        let player = app.world_mut().spawn((
+            Player,
+            Faction { id: "vlandia".to_string(), name: "Vlandia".to_string() },
+            WorldPosition { x: 100.0, y: 104.0 },
+        )).id();
+        let mut keys = ButtonInput::<KeyCode>::default();
+        keys.press(KeyCode::KeyB);
+        app.insert_resource(keys);
+
+        // Nobody besieges their own town
+        run_test_app(&mut app, 2);
+        assert!(app.world().get::<Besieging>(player).is_none());
+
+        app.world_mut().get_mut::<Settlement>(settlement).unwrap().owner_faction_id = "sturgia".to_string();
+        run_test_app(&mut app, 2);
+        assert_eq!(app.world().get::<Besieging>(player).unwrap().settlement, settlement);
+        assert_eq!(app.world().get::<UnderSiege>(settlement).unwrap().besieger, player);
+        assert_eq!(*app.world().resource::<State<WorldMapState>>().get(), WorldMapState::Siege);
+    }
+}
@@ -1,3 +1,3 @@
 mod serialization;
 
-pub use serialization::SaveSystemPlugin;
\ No newline at end of file
+pub use serialization::{SaveSystemPlugin, SaveGameConfig, SaveGameEvent, LoadGameEvent};
\ No newline at end of file
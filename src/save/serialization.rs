@@ -5,6 +5,7 @@ use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 
 use crate::core::components::*;
+use crate::plugins::{CampaignMap, CampaignClock, generate_campaign_map};
 
 pub struct SaveSystemPlugin;
 
@@ -102,6 +103,8 @@ fn handle_save_game(
     config: Res<SaveGameConfig>,
     query_player: Query<(&Transform, &CharacterStats, &Health, &Stamina), With<Player>>,
     query_settlements: Query<(&Settlement, &WorldPosition)>,
+    map: Option<Res<CampaignMap>>,
+    clock: Option<Res<CampaignClock>>,
     // Add other queries for game data you want to save
 ) {
     for event in save_events.read() {
@@ -148,9 +151,10 @@ fn handle_save_game(
             version: "1.0".to_string(),
             save_date: format!("{:?}", std::time::SystemTime::now()),
             world_data: WorldData {
-                game_time: 0.0, // Fill from game time resource
-                day: 1,         // Fill from calendar resource
-                seed: 12345,    // Fill from world generation seed
+                game_time: clock.as_ref().map_or(0.0, |clock| clock.elapsed),
+                day: clock.as_ref().map_or(1, |clock| clock.day()),
+                // The map itself is regenerated from its seed on load
+                seed: map.as_ref().map_or(0, |map| map.seed),
             },
             player_data,
             factions_data: Vec::new(), // Fill from faction queries
@@ -210,6 +214,11 @@ fn handle_load_game(
         // entities.for_each(...);
         
         // Recreate world state from save
+        // The terrain and roads come back from the seed; the settlements below
+        // carry whatever has changed since the map was generated
+        commands.insert_resource(generate_campaign_map(game_save.world_data.seed));
+        commands.insert_resource(CampaignClock::from_elapsed(game_save.world_data.game_time));
+
         // Create player
         commands.spawn((
             Player,
@@ -254,4 +263,48 @@ fn handle_load_game(
         
         info!("Game loaded successfully");
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::utils::Duration;
+    use crate::core::GameState;
+    use crate::plugins::{ClockSpeed, WorldSeed};
+    use crate::test_support::{headless_app, world_map_app, enter_state, run_test_app};
+
+    #[test]
+    fn test_save_restores_clock_and_map() {
+        let save_directory = std::env::temp_dir().join("test_save_restores_clock_and_map");
+        let mut app = world_map_app();
+        app.add_plugins(SaveSystemPlugin)
+           .insert_resource(WorldSeed(31))
+           .insert_resource(SaveGameConfig { save_directory: save_directory.clone() });
+        enter_state(&mut app, GameState::WorldMap);
+        *app.world_mut().resource_mut::<CampaignClock>() = CampaignClock {
+            elapsed: 123.0,
+            speed: ClockSpeed::Paused,
+            held: false,
+        };
+        app.world_mut().spawn((
+            Player,
+            Transform::default(),
+            CharacterStats { strength: 10, agility: 10, intelligence: 10, charisma: 10, level: 1, experience: 0 },
+            Health { current: 100.0, max: 100.0 },
+            Stamina { current: 100.0, max: 100.0, recovery_rate: 5.0 },
+        ));
+        app.world_mut().send_event(SaveGameEvent { save_name: "campaign".to_string() });
+        run_test_app(&mut app, 1);
+
+        let mut loaded = headless_app(Duration::from_secs(1));
+        loaded.add_plugins(SaveSystemPlugin)
+              .insert_resource(SaveGameConfig { save_directory });
+        loaded.world_mut().send_event(LoadGameEvent { save_name: "campaign".to_string() });
+        run_test_app(&mut loaded, 1);
+
+        assert_eq!(loaded.world().resource::<CampaignClock>().elapsed, 123.0);
+        assert_eq!(loaded.world().resource::<CampaignMap>(), app.world().resource::<CampaignMap>());
+        let settlements = loaded.world_mut().query::<&Settlement>().iter(loaded.world()).count();
+        assert_eq!(settlements, app.world().resource::<CampaignMap>().settlements.len());
+    }
+}
@@ -0,0 +1,218 @@
+//! Headless apps and fixtures shared by the unit tests
+
+use bevy::prelude::*;
+use bevy::state::app::StatesPlugin;
+use bevy::time::TimeUpdateStrategy;
+use bevy::utils::Duration;
+use bevy::input::InputPlugin;
+use bevy_rapier3d::prelude::*;
+use bevy_rand::prelude::{EntropyPlugin, WyRand};
+
+use crate::core::{
+    GameState, CombatState, Health, Stamina, Weapon, WeaponType, CharacterStats, BattleSide, Faction,
+    Morale, AttackDirection, DamageType, BodyPart, TroopRoster, TroopStack, Settlement,
+};
+use crate::plugins::{
+    CombatPlugin, WorldMapPlugin, CombatantSnapshot, AttackEvent, DamageApplied, BattleParty, CampaignMap,
+    MapCell, Terrain,
+};
+
+// Test helper to run an app for a few frames
+pub fn run_test_app(app: &mut App, frames: usize) {
+    for _ in 0..frames {
+        app.update();
+    }
+}
+
+/// Windowless app stepping `step` of game time every frame, with a fixed seed, in the
+/// starting game state. Every test app is built from this one.
+pub fn headless_app(step: Duration) -> App {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, StatesPlugin))
+       .insert_resource(TimeUpdateStrategy::ManualDuration(step))
+       .add_plugins(EntropyPlugin::<WyRand>::with_seed(7u64.to_le_bytes()))
+       .init_state::<GameState>()
+       .enable_state_scoped_entities::<GameState>();
+    app
+}
+
+/// Switches to `state` and runs the frame that enters it
+pub fn enter_state(app: &mut App, state: GameState) {
+    app.world_mut().resource_mut::<NextState<GameState>>().set(state);
+    run_test_app(app, 1);
+}
+
+/// Headless app with physics and the combat plugin, stepping a fixed 60Hz
+pub fn combat_app() -> App {
+    let mut app = headless_app(Duration::from_secs_f32(1.0 / 60.0));
+    app.add_plugins((TransformPlugin, InputPlugin))
+       .add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
+       .add_plugins(CombatPlugin);
+    app.finish();
+    app
+}
+
+/// The combat app, already switched into combat
+pub fn combat_test_app() -> App {
+    let mut app = combat_app();
+    enter_state(&mut app, GameState::Combat);
+    app
+}
+
+/// Headless app with the world map plugin, ticking a second of campaign time
+/// every frame, so a day passes every ten frames
+pub fn world_map_app() -> App {
+    let mut app = headless_app(Duration::from_secs(1));
+    app.add_plugins(WorldMapPlugin);
+    app
+}
+
+/// The world map app, already out on the map
+pub fn world_map_test_app() -> App {
+    let mut app = world_map_app();
+    enter_state(&mut app, GameState::WorldMap);
+    app
+}
+
+/// The world map app out on a hand-made map, so no world is generated
+pub fn world_map_test_app_with(map: CampaignMap) -> App {
+    let mut app = world_map_app();
+    app.insert_resource(map);
+    enter_state(&mut app, GameState::WorldMap);
+    app
+}
+
+/// Ends deployment and starts the fighting
+pub fn start_battle(app: &mut App) {
+    app.world_mut().resource_mut::<NextState<CombatState>>().set(CombatState::Active);
+    run_test_app(app, 1);
+}
+
+pub fn spawn_swordsman(app: &mut App, strength: u8, position: Vec3) -> Entity {
+    spawn_soldier(app, WeaponType::OneHandedSword, strength, position)
+}
+
+pub fn spawn_soldier(app: &mut App, weapon_type: WeaponType, strength: u8, position: Vec3) -> Entity {
+    app.world_mut().spawn((
+        Health { current: 100.0, max: 100.0 },
+        Stamina { current: 100.0, max: 100.0, recovery_rate: 5.0 },
+        CharacterStats {
+            strength,
+            agility: 10,
+            intelligence: 10,
+            charisma: 10,
+            level: 1,
+            experience: 0,
+        },
+        Weapon {
+            damage: 20.0,
+            speed: 1.0,
+            reach: 1.5,
+            weapon_type,
+        },
+        Transform::from_translation(position),
+    )).id()
+}
+
+pub fn enlist(app: &mut App, entity: Entity, side: BattleSide, faction_id: &str) {
+    app.world_mut().entity_mut(entity).insert((
+        side,
+        Faction { id: faction_id.to_string(), name: faction_id.to_string() },
+        Morale { current: 10.0, max: 100.0 },
+    ));
+}
+
+pub fn kill(app: &mut App, attacker: Entity, target: Entity) {
+    app.world_mut().send_event(DamageApplied {
+        attacker,
+        target,
+        direction: AttackDirection::Overhead,
+        amount: 1000.0,
+        damage_type: DamageType::Cut,
+        location: BodyPart::Head,
+    });
+}
+
+/// Sends a fully charged swing and runs the frame it lands in
+pub fn swing_at(app: &mut App, attacker: Entity, target: Entity, direction: AttackDirection) {
+    app.world_mut().send_event(AttackEvent { attacker, target, direction, timing: 1.0 });
+    run_test_app(app, 1);
+}
+
+pub fn snapshot(index: u32, side: BattleSide, position: Vec3) -> CombatantSnapshot {
+    CombatantSnapshot {
+        entity: Entity::from_raw(index),
+        team: Some(side),
+        position,
+        health_ratio: 1.0,
+        stamina_ratio: 1.0,
+        reach: 1.5,
+        is_ranged: false,
+    }
+}
+
+/// A party of tier 1 swordsmen and tier 2 archers, one of the archers wounded
+pub fn test_party(faction_id: &str, infantry: u32, archers: u32) -> BattleParty {
+    BattleParty {
+        faction: Faction { id: faction_id.to_string(), name: faction_id.to_string() },
+        roster: TroopRoster {
+            stacks: vec![
+                TroopStack {
+                    troop_id: "recruit".to_string(),
+                    tier: 1,
+                    weapon_type: WeaponType::OneHandedSword,
+                    count: infantry,
+                    wounded: 0,
+                    mounted: false,
+                },
+                TroopStack {
+                    troop_id: "archer".to_string(),
+                    tier: 2,
+                    weapon_type: WeaponType::Bow,
+                    count: archers,
+                    wounded: 1,
+                    mounted: false,
+                },
+            ],
+        },
+        leader: None,
+        party_entity: None,
+    }
+}
+
+pub fn hero_stats() -> CharacterStats {
+    CharacterStats {
+        strength: 12,
+        agility: 10,
+        intelligence: 10,
+        charisma: 10,
+        level: 1,
+        experience: 0,
+    }
+}
+
+pub fn test_settlement(prosperity: u32, garrison_size: u32) -> Settlement {
+    Settlement {
+        name: "Pravend".to_string(),
+        prosperity,
+        garrison_size,
+        owner_faction_id: "vlandia".to_string(),
+    }
+}
+
+/// A map of open plains, `width` by `height` cells, with nothing on it
+pub fn plains_map(width: u32, height: u32) -> CampaignMap {
+    CampaignMap {
+        seed: 0,
+        width,
+        height,
+        cells: vec![MapCell { terrain: Terrain::Plains, road: false }; (width * height) as usize],
+        settlements: Vec::new(),
+        roads: Vec::new(),
+    }
+}
+
+pub fn set_cell(map: &mut CampaignMap, x: u32, y: u32, terrain: Terrain, road: bool) {
+    let index = (y * map.width + x) as usize;
+    map.cells[index] = MapCell { terrain, road };
+}